@@ -0,0 +1,20 @@
+use std::sync::Arc;
+use database_adapter::{DatabaseAdapter, DatabaseAdapterResult};
+use crate::jwt_keys::JwtKeys;
+
+/// Maintenance done on database shared with running servers, instead of serving
+#[derive(Debug, Clone, PartialEq, clap::Subcommand)]
+pub enum AdminCommand {
+    /// Starts signing tokens with new key. Running servers pick it up, tokens signed earlier stay valid until they expire.
+    RotateJwtKeys,
+}
+
+/// Returns report to be shown to admin
+pub async fn run_admin_command(command: &AdminCommand, database_adapter: Arc<dyn DatabaseAdapter>) -> DatabaseAdapterResult<String> {
+    match command {
+        AdminCommand::RotateJwtKeys => {
+            let new_key_id = JwtKeys::load(database_adapter).await?.rotate().await?;
+            Ok(format!("Tokens are signed with key '{new_key_id}' from now on"))
+        }
+    }
+}
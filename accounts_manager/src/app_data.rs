@@ -1,11 +1,10 @@
 use std::fmt::{Debug, Formatter};
 use std::sync::Arc;
 use axum::extract::FromRef;
-use axum_jwt_auth::{JwtDecoderState, LocalDecoder};
-use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use axum_jwt_auth::JwtDecoderState;
 use serde::{Deserialize, Serialize};
 use database_adapter::DatabaseAdapter;
-use crate::SERVICE_AUDIENCE;
+use crate::jwt_keys::JwtKeys;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AccountManagerClaims {
@@ -13,34 +12,29 @@ pub struct AccountManagerClaims {
     pub iat: u64,           // Optional. Issued at (as UTC timestamp)
     pub aud: String,        // Optional. Audience
     pub exp: u64,           // Required. Expiration time (as UTC timestamp)
+    pub jti: String,        // Optional. Unique token ID, used for revocation
+    #[serde(default)]
+    pub sid: String,        // Family of refresh tokens issued with this token, dropped on logout. Empty in tokens issued before it was added.
 }
 
 #[derive(FromRef, Clone)]
 pub struct AppData {
     pub database_adapter: Arc<dyn DatabaseAdapter>,
+    pub jwt_keys: Arc<JwtKeys>,
     pub jwt_decoder: JwtDecoderState<AccountManagerClaims>,
 }
 
 impl AppData {
     pub async fn new(database_adapter: Arc<dyn DatabaseAdapter>) -> Self {
-        let public_key = database_adapter.get_jwt_public_key().await.unwrap();
-        let keys = vec![DecodingKey::from_rsa_pem(&public_key).unwrap()];
-
-        let mut validation = Validation::new(Algorithm::RS256);
-        validation.set_audience(&[SERVICE_AUDIENCE]);
-
-        let local_jwt_decoder = LocalDecoder::builder()
-            .keys(keys)
-            .validation(validation)
-            .build()
-            .unwrap();
+        let jwt_keys = Arc::new(JwtKeys::load(database_adapter.clone()).await.unwrap());
 
         let jwt_decoder = JwtDecoderState {
-            decoder: Arc::new(local_jwt_decoder),
+            decoder: jwt_keys.clone(),
         };
 
         Self {
             database_adapter,
+            jwt_keys,
             jwt_decoder,
         }
     }
@@ -0,0 +1,43 @@
+use axum::extract::{FromRequestParts, Path};
+use axum::http::request::Parts;
+use axum::response::{IntoResponse, Response};
+use axum_jwt_auth::Claims;
+use crate::app_data::{AccountManagerClaims, AppData};
+use crate::responses::ApiError;
+
+/// Account from `{username}` path segment, extracted only when it belongs to bearer of not revoked token.
+/// Use it instead of bare `Claims` on every `/accounts/{username}/...` route requiring login.
+#[derive(Debug, Clone)]
+pub struct AccountOwner {
+    pub username: String,
+    pub claims: AccountManagerClaims,
+}
+
+impl FromRequestParts<AppData> for AccountOwner {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, app_data: &AppData) -> Result<Self, Self::Rejection> {
+        let Claims(claims) = Claims::<AccountManagerClaims>::from_request_parts(parts, app_data)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        let is_revoked = app_data.database_adapter
+            .is_token_revoked(&claims.jti)
+            .await
+            .map_err(|err| ApiError::from(err).into_response())?;
+        if is_revoked {
+            return Err(ApiError::TokenRevoked.into_response());
+        }
+
+        let Path(username) = Path::<String>::from_request_parts(parts, app_data)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        if claims.iss != username {
+            tracing::warn!("Account '{}' tried to access account '{}'", claims.iss, username);
+            return Err(ApiError::AccountAccessForbidden.into_response());
+        }
+
+        Ok(Self { username, claims })
+    }
+}
@@ -1,8 +1,11 @@
 use std::sync::Arc;
+use clap::Parser;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 use accounts_manager::AccountsManagerServer;
-use database_adapter::test::DatabaseTestAdapter;
+use accounts_manager::admin::run_admin_command;
+use accounts_manager::config::{AccountsManagerArgs, AccountsManagerConfig};
+use database_adapter::sqlite::SqliteAdapter;
 
 #[tokio::main]
 async fn main() {
@@ -12,8 +15,20 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let database_adapter = Arc::new(DatabaseTestAdapter::new().await); // TODO use proper DB
-    let server = AccountsManagerServer::run(database_adapter).await.unwrap();
+    let args = AccountsManagerArgs::parse();
+    let config = AccountsManagerConfig::load(&args, std::env::vars()).expect("Invalid config");
+    tracing::info!("Running with {config:?}");
+
+    let database_adapter = SqliteAdapter::open(&config.database_path).await.unwrap();
+    let database_adapter = Arc::new(database_adapter);
+
+    if let Some(command) = &args.command {
+        let report = run_admin_command(command, database_adapter).await.expect("Command failed");
+        println!("{report}");
+        return;
+    }
+
+    let server = AccountsManagerServer::run_with_options(database_adapter, config.to_options()).await.unwrap();
 
     let ctrlc_notify = Arc::new(tokio::sync::Notify::new());
     let ctrlc_notify_shared = ctrlc_notify.clone();
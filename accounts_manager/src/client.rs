@@ -1,13 +1,25 @@
-use crate::requests::{CreateAccountRequest, LoginAccountRequest, NewCharacterRequest, UpdatePasswordRequest};
-use crate::responses::{AccountDetails, AccountsServerStatus, ApiError};
+use crate::requests::{CreateAccountRequest, LoginAccountRequest, NewCharacterRequest, RefreshTokensRequest, UpdatePasswordRequest};
+use crate::responses::{AccountDetails, AccountsServerStatus, ApiError, LoginTokens};
 use reqwest::{Client as HttpClient, Response, StatusCode};
 use std::time::Duration;
+use chrono::Utc;
+use jsonwebtoken::jwk::JwkSet;
+use tokio::sync::Mutex;
 use database_adapter::character::{CharacterData, CharacterId};
-use crate::{JwtToken};
+use crate::{JwtToken, RefreshToken};
+
+/// Session access token is refreshed when it is about to expire within this time
+pub const ACCESS_TOKEN_REFRESH_MARGIN_SECONDS: u64 = 60;
+
+struct ClientSession {
+    username: String,
+    login_tokens: LoginTokens,
+}
 
 pub struct AccountsManagerClient {
     base_url: String,
     http_client: HttpClient,
+    session: Mutex<Option<ClientSession>>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -42,6 +54,7 @@ impl AccountsManagerClient {
         Ok(Self {
             base_url,
             http_client,
+            session: Mutex::new(None),
         })
     }
 
@@ -54,6 +67,15 @@ impl AccountsManagerClient {
         resp.json().await.map_err(Into::into)
     }
 
+    pub async fn request_jwks(&self) -> AccountsManagerClientResult<JwkSet> {
+        let resp = self.http_client
+            .get(format!("{}/.well-known/jwks.json", self.base_url))
+            .send().await?
+            .error_for_status()?;
+
+        resp.json().await.map_err(Into::into)
+    }
+
     pub async fn request_create_account(
         &self,
         username: String,
@@ -83,7 +105,7 @@ impl AccountsManagerClient {
         &self,
         username: String,
         password: String,
-    ) -> AccountsManagerClientResult<JwtToken> {
+    ) -> AccountsManagerClientResult<LoginTokens> {
         let request_payload = LoginAccountRequest { password };
         let resp = self.http_client
             .post(&format!("{}/api/accounts/{}/login", self.base_url, username))
@@ -104,6 +126,72 @@ impl AccountsManagerClient {
         }
     }
 
+    /// Rejected refresh token is reported as `ApiError`, so reuse can be told apart from expiration
+    pub async fn request_refresh_tokens(
+        &self,
+        username: String,
+        refresh_token: RefreshToken,
+    ) -> AccountsManagerClientResult<LoginTokens> {
+        let request_payload = RefreshTokensRequest { refresh_token };
+        let resp = self.http_client
+            .post(format!("{}/api/accounts/{}/refresh", self.base_url, username))
+            .json(&request_payload)
+            .send().await?;
+
+        match resp.status() {
+            StatusCode::OK => Ok(resp.json().await?),
+            status => {
+                let reason = resp.text().await?;
+                Err(
+                    match serde_json::from_str::<ApiError>(&reason) {
+                        Ok(err) => err.into(),
+                        Err(_) => AccountsManagerClientError::OtherError { status, reason },
+                    }
+                )
+            }
+        }
+    }
+
+    /// Logs in and keeps tokens within client, so `session_token` can refresh them when needed
+    pub async fn start_session(
+        &self,
+        username: String,
+        password: String,
+    ) -> AccountsManagerClientResult<JwtToken> {
+        let login_tokens = self.request_login_to_account(username.clone(), password).await?;
+        let access_token = login_tokens.access_token.clone();
+        *self.session.lock().await = Some(ClientSession { username, login_tokens });
+        Ok(access_token)
+    }
+
+    /// Access token of session started with `start_session`, refreshed transparently before expiry.
+    /// Session is dropped if refreshing fails, `Unauthorized` is returned when there is no session.
+    pub async fn session_token(&self) -> AccountsManagerClientResult<JwtToken> {
+        let mut session_guard = self.session.lock().await;
+        let session = session_guard.as_mut().ok_or(AccountsManagerClientError::Unauthorized)?;
+
+        let refresh_at = session.login_tokens.access_token_expires_at.saturating_sub(ACCESS_TOKEN_REFRESH_MARGIN_SECONDS);
+        if Utc::now().timestamp() as u64 >= refresh_at {
+            tracing::debug!("Refreshing tokens of account '{}'", session.username);
+            match self.request_refresh_tokens(session.username.clone(), session.login_tokens.refresh_token.clone()).await {
+                Ok(login_tokens) => session.login_tokens = login_tokens,
+                Err(err) => {
+                    *session_guard = None;
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(session.login_tokens.access_token.clone())
+    }
+
+    /// Logs out token of session started with `start_session`
+    pub async fn end_session(&self) -> AccountsManagerClientResult<()> {
+        let access_token = self.session_token().await?;
+        let session = self.session.lock().await.take().ok_or(AccountsManagerClientError::Unauthorized)?;
+        self.request_logout_account(session.username, access_token).await
+    }
+
     pub async fn request_logout_account(
         &self,
         username: String,
@@ -267,7 +355,13 @@ impl AccountsManagerClient {
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
+    use database_adapter::DatabaseAdapterError;
+    use database_adapter::test::DatabaseTestAdapter;
+    use crate::AccountsManagerServer;
     use crate::client::{AccountsManagerClient, AccountsManagerClientError};
+    use crate::responses::ApiError;
+    use super::*;
 
     #[test]
     fn text_client_creation_not_connected_yet() {
@@ -287,4 +381,44 @@ mod tests {
             _ => panic!("Should not get ReqwestError!"),
         }
     }
+
+    #[tokio::test]
+    async fn test_session_token_is_refreshed_before_expiry() {
+        let database_adapter = Arc::new(DatabaseTestAdapter::new().await);
+        let server = AccountsManagerServer::run(database_adapter).await.unwrap();
+        let client = AccountsManagerClient::new(&server.get_address().to_string()).unwrap();
+
+        // No session yet
+        assert!(matches!(client.session_token().await, Err(AccountsManagerClientError::Unauthorized)));
+
+        client.request_create_account("User1".to_string(), "Password1".to_string()).await.unwrap();
+        let access_token = client.start_session("User1".to_string(), "Password1".to_string()).await.unwrap();
+
+        // Fresh token is not refreshed
+        assert_eq!(client.session_token().await.unwrap(), access_token);
+
+        // Make token about to expire
+        let old_refresh_token = {
+            let mut session = client.session.lock().await;
+            let session = session.as_mut().unwrap();
+            session.login_tokens.access_token_expires_at = Utc::now().timestamp() as u64 + ACCESS_TOKEN_REFRESH_MARGIN_SECONDS / 2;
+            session.login_tokens.refresh_token.clone()
+        };
+
+        let refreshed_access_token = client.session_token().await.unwrap();
+        assert_ne!(refreshed_access_token, access_token);
+        client.request_account_details("User1".to_string(), &refreshed_access_token).await.unwrap();
+
+        // Refresh token was rotated
+        let reuse_result = client.request_refresh_tokens("User1".to_string(), old_refresh_token).await;
+        assert!(matches!(
+            reuse_result,
+            Err(AccountsManagerClientError::ApiError(ApiError::DatabaseAdapterError(DatabaseAdapterError::RefreshTokenReused)))
+        ));
+
+        // Reuse dropped session tokens on server, so next refresh fails and session ends
+        client.session.lock().await.as_mut().unwrap().login_tokens.access_token_expires_at = 0;
+        assert!(client.session_token().await.is_err());
+        assert!(matches!(client.session_token().await, Err(AccountsManagerClientError::Unauthorized)));
+    }
 }
@@ -0,0 +1,194 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use crate::admin::AdminCommand;
+use crate::AccountsManagerOptions;
+
+pub const DEFAULT_PORT: u16 = 8080;
+/// Game server reads characters and revoked tokens from it, so both servers should be given the same one
+pub const DEFAULT_DATABASE_PATH: &str = "accounts.db";
+/// `ACCOUNTS_SERVER_PORT=8081` overrides `port`
+pub const ENV_PREFIX: &str = "ACCOUNTS_SERVER_";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error(transparent)]
+    StdIoError(#[from] std::io::Error),
+
+    #[error(transparent)]
+    TomlError(#[from] toml::de::Error),
+
+    #[error("Invalid value of '{key}', reason: '{reason}'")]
+    InvalidValue { key: String, reason: String },
+}
+
+pub type ConfigResult<T> = Result<T, ConfigError>;
+
+/// Settings of server binary. Defaults fit single machine deployment,
+/// tests run servers with `AccountsManagerOptions::default()` listening on any free port.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccountsManagerConfig {
+    pub bind_address: IpAddr,
+    pub port: u16,
+    pub database_path: PathBuf,
+}
+
+impl Default for AccountsManagerConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: DEFAULT_PORT,
+            database_path: PathBuf::from(DEFAULT_DATABASE_PATH),
+        }
+    }
+}
+
+/// Overrides both config file and environment
+#[derive(Debug, Default, clap::Parser)]
+#[command(version, about = "Accounts server")]
+pub struct AccountsManagerArgs {
+    /// TOML config file
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+    #[arg(long)]
+    pub bind_address: Option<IpAddr>,
+    #[arg(short, long)]
+    pub port: Option<u16>,
+    #[arg(long)]
+    pub database_path: Option<PathBuf>,
+    /// Runs given command and exits instead of serving
+    #[command(subcommand)]
+    pub command: Option<AdminCommand>,
+}
+
+impl AccountsManagerConfig {
+    /// Config file given in `args` first, then environment, then `args` themselves
+    pub fn load(args: &AccountsManagerArgs, env: impl IntoIterator<Item = (String, String)>) -> ConfigResult<Self> {
+        let table = load_layered_table(args.config.as_deref(), ENV_PREFIX, env)?;
+        let mut config: Self = toml::Value::Table(table).try_into()?;
+
+        if let Some(bind_address) = args.bind_address {
+            config.bind_address = bind_address;
+        }
+        if let Some(port) = args.port {
+            config.port = port;
+        }
+        if let Some(database_path) = &args.database_path {
+            config.database_path = database_path.clone();
+        }
+        Ok(config)
+    }
+
+    pub fn to_options(&self) -> AccountsManagerOptions {
+        AccountsManagerOptions { address: SocketAddr::new(self.bind_address, self.port) }
+    }
+}
+
+/// TOML file, if any, with environment variables starting with `env_prefix` put over it.
+/// Variable name is lowercased key, with `__` separating nested tables,
+/// so `PREFIX_LIMITS__IDLE_TIMEOUT_MS=5000` becomes `limits.idle_timeout_ms = 5000`.
+pub fn load_layered_table(
+    path: Option<&Path>,
+    env_prefix: &str,
+    env: impl IntoIterator<Item = (String, String)>
+) -> ConfigResult<toml::Table> {
+    let mut table = match path {
+        Some(path) => std::fs::read_to_string(path)?.parse::<toml::Table>()?,
+        None => toml::Table::new(),
+    };
+
+    for (key, value) in env {
+        let Some(key) = key.strip_prefix(env_prefix) else {
+            continue;
+        };
+        let key = key.to_lowercase();
+        let mut keys: Vec<&str> = key.split("__").collect();
+        // Safe unwrap - split yields at least one part
+        let last_key = keys.pop().unwrap();
+
+        let mut nested_table = &mut table;
+        for key in keys {
+            let entry = nested_table.entry(key).or_insert_with(|| toml::Table::new().into());
+            if !entry.is_table() {
+                *entry = toml::Table::new().into();
+            }
+            // Safe unwrap - made a table above
+            nested_table = entry.as_table_mut().unwrap();
+        }
+        nested_table.insert(last_key.to_string(), parse_env_value(&value));
+    }
+    Ok(table)
+}
+
+/// Taken as TOML value when it is one, so numbers and booleans need no quotes, as string otherwise
+fn parse_env_value(value: &str) -> toml::Value {
+    format!("value = {value}")
+        .parse::<toml::Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn test_layering_config() {
+        let path = std::env::temp_dir().join(format!("accounts_manager_config_test_{}.toml", std::process::id()));
+        std::fs::write(&path, "bind_address = \"0.0.0.0\"\nport = 9000\ndatabase_path = \"file.db\"\n").unwrap();
+
+        let args = AccountsManagerArgs { config: Some(path.clone()), ..Default::default() };
+        let config = AccountsManagerConfig::load(&args, env(&[])).unwrap();
+        assert_eq!(config.to_options().address, "0.0.0.0:9000".parse().unwrap());
+        assert_eq!(config.database_path, PathBuf::from("file.db"));
+
+        // Environment overrides file, unrelated variables are ignored
+        let vars = env(&[("ACCOUNTS_SERVER_PORT", "9001"), ("ACCOUNTS_SERVER_DATABASE_PATH", "env.db"), ("PORT", "1")]);
+        let config = AccountsManagerConfig::load(&args, vars.clone()).unwrap();
+        assert_eq!((config.port, config.database_path.as_path()), (9001, Path::new("env.db")));
+
+        // Command line overrides both
+        let args = AccountsManagerArgs::try_parse_from(["accounts_server", "-c", path.to_str().unwrap(), "--port", "9002"]).unwrap();
+        let config = AccountsManagerConfig::load(&args, vars).unwrap();
+        assert_eq!((config.port, config.database_path.as_path()), (9002, Path::new("env.db")));
+        assert_eq!(config.bind_address, IpAddr::from([0, 0, 0, 0]));
+        assert_eq!(args.command, None);
+
+        let args = AccountsManagerArgs::try_parse_from(["accounts_server", "-c", path.to_str().unwrap(), "rotate-jwt-keys"]).unwrap();
+        assert_eq!(args.command, Some(AdminCommand::RotateJwtKeys));
+        assert_eq!(AccountsManagerConfig::load(&args, env(&[])).unwrap().port, 9000);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_loading_bad_config_should_fail() {
+        let args = AccountsManagerArgs::default();
+        assert_eq!(AccountsManagerConfig::load(&args, env(&[])).unwrap(), AccountsManagerConfig::default());
+
+        let result = AccountsManagerConfig::load(&args, env(&[("ACCOUNTS_SERVER_PORT", "not a port")]));
+        assert!(matches!(result, Err(ConfigError::TomlError(_))));
+
+        let result = AccountsManagerConfig::load(&args, env(&[("ACCOUNTS_SERVER_UNKNOWN", "1")]));
+        assert!(matches!(result, Err(ConfigError::TomlError(_))));
+
+        let args = AccountsManagerArgs { config: Some(PathBuf::from("missing.toml")), ..Default::default() };
+        assert!(matches!(AccountsManagerConfig::load(&args, env(&[])), Err(ConfigError::StdIoError(_))));
+    }
+
+    #[test]
+    fn test_nesting_env_values() {
+        let vars = env(&[("P_LIMITS__IDLE_TIMEOUT_MS", "5000"), ("P_NAME", "127.0.0.1"), ("P_ENABLED", "true")]);
+        let table = load_layered_table(None, "P_", vars).unwrap();
+        assert_eq!(table["limits"]["idle_timeout_ms"].as_integer(), Some(5000));
+        assert_eq!(table["name"].as_str(), Some("127.0.0.1"));
+        assert_eq!(table["enabled"].as_bool(), Some(true));
+    }
+}
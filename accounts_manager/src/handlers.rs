@@ -2,10 +2,11 @@ use axum::Json;
 use axum::extract::{Path, State};
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
-use axum_jwt_auth::Claims;
+use jsonwebtoken::jwk::JwkSet;
 use serde::Serialize;
-use crate::app_data::{AccountManagerClaims, AppData};
-use crate::requests::{CreateAccountRequest, LoginAccountRequest, NewCharacterRequest, UpdatePasswordRequest};
+use crate::app_data::AppData;
+use crate::auth::AccountOwner;
+use crate::requests::{CreateAccountRequest, LoginAccountRequest, NewCharacterRequest, RefreshTokensRequest, UpdatePasswordRequest};
 use crate::responses::{AccountDetails, AccountsServerStatus, ApiError};
 use crate::services;
 
@@ -22,6 +23,11 @@ pub async fn overall_status(State(app_data): State<AppData>) -> Json<AccountsSer
     Json(status)
 }
 
+/// Public keys for verifying access tokens, also the ones retired but still valid
+pub async fn get_jwks(State(app_data): State<AppData>) -> Result<Json<JwkSet>, ApiError> {
+    Ok(Json(app_data.jwt_keys.get_jwks().await?))
+}
+
 pub async fn create_account(
     State(app_data): State<AppData>,
     Json(payload): Json<CreateAccountRequest>,
@@ -43,16 +49,25 @@ pub async fn login_to_account(
     }
 }
 
-pub async fn logout_account(
-    State(_app_data): State<AppData>,
+pub async fn refresh_tokens(
+    State(app_data): State<AppData>,
     Path(username): Path<String>,
+    Json(payload): Json<RefreshTokensRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let login_tokens = services::refresh_tokens(username, payload.refresh_token, app_data.database_adapter.clone()).await?;
+    Ok(Json(login_tokens))
+}
+
+pub async fn logout_account(
+    AccountOwner { username, claims }: AccountOwner,
+    State(app_data): State<AppData>,
 ) -> Result<impl IntoResponse, ApiError> {
+    services::logout_account(claims, app_data.database_adapter.clone()).await?;
     Ok((StatusCode::OK, format!("Logout account {}", username)))
 }
 
 pub async fn get_account_details(
-    Claims(_claims): Claims<AccountManagerClaims>,
-    Path(username): Path<String>,
+    AccountOwner { username, .. }: AccountOwner,
     State(app_data): State<AppData>,
 ) -> Result<impl IntoResponse, ApiError> {
     let characters_count = app_data.database_adapter.get_characters_of_account(&username).await?.len();
@@ -65,9 +80,8 @@ pub async fn get_account_details(
 }
 
 pub async fn delete_account(
-    Claims(claims): Claims<AccountManagerClaims>,
+    AccountOwner { username, .. }: AccountOwner,
     State(app_data): State<AppData>,
-    Path(username): Path<String>,
 ) -> Result<impl IntoResponse, ApiError> {
     match services::delete_account(username, app_data.database_adapter.clone()).await {
         Ok(account)  => Ok((StatusCode::OK, "Account deleted")),
@@ -76,9 +90,8 @@ pub async fn delete_account(
 }
 
 pub async fn update_account_password(
-    Claims(claims): Claims<AccountManagerClaims>,
+    AccountOwner { username, .. }: AccountOwner,
     State(app_data): State<AppData>,
-    Path(username): Path<String>,
     Json(payload): Json<UpdatePasswordRequest>,
 ) -> Result<impl IntoResponse, ApiError> {
     match services::update_account_password(
@@ -93,9 +106,8 @@ pub async fn update_account_password(
 }
 
 pub async fn get_characters_of_account(
-    Claims(claims): Claims<AccountManagerClaims>,
+    AccountOwner { username, .. }: AccountOwner,
     State(app_data): State<AppData>,
-    Path(username): Path<String>,
 ) -> Result<impl IntoResponse, ApiError> {
     match services::get_characters_of_account(
         username,
@@ -107,9 +119,8 @@ pub async fn get_characters_of_account(
 }
 
 pub async fn create_character_for_account(
-    Claims(claims): Claims<AccountManagerClaims>,
+    AccountOwner { username, .. }: AccountOwner,
     State(app_data): State<AppData>,
-    Path(username): Path<String>,
     Json(payload): Json<NewCharacterRequest>,
 ) -> Result<impl IntoResponse, ApiError> {
     match services::create_character_for_account(
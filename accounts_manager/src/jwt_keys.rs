@@ -0,0 +1,556 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use async_trait::async_trait;
+use axum_jwt_auth::JwtDecoder;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{Duration, Utc};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, TokenData, Validation};
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters,
+    CommonParameters,
+    EllipticCurve,
+    Jwk,
+    JwkSet,
+    KeyAlgorithm,
+    OctetKeyPairParameters,
+    OctetKeyPairType,
+    PublicKeyUse,
+    RSAKeyParameters,
+    RSAKeyType
+};
+use ring::rand::SystemRandom;
+use ring::rsa::PublicKeyComponents;
+use ring::signature::{Ed25519KeyPair, KeyPair, RsaKeyPair};
+use tokio::sync::RwLock;
+use database_adapter::{DatabaseAdapter, DatabaseAdapterError, DatabaseAdapterResult};
+use database_adapter::jwt_key::JwtKeyData;
+use crate::app_data::AccountManagerClaims;
+use crate::{services, JWT_EXPIRATION_MINUTES, SERVICE_AUDIENCE};
+
+fn jwt_error(reason: impl ToString) -> DatabaseAdapterError {
+    DatabaseAdapterError::JwtError(reason.to_string())
+}
+
+fn parse_algorithm(key: &JwtKeyData) -> DatabaseAdapterResult<Algorithm> {
+    match Algorithm::from_str(&key.algorithm) {
+        Ok(algorithm @ (Algorithm::RS256 | Algorithm::EdDSA)) => Ok(algorithm),
+        _ => Err(jwt_error(format!("Unsupported algorithm '{}' of key '{}'", key.algorithm, key.key_id))),
+    }
+}
+
+/// New keys are Ed25519, they are small and fast to generate
+pub fn generate_jwt_key(created_at: u64) -> DatabaseAdapterResult<JwtKeyData> {
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).map_err(jwt_error)?;
+    let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).map_err(jwt_error)?;
+
+    Ok(JwtKeyData {
+        key_id: services::generate_token_id(),
+        algorithm: "EdDSA".to_string(),
+        private_key: pkcs8.as_ref().to_vec(),
+        public_key: key_pair.public_key().as_ref().to_vec(),
+        created_at,
+        retired_at: None,
+    })
+}
+
+/// Public part of the key, the only form keys leave the server
+pub fn jwk_of_key(key: &JwtKeyData) -> DatabaseAdapterResult<Jwk> {
+    let (key_algorithm, algorithm_parameters) = match parse_algorithm(key)? {
+        Algorithm::RS256 => {
+            // Components are taken from private key, so PKCS#1 and PKCS#8 encodings are both fine
+            let private_key = pem::parse(&key.private_key).map_err(jwt_error)?;
+            let key_pair = match private_key.tag() {
+                "RSA PRIVATE KEY" => RsaKeyPair::from_der(private_key.contents()),
+                _ => RsaKeyPair::from_pkcs8(private_key.contents()),
+            }.map_err(jwt_error)?;
+            let components = PublicKeyComponents::<Vec<u8>>::from(key_pair.public());
+
+            (KeyAlgorithm::RS256, AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n: URL_SAFE_NO_PAD.encode(components.n),
+                e: URL_SAFE_NO_PAD.encode(components.e),
+            }))
+        },
+        _ => (KeyAlgorithm::EdDSA, AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+            key_type: OctetKeyPairType::OctetKeyPair,
+            curve: EllipticCurve::Ed25519,
+            x: URL_SAFE_NO_PAD.encode(&key.public_key),
+        })),
+    };
+
+    Ok(Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(key_algorithm),
+            key_id: Some(key.key_id.clone()),
+            ..Default::default()
+        },
+        algorithm: algorithm_parameters,
+    })
+}
+
+/// Tokens live at most this long, so key retired longer ago can't have signed any valid token
+fn token_lifetime() -> u64 {
+    Duration::minutes(JWT_EXPIRATION_MINUTES).num_seconds() as u64
+}
+
+/// UTC timestamp since which tokens signed with the key are rejected, `None` for active key
+pub fn key_verifies_until(key: &JwtKeyData) -> Option<u64> {
+    key.retired_at.map(|retired_at| retired_at.saturating_add(token_lifetime()))
+}
+
+pub fn is_key_expired(key: &JwtKeyData, now: u64) -> bool {
+    key_verifies_until(key).is_some_and(|verifies_until| verifies_until < now)
+}
+
+pub struct SigningKey {
+    pub key_id: String,
+    pub algorithm: Algorithm,
+    pub encoding_key: EncodingKey,
+}
+
+/// The newest active key, read on every use so rotation done by any instance is picked up
+pub async fn get_signing_key(database_adapter: &Arc<dyn DatabaseAdapter>) -> DatabaseAdapterResult<SigningKey> {
+    let jwt_keys = database_adapter.get_jwt_keys().await?;
+    let key = jwt_keys
+        .iter()
+        .rev()
+        .find(|key| key.is_active())
+        .ok_or(jwt_error("No active JWT key"))?;
+
+    let algorithm = parse_algorithm(key)?;
+    let encoding_key = match algorithm {
+        Algorithm::RS256 => EncodingKey::from_rsa_pem(&key.private_key).map_err(jwt_error)?,
+        _ => EncodingKey::from_ed_der(&key.private_key),
+    };
+
+    Ok(SigningKey {
+        key_id: key.key_id.clone(),
+        algorithm,
+        encoding_key,
+    })
+}
+
+/// Public key able to verify tokens with given `kid`
+#[derive(Clone)]
+pub struct VerifyingKey {
+    key_id: String,
+    algorithm: Algorithm,
+    decoding_key: DecodingKey,
+    verifies_until: Option<u64>,
+}
+
+impl VerifyingKey {
+    /// Tokens are rejected since `verifies_until` UTC timestamp, `None` for no limit
+    pub fn from_jwk(jwk: &Jwk, verifies_until: Option<u64>) -> DatabaseAdapterResult<Self> {
+        let key_id = jwk.common.key_id.clone().ok_or(jwt_error("JWK without key ID"))?;
+        let algorithm = match jwk.common.key_algorithm {
+            Some(KeyAlgorithm::RS256) => Algorithm::RS256,
+            Some(KeyAlgorithm::EdDSA) => Algorithm::EdDSA,
+            key_algorithm => return Err(jwt_error(format!("Unsupported algorithm {key_algorithm:?} of key '{key_id}'"))),
+        };
+
+        Ok(Self {
+            key_id,
+            algorithm,
+            decoding_key: DecodingKey::from_jwk(jwk).map_err(jwt_error)?,
+            verifies_until,
+        })
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        self.verifies_until.is_some_and(|verifies_until| verifies_until < now)
+    }
+}
+
+/// Where `JwtVerifier` takes keys from
+#[async_trait]
+pub trait VerifyingKeysSource: Send + Sync {
+    async fn get_verifying_keys(&self) -> DatabaseAdapterResult<Vec<VerifyingKey>>;
+}
+
+/// Keys stored in database, retired ones included until tokens signed with them expire
+#[async_trait]
+impl VerifyingKeysSource for Arc<dyn DatabaseAdapter> {
+    async fn get_verifying_keys(&self) -> DatabaseAdapterResult<Vec<VerifyingKey>> {
+        self.get_jwt_keys().await?
+            .iter()
+            .map(|key| VerifyingKey::from_jwk(&jwk_of_key(key)?, key_verifies_until(key)))
+            .collect()
+    }
+}
+
+/// Unknown key ID costs nothing to send, so it makes source read at most once per this time
+pub const RELOAD_ON_DEMAND_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+#[derive(Default)]
+struct CachedKeys {
+    verifying_keys: HashMap<String, VerifyingKey>,
+    loaded_at: Option<Instant>,
+}
+
+/// Verifies access tokens with key selected by `kid` header. Keys are cached,
+/// cache is reloaded when token refers to unknown key and when it gets older than max age,
+/// both at most once per `RELOAD_ON_DEMAND_INTERVAL`.
+pub struct JwtVerifier<S> {
+    source: S,
+    cached_keys: RwLock<CachedKeys>,
+    max_age: Option<std::time::Duration>,
+    last_reload_on_demand: Mutex<Option<Instant>>,
+    reload_on_demand_interval: std::time::Duration,
+}
+
+impl<S: VerifyingKeysSource> JwtVerifier<S> {
+    /// Nothing is loaded until `reload` or the first token
+    pub fn new(source: S, max_age: Option<std::time::Duration>) -> Self {
+        Self {
+            source,
+            cached_keys: RwLock::new(CachedKeys::default()),
+            max_age,
+            last_reload_on_demand: Mutex::new(None),
+            reload_on_demand_interval: RELOAD_ON_DEMAND_INTERVAL,
+        }
+    }
+
+    pub async fn reload(&self) -> DatabaseAdapterResult<()> {
+        let verifying_keys = self.source
+            .get_verifying_keys().await?
+            .into_iter()
+            .map(|verifying_key| (verifying_key.key_id.clone(), verifying_key))
+            .collect();
+
+        *self.cached_keys.write().await = CachedKeys {
+            verifying_keys,
+            loaded_at: Some(Instant::now()),
+        };
+        Ok(())
+    }
+
+    pub async fn decode(&self, token: &str) -> Result<TokenData<AccountManagerClaims>, axum_jwt_auth::Error> {
+        let header = jsonwebtoken::decode_header(token)?;
+        let key_id = header.kid.ok_or(jsonwebtoken::errors::Error::from(ErrorKind::InvalidToken))?;
+
+        // Key could be added by other instance sharing the source, or removed from it since loading
+        if (self.find_verifying_key(&key_id).await.is_none() || self.is_stale().await) && self.reserve_reload_on_demand() {
+            self.reload().await.map_err(|err| axum_jwt_auth::Error::Configuration(err.to_string()))?;
+        }
+        let verifying_key = self.find_verifying_key(&key_id).await
+            .ok_or(jsonwebtoken::errors::Error::from(ErrorKind::InvalidToken))?;
+
+        // Regardless of `exp`, retired key could have leaked since, like the legacy one committed to repository
+        if verifying_key.is_expired(Utc::now().timestamp() as u64) {
+            return Err(jsonwebtoken::errors::Error::from(ErrorKind::InvalidToken).into());
+        }
+
+        let mut validation = Validation::new(verifying_key.algorithm);
+        validation.set_audience(&[SERVICE_AUDIENCE]);
+        Ok(jsonwebtoken::decode(token, &verifying_key.decoding_key, &validation)?)
+    }
+
+    async fn find_verifying_key(&self, key_id: &str) -> Option<VerifyingKey> {
+        self.cached_keys.read().await.verifying_keys.get(key_id).cloned()
+    }
+
+    async fn is_stale(&self) -> bool {
+        let loaded_at = self.cached_keys.read().await.loaded_at;
+        self.max_age.is_some_and(|max_age| loaded_at.is_none_or(|loaded_at| loaded_at.elapsed() >= max_age))
+    }
+
+    /// `false` if reload on demand was done within interval
+    fn reserve_reload_on_demand(&self) -> bool {
+        // Safe unwrap - lock is never held across panic
+        let mut last_reload = self.last_reload_on_demand.lock().unwrap();
+        let now = Instant::now();
+        if last_reload.is_some_and(|last_reload| now.duration_since(last_reload) < self.reload_on_demand_interval) {
+            return false;
+        }
+        *last_reload = Some(now);
+        true
+    }
+}
+
+/// Signs with the newest active key and verifies with any key still verifying,
+/// keys are cached and reloaded after rotation.
+pub struct JwtKeys {
+    database_adapter: Arc<dyn DatabaseAdapter>,
+    verifier: JwtVerifier<Arc<dyn DatabaseAdapter>>,
+}
+
+impl JwtKeys {
+    /// Generates the first key if there is no active one, removes keys of expired tokens
+    pub async fn load(database_adapter: Arc<dyn DatabaseAdapter>) -> DatabaseAdapterResult<Self> {
+        let now = Utc::now().timestamp() as u64;
+        let has_active_key = database_adapter.get_jwt_keys().await?.iter().any(JwtKeyData::is_active);
+        if !has_active_key {
+            tracing::info!("No active JWT key, generating one");
+            database_adapter.rotate_jwt_key(generate_jwt_key(now)?).await?;
+        }
+
+        let removed_count = Self::remove_expired_keys(&database_adapter, now).await?;
+        if removed_count > 0 {
+            tracing::info!("Removed {removed_count} expired JWT keys");
+        }
+
+        let jwt_keys = Self {
+            verifier: JwtVerifier::new(database_adapter.clone(), None),
+            database_adapter,
+        };
+        jwt_keys.reload().await?;
+        Ok(jwt_keys)
+    }
+
+    pub async fn reload(&self) -> DatabaseAdapterResult<()> {
+        self.verifier.reload().await
+    }
+
+    /// Keys which can still verify tokens
+    pub async fn get_jwks(&self) -> DatabaseAdapterResult<JwkSet> {
+        let now = Utc::now().timestamp() as u64;
+        let keys = self.database_adapter
+            .get_jwt_keys().await?
+            .iter()
+            .filter(|key| !is_key_expired(key, now))
+            .map(jwk_of_key)
+            .collect::<DatabaseAdapterResult<Vec<_>>>()?;
+        Ok(JwkSet { keys })
+    }
+
+    /// New key signs tokens from now on. Retired keys keep verifying until tokens signed with them expire,
+    /// keys retired earlier than that are removed. Returns ID of the new key.
+    pub async fn rotate(&self) -> DatabaseAdapterResult<String> {
+        let now = Utc::now().timestamp() as u64;
+        let new_key = generate_jwt_key(now)?;
+        let new_key_id = new_key.key_id.clone();
+        self.database_adapter.rotate_jwt_key(new_key).await?;
+        let removed_count = Self::remove_expired_keys(&self.database_adapter, now).await?;
+
+        self.reload().await?;
+        tracing::info!("Rotated JWT keys, new key '{new_key_id}', removed {removed_count} expired keys");
+        Ok(new_key_id)
+    }
+
+    async fn remove_expired_keys(database_adapter: &Arc<dyn DatabaseAdapter>, now: u64) -> DatabaseAdapterResult<usize> {
+        database_adapter.remove_jwt_keys_retired_before(now.saturating_sub(token_lifetime())).await
+    }
+}
+
+impl Debug for JwtKeys {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "JwtKeys")
+    }
+}
+
+#[async_trait]
+impl JwtDecoder<AccountManagerClaims> for JwtKeys {
+    async fn decode(&self, token: &str) -> Result<TokenData<AccountManagerClaims>, axum_jwt_auth::Error> {
+        self.verifier.decode(token).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::{encode, Header};
+    use database_adapter::sqlite::SqliteAdapter;
+    use database_adapter::test::{DatabaseTestAdapter, TEST_JWT_KEY_ID, TEST_JWT_PRIVATE_KEY, TEST_JWT_PUBLIC_KEY};
+    use super::*;
+
+    async fn sign_claims(database_adapter: &Arc<dyn DatabaseAdapter>) -> String {
+        let signing_key = get_signing_key(database_adapter).await.unwrap();
+        let mut header = Header::new(signing_key.algorithm);
+        header.kid = Some(signing_key.key_id);
+
+        let claims = AccountManagerClaims {
+            iss: "User1".to_string(),
+            iat: Utc::now().timestamp() as u64,
+            aud: SERVICE_AUDIENCE.to_string(),
+            exp: Utc::now().timestamp() as u64 + 60,
+            jti: services::generate_token_id(),
+            sid: services::generate_token_id(),
+        };
+        encode(&header, &claims, &signing_key.encoding_key).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_loading_generates_first_key() {
+        let database_adapter: Arc<dyn DatabaseAdapter> = Arc::new(SqliteAdapter::open_in_memory().await.unwrap());
+        let jwt_keys = JwtKeys::load(database_adapter.clone()).await.unwrap();
+
+        let keys = database_adapter.get_jwt_keys().await.unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].algorithm, "EdDSA");
+
+        let token = sign_claims(&database_adapter).await;
+        assert_eq!(jwt_keys.decode(&token).await.unwrap().claims.iss, "User1");
+
+        // Loading again keeps the key
+        JwtKeys::load(database_adapter.clone()).await.unwrap();
+        assert_eq!(database_adapter.get_jwt_keys().await.unwrap(), keys);
+    }
+
+    #[tokio::test]
+    async fn test_migrated_legacy_key_only_verifies() {
+        let path = std::env::temp_dir().join(format!("accounts_manager_legacy_key_test_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        // Database as it was before multiple keys, with the single key taken from repository
+        {
+            let mut connection = rusqlite::Connection::open(&path).unwrap();
+            database_adapter::sqlite::migrations::migrate_to(&mut connection, 4).unwrap();
+            connection.execute(
+                "INSERT INTO jwt_keys (id, private_key, public_key) VALUES (0, ?1, ?2)",
+                (TEST_JWT_PRIVATE_KEY, TEST_JWT_PUBLIC_KEY),
+            ).unwrap();
+        }
+
+        let database_adapter: Arc<dyn DatabaseAdapter> = Arc::new(SqliteAdapter::open(&path).await.unwrap());
+        // Test adapter signs with the same key
+        let legacy_token = sign_claims(&(Arc::new(DatabaseTestAdapter::new().await) as Arc<dyn DatabaseAdapter>)).await;
+
+        let jwt_keys = JwtKeys::load(database_adapter.clone()).await.unwrap();
+        let signing_key = get_signing_key(&database_adapter).await.unwrap();
+        assert_ne!(signing_key.key_id, TEST_JWT_KEY_ID);
+
+        // Tokens issued before upgrade stay valid until they expire
+        assert_eq!(jsonwebtoken::decode_header(&legacy_token).unwrap().kid.unwrap(), TEST_JWT_KEY_ID);
+        jwt_keys.decode(&legacy_token).await.unwrap();
+        let token = sign_claims(&database_adapter).await;
+        assert_eq!(jsonwebtoken::decode_header(&token).unwrap().kid.unwrap(), signing_key.key_id);
+        jwt_keys.decode(&token).await.unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_old_tokens_are_verified_after_rotation() {
+        let database_adapter: Arc<dyn DatabaseAdapter> = Arc::new(DatabaseTestAdapter::new().await);
+        let jwt_keys = JwtKeys::load(database_adapter.clone()).await.unwrap();
+
+        let old_token = sign_claims(&database_adapter).await;
+        let new_key_id = jwt_keys.rotate().await.unwrap();
+        let new_token = sign_claims(&database_adapter).await;
+
+        assert_eq!(jsonwebtoken::decode_header(&old_token).unwrap().kid.unwrap(), TEST_JWT_KEY_ID);
+        assert_eq!(jsonwebtoken::decode_header(&new_token).unwrap().kid.unwrap(), new_key_id);
+        jwt_keys.decode(&old_token).await.unwrap();
+        jwt_keys.decode(&new_token).await.unwrap();
+
+        let jwks = jwt_keys.get_jwks().await.unwrap();
+        assert!(jwks.find(TEST_JWT_KEY_ID).is_some());
+        assert!(jwks.find(&new_key_id).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_rotation_removes_keys_of_expired_tokens() {
+        let database_adapter: Arc<dyn DatabaseAdapter> = Arc::new(DatabaseTestAdapter::new().await);
+        let jwt_keys = JwtKeys::load(database_adapter.clone()).await.unwrap();
+
+        // Test key gets retired long ago
+        database_adapter.rotate_jwt_key(generate_jwt_key(1).unwrap()).await.unwrap();
+        let second_key_id = jwt_keys.rotate().await.unwrap();
+
+        let key_ids: Vec<String> = database_adapter.get_jwt_keys().await.unwrap()
+            .into_iter()
+            .map(|key| key.key_id)
+            .collect();
+        assert_eq!(key_ids.len(), 2);
+        assert!(!key_ids.contains(&TEST_JWT_KEY_ID.to_string()));
+        assert_eq!(key_ids.last(), Some(&second_key_id));
+    }
+
+    #[tokio::test]
+    async fn test_token_of_expired_key_is_rejected() {
+        let database_adapter: Arc<dyn DatabaseAdapter> = Arc::new(DatabaseTestAdapter::new().await);
+        let jwt_keys = JwtKeys::load(database_adapter.clone()).await.unwrap();
+        let token = sign_claims(&database_adapter).await;
+
+        // Test key gets retired long ago, its tokens can't be valid anymore whatever their expiration says
+        database_adapter.rotate_jwt_key(generate_jwt_key(1).unwrap()).await.unwrap();
+        jwt_keys.reload().await.unwrap();
+        assert!(jwt_keys.decode(&token).await.is_err());
+        assert!(jwt_keys.get_jwks().await.unwrap().find(TEST_JWT_KEY_ID).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_loading_removes_expired_keys() {
+        let database_adapter: Arc<dyn DatabaseAdapter> = Arc::new(DatabaseTestAdapter::new().await);
+        database_adapter.rotate_jwt_key(generate_jwt_key(1).unwrap()).await.unwrap();
+
+        JwtKeys::load(database_adapter.clone()).await.unwrap();
+        let keys = database_adapter.get_jwt_keys().await.unwrap();
+        assert_eq!(keys.len(), 1);
+        assert!(keys[0].is_active());
+    }
+
+    #[tokio::test]
+    async fn test_key_added_by_other_instance_is_picked_up() {
+        let database_adapter: Arc<dyn DatabaseAdapter> = Arc::new(DatabaseTestAdapter::new().await);
+        let jwt_keys = JwtKeys::load(database_adapter.clone()).await.unwrap();
+        let other_jwt_keys = JwtKeys::load(database_adapter.clone()).await.unwrap();
+
+        other_jwt_keys.rotate().await.unwrap();
+        let token = sign_claims(&database_adapter).await;
+        jwt_keys.decode(&token).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unknown_keys_reload_rarely() {
+        let database_adapter: Arc<dyn DatabaseAdapter> = Arc::new(SqliteAdapter::open_in_memory().await.unwrap());
+        let mut jwt_keys = JwtKeys::load(database_adapter.clone()).await.unwrap();
+        jwt_keys.verifier.reload_on_demand_interval = std::time::Duration::from_millis(300);
+        let other_jwt_keys = JwtKeys::load(database_adapter.clone()).await.unwrap();
+
+        // Reloads and still doesn't know the key
+        let unknown_key_token = sign_claims(&(Arc::new(DatabaseTestAdapter::new().await) as Arc<dyn DatabaseAdapter>)).await;
+        assert!(jwt_keys.decode(&unknown_key_token).await.is_err());
+
+        // Key added by other instance is not looked for until interval passes
+        other_jwt_keys.rotate().await.unwrap();
+        let token = sign_claims(&database_adapter).await;
+        assert!(jwt_keys.decode(&token).await.is_err());
+
+        tokio::time::sleep(jwt_keys.verifier.reload_on_demand_interval).await;
+        jwt_keys.decode(&token).await.unwrap();
+        assert!(jwt_keys.decode(&unknown_key_token).await.is_err());
+    }
+
+    struct TestKeysSource(Mutex<Vec<VerifyingKey>>);
+
+    #[async_trait]
+    impl VerifyingKeysSource for TestKeysSource {
+        async fn get_verifying_keys(&self) -> DatabaseAdapterResult<Vec<VerifyingKey>> {
+            Ok(self.0.lock().unwrap().clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stale_keys_are_reloaded() {
+        let database_adapter: Arc<dyn DatabaseAdapter> = Arc::new(DatabaseTestAdapter::new().await);
+        let source = TestKeysSource(Mutex::new(database_adapter.get_verifying_keys().await.unwrap()));
+        let mut verifier = JwtVerifier::new(source, Some(std::time::Duration::from_millis(300)));
+        verifier.reload_on_demand_interval = std::time::Duration::from_millis(100);
+        let token = sign_claims(&database_adapter).await;
+        verifier.decode(&token).await.unwrap();
+
+        // Source stops publishing the key, cached one verifies until cache gets too old
+        verifier.source.0.lock().unwrap().clear();
+        verifier.decode(&token).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+        assert!(verifier.decode(&token).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_token_of_unknown_key_is_rejected() {
+        let database_adapter: Arc<dyn DatabaseAdapter> = Arc::new(DatabaseTestAdapter::new().await);
+        let token = sign_claims(&database_adapter).await;
+
+        // Key is gone, as if database was replaced
+        let other_database_adapter: Arc<dyn DatabaseAdapter> = Arc::new(SqliteAdapter::open_in_memory().await.unwrap());
+        let jwt_keys = JwtKeys::load(other_database_adapter).await.unwrap();
+        assert!(jwt_keys.decode(&token).await.is_err());
+    }
+}
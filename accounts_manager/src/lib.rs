@@ -8,7 +8,8 @@ use tower_http::trace::{
     DefaultOnResponse,
     TraceLayer
 };
-use database_adapter::DatabaseAdapter;
+use database_adapter::{DatabaseAdapter, DatabaseAdapterResult};
+use crate::jwt_keys::JwtKeys;
 
 pub mod router;
 pub mod app_data;
@@ -17,24 +18,48 @@ pub mod client;
 pub mod requests;
 pub mod responses;
 pub mod services;
+pub mod auth;
+pub mod jwt_keys;
+pub mod config;
+pub mod admin;
 mod testing;
 
 pub const SERVICE_AUDIENCE: &str = "accounts_manager";
-pub const JWT_EXPIRATION_HOURS: i64 = 1;
+pub const JWT_EXPIRATION_MINUTES: i64 = 15;
+pub const REFRESH_TOKEN_EXPIRATION_DAYS: i64 = 30;
 
 pub type JwtToken = String;
+pub type RefreshToken = String;
+
+#[derive(Debug, Clone)]
+pub struct AccountsManagerOptions {
+    /// Port 0 lets system pick a free one, see `get_address`
+    pub address: std::net::SocketAddr,
+}
+
+impl Default for AccountsManagerOptions {
+    fn default() -> Self {
+        Self { address: std::net::SocketAddr::from(([127, 0, 0, 1], 0)) }
+    }
+}
 
 #[derive(Debug)]
 pub struct AccountsManagerServer {
     task_handle: tokio::task::JoinHandle<Result<(), std::io::Error>>,
     shutdown_tx: Option<tokio::sync::oneshot::Sender<()>>,
     address: std::net::SocketAddr,
+    jwt_keys: Arc<JwtKeys>,
 }
 
 impl AccountsManagerServer {
     pub async fn run(database_adapter: Arc<dyn DatabaseAdapter>) -> tokio::io::Result<Self> {
+        Self::run_with_options(database_adapter, AccountsManagerOptions::default()).await
+    }
+
+    pub async fn run_with_options(database_adapter: Arc<dyn DatabaseAdapter>, options: AccountsManagerOptions) -> tokio::io::Result<Self> {
 
         let app_data = AppData::new(database_adapter).await;
+        let jwt_keys = app_data.jwt_keys.clone();
 
         let app = router::get_router(app_data)
             .layer(TraceLayer::new_for_http()
@@ -43,7 +68,7 @@ impl AccountsManagerServer {
                        .on_response(DefaultOnResponse::new().level(tracing::Level::DEBUG)),
             );
 
-        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let listener = tokio::net::TcpListener::bind(options.address).await?;
         let address = listener.local_addr()?;
 
         tracing::info!("{}({}) listening on {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), address);
@@ -63,6 +88,7 @@ impl AccountsManagerServer {
                 task_handle,
                 shutdown_tx: Some(shutdown_tx),
                 address,
+                jwt_keys,
             }
         )
     }
@@ -103,6 +129,11 @@ impl AccountsManagerServer {
     pub fn get_url(&self) -> String {
         format!("http://{}", self.address)
     }
+
+    /// Starts signing with new key, returns its ID. Tokens signed earlier stay valid until they expire.
+    pub async fn rotate_jwt_keys(&self) -> DatabaseAdapterResult<String> {
+        self.jwt_keys.rotate().await
+    }
 }
 
 
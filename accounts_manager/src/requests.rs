@@ -20,4 +20,9 @@ pub struct UpdatePasswordRequest {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NewCharacterRequest {
     pub character_name: String,
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefreshTokensRequest {
+    pub refresh_token: String,
+}
@@ -4,6 +4,7 @@ use axum::response::{IntoResponse, Response};
 use serde::{Deserialize, Serialize};
 use database_adapter::character::CharacterId;
 use database_adapter::DatabaseAdapterError;
+use crate::{JwtToken, RefreshToken};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AccountsServerStatus {
@@ -16,10 +17,25 @@ pub struct AccountDetails {
     pub characters_count: usize,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LoginTokens {
+    pub access_token: JwtToken,
+    /// UTC timestamp, refresh before it passes
+    pub access_token_expires_at: u64,
+    /// Single use, every refresh returns new one
+    pub refresh_token: RefreshToken,
+}
+
 #[derive(Debug, thiserror::Error, Serialize, Deserialize, PartialEq, Clone)]
 pub enum ApiError {
     #[error(transparent)]
     DatabaseAdapterError(#[from] DatabaseAdapterError),
+
+    #[error("Access to account of other user is forbidden")]
+    AccountAccessForbidden,
+
+    #[error("Token was revoked")]
+    TokenRevoked,
 }
 
 impl IntoResponse for ApiError {
@@ -37,7 +53,15 @@ impl IntoResponse for ApiError {
                 DatabaseAdapterError::CharacterNotAttached => StatusCode::BAD_REQUEST,
                 DatabaseAdapterError::CharacterNotOwnedByAccount => StatusCode::BAD_REQUEST,
                 DatabaseAdapterError::JwtError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            }
+                DatabaseAdapterError::JwtKeyIdAlreadyExists => StatusCode::INTERNAL_SERVER_ERROR,
+                DatabaseAdapterError::RefreshTokenNotFound => StatusCode::UNAUTHORIZED,
+                DatabaseAdapterError::RefreshTokenExpired => StatusCode::UNAUTHORIZED,
+                DatabaseAdapterError::RefreshTokenReused => StatusCode::UNAUTHORIZED,
+                DatabaseAdapterError::StorageError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                DatabaseAdapterError::UnsupportedSchemaVersion { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            },
+            ApiError::AccountAccessForbidden => StatusCode::FORBIDDEN,
+            ApiError::TokenRevoked => StatusCode::UNAUTHORIZED,
         };
         (status_code, Json(self)).into_response()
     }
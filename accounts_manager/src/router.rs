@@ -19,6 +19,10 @@ pub fn get_router(app_data: AppData) -> Router {
             "/accounts/{username}/login",
             post(login_to_account),
         )
+        .route(
+            "/accounts/{username}/refresh",
+            post(refresh_tokens),
+        )
         .route(
             "/accounts/{username}/logout",
             post(logout_account),
@@ -42,6 +46,7 @@ pub fn get_router(app_data: AppData) -> Router {
 
     Router::new()
         .route("/", get(overall_status))
+        .route("/.well-known/jwks.json", get(get_jwks))
         .nest("/api", api_routes)
         .with_state(app_data)
 }
@@ -1,10 +1,15 @@
 use std::sync::Arc;
-use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use jsonwebtoken::{encode, Header};
 use database_adapter::{AccountData, DatabaseAdapter, DatabaseAdapterError, DatabaseAdapterResult};
 use database_adapter::character::{CharacterData, CharacterId, NewCharacterData};
 use chrono::{Duration, Utc};
+use rand_core::{OsRng, RngCore};
+use ring::digest::{digest, SHA256};
+use database_adapter::refresh_token::RefreshTokenData;
 use crate::app_data::AccountManagerClaims;
-use crate::{JwtToken, JWT_EXPIRATION_HOURS, SERVICE_AUDIENCE};
+use crate::jwt_keys;
+use crate::responses::LoginTokens;
+use crate::{RefreshToken, JWT_EXPIRATION_MINUTES, REFRESH_TOKEN_EXPIRATION_DAYS, SERVICE_AUDIENCE};
 
 async fn verify_password(
     username: &str,
@@ -35,30 +40,133 @@ pub async fn login_to_account(
     username: String,
     password: String,
     database_adapter: Arc<dyn DatabaseAdapter>,
-) -> DatabaseAdapterResult<JwtToken> {
+) -> DatabaseAdapterResult<LoginTokens> {
     verify_password(&username, &password, database_adapter.clone()).await?;
 
-    let private_key = database_adapter.get_jwt_private_key().await?;
-    let key = EncodingKey::from_rsa_pem(&private_key)
-        .map_err(|e| DatabaseAdapterError::JwtError(e.to_string()))?;
+    // Every login starts new family of refresh tokens
+    let family_id = generate_token_id();
+    let refresh_token = generate_token_id();
+    database_adapter.add_refresh_token(new_refresh_token_data(username.clone(), family_id.clone(), &refresh_token)).await?;
+
+    let pruned_count = database_adapter.prune_refresh_tokens(Utc::now().timestamp() as u64).await?;
+    if pruned_count > 0 {
+        tracing::debug!("Pruned {pruned_count} expired refresh tokens");
+    }
+
+    issue_login_tokens(username, family_id, refresh_token, database_adapter).await
+}
 
-    let mut header = Header::new(Algorithm::RS256);
-    header.kid = Some("test".to_string()); // Change is multiple keys comes in
+/// Exchanges refresh token for new pair, presented token becomes unusable.
+/// Presenting already exchanged token means it leaked, so all tokens of that login are dropped.
+pub async fn refresh_tokens(
+    username: String,
+    refresh_token: RefreshToken,
+    database_adapter: Arc<dyn DatabaseAdapter>,
+) -> DatabaseAdapterResult<LoginTokens> {
+    let refresh_token_hash = hash_refresh_token(&refresh_token);
+    let current_refresh_token = database_adapter.get_refresh_token(&refresh_token_hash).await?;
+    if current_refresh_token.username != username {
+        return Err(DatabaseAdapterError::RefreshTokenNotFound);
+    }
+    if current_refresh_token.expires_at < Utc::now().timestamp() as u64 {
+        return Err(DatabaseAdapterError::RefreshTokenExpired);
+    }
+
+    let family_id = current_refresh_token.family_id;
+    let new_refresh_token = generate_token_id();
+    let new_refresh_token_data = new_refresh_token_data(username.clone(), family_id.clone(), &new_refresh_token);
+    match database_adapter.rotate_refresh_token(&refresh_token_hash, new_refresh_token_data).await {
+        Err(DatabaseAdapterError::RefreshTokenReused) => {
+            tracing::warn!("Refresh token of account '{username}' reused, dropping its family");
+            database_adapter.remove_refresh_token_family(&family_id).await?;
+            return Err(DatabaseAdapterError::RefreshTokenReused);
+        }
+        result => result?,
+    }
+
+    issue_login_tokens(username, family_id, new_refresh_token, database_adapter).await
+}
+
+/// Only hash is stored, so tokens read from database can't be used
+fn hash_refresh_token(refresh_token: &str) -> String {
+    digest(&SHA256, refresh_token.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn new_refresh_token_data(username: String, family_id: String, refresh_token: &str) -> RefreshTokenData {
+    let expires_at = Utc::now() + Duration::days(REFRESH_TOKEN_EXPIRATION_DAYS);
+    RefreshTokenData {
+        token: hash_refresh_token(refresh_token),
+        username,
+        family_id,
+        expires_at: expires_at.timestamp() as u64,
+        rotated: false,
+    }
+}
+
+async fn issue_login_tokens(
+    username: String,
+    family_id: String,
+    refresh_token: RefreshToken,
+    database_adapter: Arc<dyn DatabaseAdapter>,
+) -> DatabaseAdapterResult<LoginTokens> {
+    let signing_key = jwt_keys::get_signing_key(&database_adapter).await?;
+
+    let mut header = Header::new(signing_key.algorithm);
+    header.kid = Some(signing_key.key_id);
 
     let utc_now = Utc::now();
-    let exp = utc_now + Duration::hours(JWT_EXPIRATION_HOURS);
+    let exp = utc_now + Duration::minutes(JWT_EXPIRATION_MINUTES);
 
     let claims = AccountManagerClaims {
         iss: username,
         iat: utc_now.timestamp() as u64,
         aud: SERVICE_AUDIENCE.to_string(),
         exp: exp.timestamp() as u64,
+        jti: generate_token_id(),
+        sid: family_id,
     };
 
-    let token = encode::<AccountManagerClaims>(&header, &claims, &key).unwrap();
-    Ok(token)
+    let access_token = encode::<AccountManagerClaims>(&header, &claims, &signing_key.encoding_key)
+        .map_err(|e| DatabaseAdapterError::JwtError(e.to_string()))?;
+    Ok(LoginTokens {
+        access_token,
+        access_token_expires_at: claims.exp,
+        refresh_token,
+    })
 }
 
+/// Random, so revoking one token does not affect others issued in the same second
+pub(crate) fn generate_token_id() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Token stays revoked until its own expiration, revocations expired by then are dropped.
+/// Refresh tokens of the same login are dropped too, other logins of account stay valid.
+pub async fn logout_account(
+    claims: AccountManagerClaims,
+    database_adapter: Arc<dyn DatabaseAdapter>,
+) -> DatabaseAdapterResult<()> {
+    database_adapter.revoke_token(&claims.jti, claims.exp).await?;
+
+    if claims.sid.is_empty() {
+        // Token doesn't tell its login, so none of them can be prolonged
+        database_adapter.remove_refresh_tokens_of_account(&claims.iss).await?;
+    } else {
+        database_adapter.remove_refresh_token_family(&claims.sid).await?;
+    }
+
+    let pruned_count = database_adapter.prune_revoked_tokens(Utc::now().timestamp() as u64).await?;
+    if pruned_count > 0 {
+        tracing::debug!("Pruned {pruned_count} expired revoked tokens");
+    }
+    Ok(())
+}
 
 pub async fn delete_account(
     username: String,
@@ -75,6 +183,9 @@ pub async fn update_account_password(
     database_adapter: Arc<dyn DatabaseAdapter>,
 ) -> DatabaseAdapterResult<()> {
     database_adapter.change_password(&username, &password_old, &password_new).await?;
+
+    // Sessions started with old password cannot be prolonged
+    database_adapter.remove_refresh_tokens_of_account(&username).await?;
     Ok(())
 }
 
@@ -101,14 +212,14 @@ pub async fn create_character_for_account(
         speed: 1.0,
     };
 
-    let new_character_id = database_adapter.add_character(new_character).await?;
-    database_adapter.attach_character_to_account(&username, new_character_id).await?;
+    let new_character_id = database_adapter.create_character_for_account(&username, new_character).await?;
     Ok(new_character_id)
 }
 
 #[cfg(test)]
 mod tests {
     use database_adapter::DatabaseAdapterError;
+    use axum_jwt_auth::JwtDecoder;
     use database_adapter::test::DatabaseTestAdapter;
     use super::*;
 
@@ -187,6 +298,64 @@ mod tests {
         assert_eq!(bad_username_login_result, Err(DatabaseAdapterError::UsernameNotFound));
     }
 
+    #[tokio::test]
+    async fn test_refreshing_tokens() {
+        let database_adapter = Arc::new(DatabaseTestAdapter::new().await);
+        create_account("User1".to_string(), "Password1".to_string(), database_adapter.clone()).await.unwrap();
+        create_account("User2".to_string(), "Password2".to_string(), database_adapter.clone()).await.unwrap();
+
+        let login_tokens = login_to_account("User1".to_string(), "Password1".to_string(), database_adapter.clone())
+            .await
+            .unwrap();
+        assert!(login_tokens.access_token_expires_at > Utc::now().timestamp() as u64);
+
+        // Token is bound to account
+        let other_account_result = refresh_tokens("User2".to_string(), login_tokens.refresh_token.clone(), database_adapter.clone()).await;
+        assert_eq!(other_account_result, Err(DatabaseAdapterError::RefreshTokenNotFound));
+
+        let refreshed_tokens = refresh_tokens("User1".to_string(), login_tokens.refresh_token.clone(), database_adapter.clone())
+            .await
+            .unwrap();
+        assert_ne!(refreshed_tokens.refresh_token, login_tokens.refresh_token);
+        assert_ne!(refreshed_tokens.access_token, login_tokens.access_token);
+
+        // Reusing rotated token drops whole family, including its successor
+        let reuse_result = refresh_tokens("User1".to_string(), login_tokens.refresh_token.clone(), database_adapter.clone()).await;
+        assert_eq!(reuse_result, Err(DatabaseAdapterError::RefreshTokenReused));
+        let successor_result = refresh_tokens("User1".to_string(), refreshed_tokens.refresh_token, database_adapter.clone()).await;
+        assert_eq!(successor_result, Err(DatabaseAdapterError::RefreshTokenNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_refreshing_expired_token() {
+        let database_adapter = Arc::new(DatabaseTestAdapter::new().await);
+        create_account("User1".to_string(), "Password1".to_string(), database_adapter.clone()).await.unwrap();
+
+        let refresh_token = generate_token_id();
+        let mut refresh_token_data = new_refresh_token_data("User1".to_string(), generate_token_id(), &refresh_token);
+        refresh_token_data.expires_at = Utc::now().timestamp() as u64 - 1;
+        database_adapter.add_refresh_token(refresh_token_data).await.unwrap();
+
+        let result = refresh_tokens("User1".to_string(), refresh_token, database_adapter.clone()).await;
+        assert_eq!(result, Err(DatabaseAdapterError::RefreshTokenExpired));
+    }
+
+    #[tokio::test]
+    async fn test_changing_password_invalidates_refresh_tokens() {
+        let database_adapter = Arc::new(DatabaseTestAdapter::new().await);
+        create_account("User1".to_string(), "Password1".to_string(), database_adapter.clone()).await.unwrap();
+        let login_tokens = login_to_account("User1".to_string(), "Password1".to_string(), database_adapter.clone())
+            .await
+            .unwrap();
+
+        update_account_password("User1".to_string(), "Password1".to_string(), "Password2".to_string(), database_adapter.clone())
+            .await
+            .unwrap();
+
+        let result = refresh_tokens("User1".to_string(), login_tokens.refresh_token, database_adapter.clone()).await;
+        assert_eq!(result, Err(DatabaseAdapterError::RefreshTokenNotFound));
+    }
+
     #[tokio::test]
     async fn test_delete_account() {
         // Deleting account assume user is verified
@@ -221,4 +390,80 @@ mod tests {
         );
         assert_eq!(database_adapter.get_accounts_count().await.unwrap(), 1);
     }
+
+    #[tokio::test]
+    async fn test_create_character_for_not_existing_account() {
+        let database_adapter = Arc::new(DatabaseTestAdapter::new().await);
+
+        let err_user_not_found = create_character_for_account(
+            "User1".to_string(),
+            "Janusz".to_string(),
+            database_adapter.clone(),
+        ).await
+        .unwrap_err();
+
+        assert_eq!(err_user_not_found, DatabaseAdapterError::UsernameNotFound);
+        // No orphaned character left
+        assert!(database_adapter.get_characters().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_logout_revokes_token() {
+        let database_adapter = Arc::new(DatabaseTestAdapter::new().await);
+        let claims = AccountManagerClaims {
+            iss: "User1".to_string(),
+            iat: 0,
+            aud: SERVICE_AUDIENCE.to_string(),
+            exp: Utc::now().timestamp() as u64 + 60,
+            jti: generate_token_id(),
+            sid: generate_token_id(),
+        };
+        let expired_jti = generate_token_id();
+        database_adapter.revoke_token(&expired_jti, 1).await.unwrap();
+
+        logout_account(claims.clone(), database_adapter.clone()).await.unwrap();
+        assert!(database_adapter.is_token_revoked(&claims.jti).await.unwrap());
+
+        // Expired revocation got pruned on the way
+        assert!(!database_adapter.is_token_revoked(&expired_jti).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_logout_drops_refresh_tokens_of_login() {
+        let database_adapter = Arc::new(DatabaseTestAdapter::new().await);
+        create_account("User1".to_string(), "Password1".to_string(), database_adapter.clone()).await.unwrap();
+        let login_tokens = login_to_account("User1".to_string(), "Password1".to_string(), database_adapter.clone())
+            .await
+            .unwrap();
+        let other_login_tokens = login_to_account("User1".to_string(), "Password1".to_string(), database_adapter.clone())
+            .await
+            .unwrap();
+
+        let claims = jwt_keys::JwtKeys::load(database_adapter.clone()).await.unwrap()
+            .decode(&login_tokens.access_token).await.unwrap()
+            .claims;
+        logout_account(claims, database_adapter.clone()).await.unwrap();
+
+        let result = refresh_tokens("User1".to_string(), login_tokens.refresh_token, database_adapter.clone()).await;
+        assert_eq!(result, Err(DatabaseAdapterError::RefreshTokenNotFound));
+        refresh_tokens("User1".to_string(), other_login_tokens.refresh_token, database_adapter.clone()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_storing_only_hashes_of_refresh_tokens() {
+        let database_adapter = Arc::new(DatabaseTestAdapter::new().await);
+        create_account("User1".to_string(), "Password1".to_string(), database_adapter.clone()).await.unwrap();
+        let login_tokens = login_to_account("User1".to_string(), "Password1".to_string(), database_adapter.clone())
+            .await
+            .unwrap();
+
+        let refresh_token_hash = hash_refresh_token(&login_tokens.refresh_token);
+        assert_ne!(refresh_token_hash, login_tokens.refresh_token);
+        assert_eq!(database_adapter.get_refresh_token(&login_tokens.refresh_token).await, Err(DatabaseAdapterError::RefreshTokenNotFound));
+        assert_eq!(database_adapter.get_refresh_token(&refresh_token_hash).await.unwrap().username, "User1");
+
+        // Value read from database is not accepted as token
+        let result = refresh_tokens("User1".to_string(), refresh_token_hash, database_adapter.clone()).await;
+        assert_eq!(result, Err(DatabaseAdapterError::RefreshTokenNotFound));
+    }
 }
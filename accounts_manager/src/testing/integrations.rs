@@ -3,11 +3,15 @@ mod tests {
     use crate::client::{AccountsManagerClient, AccountsManagerClientError};
     use crate::testing::tests_trace_setup;
     use crate::AccountsManagerServer;
+    use crate::admin::{run_admin_command, AdminCommand};
     use std::future::Future;
     use std::sync::Arc;
     use database_adapter::DatabaseAdapterError;
     use database_adapter::test::DatabaseTestAdapter;
     use crate::responses::ApiError;
+    use jsonwebtoken::{DecodingKey, Validation};
+    use crate::app_data::AccountManagerClaims;
+    use crate::SERVICE_AUDIENCE;
 
     #[tokio::test]
     async fn test_client_connecting_to_server() {
@@ -69,7 +73,7 @@ mod tests {
             client.request_create_account(username.clone(), password.clone() ).await.unwrap();
 
             let token = client.request_login_to_account(username.clone(), password.clone()).await
-                .unwrap();
+                .unwrap().access_token;
 
             let _ = client.request_account_details(username.clone(), &token).await
                 .unwrap();;
@@ -107,7 +111,7 @@ mod tests {
             client.request_create_account(username.clone(), password.clone() ).await.unwrap();
 
             let token = client.request_login_to_account(username.clone(), password.clone()).await
-                .unwrap();
+                .unwrap().access_token;
 
             let _ = client.request_account_details(username.clone(), &token).await.unwrap();
 
@@ -122,6 +126,102 @@ mod tests {
         .await;
     }
 
+    #[tokio::test]
+    async fn test_tokens_stay_valid_across_jwt_key_rotation() {
+        tests_trace_setup();
+        let database_adapter = Arc::new(DatabaseTestAdapter::new().await);
+        let server = AccountsManagerServer::run(database_adapter).await.unwrap();
+        let client = AccountsManagerClient::new(&server.get_address().to_string()).unwrap();
+
+        let username = "User1".to_string();
+        let password = "Password1234%^&".to_string();
+        client.request_create_account(username.clone(), password.clone()).await.unwrap();
+
+        let old_token = client.request_login_to_account(username.clone(), password.clone()).await.unwrap().access_token;
+        let new_key_id = server.rotate_jwt_keys().await.unwrap();
+        let new_token = client.request_login_to_account(username.clone(), password.clone()).await.unwrap().access_token;
+
+        let old_key_id = jsonwebtoken::decode_header(&old_token).unwrap().kid.unwrap();
+        assert_ne!(old_key_id, new_key_id);
+        assert_eq!(jsonwebtoken::decode_header(&new_token).unwrap().kid.unwrap(), new_key_id);
+
+        client.request_account_details(username.clone(), &old_token).await.unwrap();
+        client.request_account_details(username.clone(), &new_token).await.unwrap();
+
+        // Other services verify tokens with published keys only
+        let jwks = client.request_jwks().await.unwrap();
+        for token in [&old_token, &new_token] {
+            let header = jsonwebtoken::decode_header(token).unwrap();
+            let jwk = jwks.find(&header.kid.unwrap()).unwrap();
+            let mut validation = Validation::new(header.alg);
+            validation.set_audience(&[SERVICE_AUDIENCE]);
+            let token_data = jsonwebtoken::decode::<AccountManagerClaims>(token, &DecodingKey::from_jwk(jwk).unwrap(), &validation).unwrap();
+            assert_eq!(token_data.claims.iss, username);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rotating_jwt_keys_with_admin_command() {
+        tests_trace_setup();
+        let database_adapter = Arc::new(DatabaseTestAdapter::new().await);
+        let server = AccountsManagerServer::run(database_adapter.clone()).await.unwrap();
+        let client = AccountsManagerClient::new(&server.get_address().to_string()).unwrap();
+
+        let username = "User1".to_string();
+        let password = "Password1234%^&".to_string();
+        client.request_create_account(username.clone(), password.clone()).await.unwrap();
+        let old_token = client.request_login_to_account(username.clone(), password.clone()).await.unwrap().access_token;
+
+        // Run by separate process sharing the database, server is not told about it
+        let report = run_admin_command(&AdminCommand::RotateJwtKeys, database_adapter.clone()).await.unwrap();
+        let new_token = client.request_login_to_account(username.clone(), password.clone()).await.unwrap().access_token;
+
+        let new_key_id = jsonwebtoken::decode_header(&new_token).unwrap().kid.unwrap();
+        assert_ne!(jsonwebtoken::decode_header(&old_token).unwrap().kid.unwrap(), new_key_id);
+        assert!(report.contains(&new_key_id), "{report}");
+
+        client.request_account_details(username.clone(), &old_token).await.unwrap();
+        client.request_account_details(username.clone(), &new_token).await.unwrap();
+        assert!(client.request_jwks().await.unwrap().find(&new_key_id).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_using_token_after_logout_should_fail() {
+        tests_trace_setup();
+
+        setup_server_client_interaction(|client| async move {
+            let username = "User1".to_string();
+            let password = "Password1234%^&".to_string();
+            client.request_create_account(username.clone(), password.clone()).await.unwrap();
+
+            let login_tokens = client.request_login_to_account(username.clone(), password.clone()).await.unwrap();
+            let token = login_tokens.access_token;
+            let other_token = client.request_login_to_account(username.clone(), password.clone()).await.unwrap().access_token;
+
+            client.request_logout_account(username.clone(), token.clone()).await.unwrap();
+
+            // Session can't be prolonged either
+            let refresh_result = client.request_refresh_tokens(username.clone(), login_tokens.refresh_token).await;
+            assert!(matches!(refresh_result, Err(AccountsManagerClientError::ApiError(_))), "{refresh_result:?}");
+
+            // Logged out token is rejected everywhere
+            let details_result = client.request_account_details(username.clone(), &token).await;
+            assert!(matches!(details_result, Err(AccountsManagerClientError::Unauthorized)));
+            let characters_result = client.request_account_characters(username.clone(), &token).await;
+            assert!(matches!(characters_result, Err(AccountsManagerClientError::Unauthorized)));
+            let logout_result = client.request_logout_account(username.clone(), token.clone()).await;
+            assert!(matches!(logout_result, Err(AccountsManagerClientError::Unauthorized)));
+
+            // Other sessions are not affected
+            client.request_account_details(username.clone(), &other_token).await.unwrap();
+
+            // Logging in again gives working token
+            let token = client.request_login_to_account(username.clone(), password.clone()).await.unwrap().access_token;
+            client.request_account_details(username.clone(), &token).await.unwrap();
+        })
+        .await;
+    }
+
     #[tokio::test]
     async fn test_creating_account_should_not_create_already_existing_account() {
         tests_trace_setup();
@@ -149,7 +249,8 @@ mod tests {
                     ApiError::DatabaseAdapterError(acc_err) => match acc_err {
                         DatabaseAdapterError::UsernameAlreadyExists =>  (),
                         err => panic!("Unexpected error: {:?} should give 'DatabaseAdapterError::UsernameAlreadyExists'", err),
-                    }
+                    },
+                    err => panic!("Unexpected error: {:?} should give 'ApiError::DatabaseAdapterError'", err),
                 },
                 _ => panic!("Should give response 'ApiError', got instead {response:?}"),
             }
@@ -180,7 +281,7 @@ mod tests {
             assert_eq!(response.accounts_count, 1);
 
             let token = client.request_login_to_account(username.to_string(), password.to_string()).await
-                .unwrap();
+                .unwrap().access_token;
 
             // Delete account with correct password
             client.request_delete_account(username.to_string(), token)
@@ -213,7 +314,7 @@ mod tests {
             assert_eq!(response.accounts_count, 1);
 
             let _token = client.request_login_to_account(username.to_string(), password.to_string()).await
-                .unwrap();
+                .unwrap().access_token;
 
             // Delete account with bad password
             let deletion_error = client.request_delete_account(username.to_string(), "bad_token1234".to_string())
@@ -244,16 +345,16 @@ mod tests {
                 .unwrap();
 
             let token = client.request_login_to_account(username.to_string(), password.to_string()).await
-                .unwrap();
+                .unwrap().access_token;
 
             let response = client.get_server_status().await.unwrap();
             assert_eq!(response.accounts_count, 1);
 
-            // Delete account with bad username
+            // Delete account with bad username, token does not belong to it
             let deletion_error = client.request_delete_account("Noname105".to_string(), token)
                 .await
                 .unwrap_err();
-            assert!(matches!(deletion_error, AccountsManagerClientError::ApiError(ApiError::DatabaseAdapterError(DatabaseAdapterError::UsernameNotFound))));
+            assert!(matches!(deletion_error, AccountsManagerClientError::ApiError(ApiError::AccountAccessForbidden)));
 
             let response = client.get_server_status().await.unwrap();
             assert_eq!(response.accounts_count, 1); // Count remains the same
@@ -273,7 +374,7 @@ mod tests {
 
             let token = client.request_login_to_account(username.to_string(), password_old.to_string())
                 .await
-                .unwrap();
+                .unwrap().access_token;
             
             // Change password with correct old password
             let password_new = "Password1234%^&111111111";
@@ -313,7 +414,7 @@ mod tests {
 
             // Login
             let token = client.request_login_to_account(username.to_string(), password.to_string()).await
-                .unwrap();
+                .unwrap().access_token;
 
             // Get all  - initially zero
             let characters_data = client.request_account_characters(username.to_string(), &token)
@@ -337,4 +438,92 @@ mod tests {
             assert_eq!(the_one_character.id, new_character_id);
         }).await;
     }
+
+    /// Creates two accounts and returns token of the first one
+    async fn create_two_accounts_login_first(client: &AccountsManagerClient) -> String {
+        client.request_create_account("User1".to_string(), "Password1".to_string()).await.unwrap();
+        client.request_create_account("User2".to_string(), "Password2".to_string()).await.unwrap();
+        client.request_login_to_account("User1".to_string(), "Password1".to_string()).await.unwrap().access_token
+    }
+
+    fn is_forbidden<T>(result: Result<T, AccountsManagerClientError>) -> bool {
+        matches!(result, Err(AccountsManagerClientError::ApiError(ApiError::AccountAccessForbidden)))
+    }
+
+    #[tokio::test]
+    async fn test_accessing_other_account_details_should_fail() {
+        tests_trace_setup();
+
+        setup_server_client_interaction(|client| async move {
+            let token = create_two_accounts_login_first(&client).await;
+
+            assert!(is_forbidden(client.request_account_details("User2".to_string(), &token).await));
+            assert!(is_forbidden(client.request_account_characters("User2".to_string(), &token).await));
+
+            // Own account is still accessible
+            client.request_account_details("User1".to_string(), &token).await.unwrap();
+        }).await;
+    }
+
+    #[tokio::test]
+    async fn test_deleting_other_account_should_fail() {
+        tests_trace_setup();
+
+        setup_server_client_interaction(|client| async move {
+            let token = create_two_accounts_login_first(&client).await;
+
+            assert!(is_forbidden(client.request_delete_account("User2".to_string(), token.clone()).await));
+
+            let response = client.get_server_status().await.unwrap();
+            assert_eq!(response.accounts_count, 2); // Count remains the same
+        }).await;
+    }
+
+    #[tokio::test]
+    async fn test_changing_password_of_other_account_should_fail() {
+        tests_trace_setup();
+
+        setup_server_client_interaction(|client| async move {
+            let token = create_two_accounts_login_first(&client).await;
+
+            let result = client.request_update_account_password(
+                "User2".to_string(),
+                "Password2".to_string(),
+                "Hijacked".to_string(),
+                &token
+            ).await;
+            assert!(is_forbidden(result));
+
+            // Password remains the same
+            client.request_login_to_account("User2".to_string(), "Password2".to_string()).await.unwrap();
+        }).await;
+    }
+
+    #[tokio::test]
+    async fn test_creating_character_for_other_account_should_fail() {
+        tests_trace_setup();
+
+        setup_server_client_interaction(|client| async move {
+            let token = create_two_accounts_login_first(&client).await;
+
+            let result = client.request_create_character("User2".to_string(), "Intruder".to_string(), &token).await;
+            assert!(is_forbidden(result));
+
+            // No character was created
+            let token_user2 = client.request_login_to_account("User2".to_string(), "Password2".to_string()).await.unwrap().access_token;
+            let characters_data = client.request_account_characters("User2".to_string(), &token_user2).await.unwrap();
+            assert!(characters_data.is_empty());
+        }).await;
+    }
+
+    #[tokio::test]
+    async fn test_logging_out_other_account_should_fail() {
+        tests_trace_setup();
+
+        setup_server_client_interaction(|client| async move {
+            let token = create_two_accounts_login_first(&client).await;
+
+            assert!(is_forbidden(client.request_logout_account("User2".to_string(), token).await));
+        }).await;
+    }
 }
@@ -0,0 +1,529 @@
+//! Behaviour every `DatabaseAdapter` implementation must follow.
+//!
+//! `DatabaseTestAdapter` is the reference, new backends should pass the same checks before deploying them:
+//! ```ignore
+//! #[tokio::test]
+//! async fn test_conformance() {
+//!     run_conformance(Arc::new(MyAdapter::new().await)).await;
+//! }
+//! ```
+//! Checks panic on mismatch. They do not assume an empty adapter, so all of them can share one instance.
+
+use std::sync::Arc;
+use crate::{AccountData, DatabaseAdapter, DatabaseAdapterError};
+use crate::character::{CharacterId, NewCharacterData};
+use crate::jwt_key::JwtKeyData;
+use crate::refresh_token::RefreshTokenData;
+
+const PASSWORD: &str = "Password12345!@#";
+
+pub async fn run_conformance(adapter: Arc<dyn DatabaseAdapter>) {
+    check_adding_accounts(adapter.clone()).await;
+    check_removing_accounts(adapter.clone()).await;
+    check_changing_password(adapter.clone()).await;
+    check_adding_characters(adapter.clone()).await;
+    check_removing_characters(adapter.clone()).await;
+    check_updating_characters(adapter.clone()).await;
+    check_attaching_characters(adapter.clone()).await;
+    check_detaching_characters(adapter.clone()).await;
+    check_creating_character_for_account(adapter.clone()).await;
+    check_removing_account_keeps_characters(adapter.clone()).await;
+    check_revoking_tokens(adapter.clone()).await;
+    check_refresh_tokens(adapter.clone()).await;
+    check_rotating_refresh_tokens(adapter.clone()).await;
+    check_rotating_jwt_keys(adapter.clone()).await;
+}
+
+fn new_character_data(name: &str) -> NewCharacterData {
+    NewCharacterData {
+        name: name.to_string(),
+        position_x: 1.5,
+        position_y: -2.0,
+        speed: 1.25,
+    }
+}
+
+async fn add_account(adapter: &Arc<dyn DatabaseAdapter>, username: &str) {
+    adapter
+        .add_account(AccountData::new(username.to_string(), PASSWORD).unwrap())
+        .await
+        .unwrap();
+}
+
+pub async fn check_adding_accounts(adapter: Arc<dyn DatabaseAdapter>) {
+    let initial_count = adapter.get_accounts_count().await.unwrap();
+
+    add_account(&adapter, "Conformance_Add1").await;
+    add_account(&adapter, "Conformance_Add2").await;
+    assert_eq!(adapter.get_accounts_count().await.unwrap(), initial_count + 2);
+
+    let result = adapter
+        .add_account(AccountData::new("Conformance_Add1".to_string(), "Other").unwrap())
+        .await;
+    assert_eq!(result, Err(DatabaseAdapterError::UsernameAlreadyExists));
+    assert_eq!(adapter.get_accounts_count().await.unwrap(), initial_count + 2);
+
+    // Password of already existing account was not overwritten
+    assert!(adapter.is_password_matching("Conformance_Add1", PASSWORD).await.unwrap());
+
+    let account = adapter.get_account_by_name("Conformance_Add2").await.unwrap();
+    assert_eq!(account.username, "Conformance_Add2");
+    assert!(account.characters.is_empty());
+
+    let accounts = adapter.get_accounts().await.unwrap();
+    assert_eq!(accounts.len(), initial_count + 2);
+    assert!(accounts.iter().any(|account| account.username == "Conformance_Add1"));
+
+    assert_eq!(
+        adapter.get_account_by_name("Conformance_Unknown").await,
+        Err(DatabaseAdapterError::UsernameNotFound)
+    );
+    assert_eq!(
+        adapter.is_password_matching("Conformance_Unknown", PASSWORD).await,
+        Err(DatabaseAdapterError::UsernameNotFound)
+    );
+}
+
+pub async fn check_removing_accounts(adapter: Arc<dyn DatabaseAdapter>) {
+    add_account(&adapter, "Conformance_Remove").await;
+    let count = adapter.get_accounts_count().await.unwrap();
+
+    assert_eq!(
+        adapter.remove_account_with_username("Conformance_Unknown").await,
+        Err(DatabaseAdapterError::UsernameNotFound)
+    );
+    assert_eq!(adapter.get_accounts_count().await.unwrap(), count);
+
+    adapter.remove_account_with_username("Conformance_Remove").await.unwrap();
+    assert_eq!(adapter.get_accounts_count().await.unwrap(), count - 1);
+    assert_eq!(
+        adapter.get_account_by_name("Conformance_Remove").await,
+        Err(DatabaseAdapterError::UsernameNotFound)
+    );
+    assert_eq!(
+        adapter.remove_account_with_username("Conformance_Remove").await,
+        Err(DatabaseAdapterError::UsernameNotFound)
+    );
+
+    // Username can be used again
+    add_account(&adapter, "Conformance_Remove").await;
+}
+
+pub async fn check_changing_password(adapter: Arc<dyn DatabaseAdapter>) {
+    add_account(&adapter, "Conformance_Password").await;
+
+    adapter
+        .change_password("Conformance_Password", PASSWORD, "NewPassword")
+        .await
+        .unwrap();
+    assert!(adapter.is_password_matching("Conformance_Password", "NewPassword").await.unwrap());
+    assert!(!adapter.is_password_matching("Conformance_Password", PASSWORD).await.unwrap());
+
+    // Old password no longer valid
+    assert_eq!(
+        adapter.change_password("Conformance_Password", PASSWORD, "NewerPassword").await,
+        Err(DatabaseAdapterError::BadPassword)
+    );
+    assert!(adapter.is_password_matching("Conformance_Password", "NewPassword").await.unwrap());
+
+    assert_eq!(
+        adapter.change_password("Conformance_Unknown", PASSWORD, "NewPassword").await,
+        Err(DatabaseAdapterError::UsernameNotFound)
+    );
+
+    // Account data remains the same
+    let character_id = adapter.add_character(new_character_data("Conformance_Password")).await.unwrap();
+    adapter.attach_character_to_account("Conformance_Password", character_id).await.unwrap();
+    adapter
+        .change_password("Conformance_Password", "NewPassword", "NewerPassword")
+        .await
+        .unwrap();
+    assert_eq!(
+        adapter.get_characters_of_account("Conformance_Password").await.unwrap(),
+        vec![character_id]
+    );
+}
+
+pub async fn check_adding_characters(adapter: Arc<dyn DatabaseAdapter>) {
+    let initial_count = adapter.get_characters().await.unwrap().len();
+
+    let character_ids: Vec<CharacterId> = {
+        let mut ids = Vec::new();
+        for name in ["Conformance_A", "Conformance_B", "Conformance_C"] {
+            ids.push(adapter.add_character(new_character_data(name)).await.unwrap());
+        }
+        ids
+    };
+
+    // IDs are assigned in increasing order
+    assert!(character_ids.windows(2).all(|ids| ids[0] < ids[1]), "IDs {character_ids:?}");
+    assert_eq!(adapter.get_characters().await.unwrap().len(), initial_count + 3);
+
+    let character = adapter.get_character_by_id(character_ids[1]).await.unwrap();
+    assert_eq!(character.id, character_ids[1]);
+    assert_eq!(character.name, "Conformance_B");
+    assert_eq!(character.position_x, 1.5);
+    assert_eq!(character.position_y, -2.0);
+    assert_eq!(character.speed, 1.25);
+
+    // Fresh character is not attached
+    assert_eq!(adapter.get_account_of_character(character_ids[0]).await.unwrap(), None);
+
+    let unknown_character_id = character_ids[2] + 1000;
+    assert_eq!(
+        adapter.get_character_by_id(unknown_character_id).await,
+        Err(DatabaseAdapterError::CharacterIdNotFound)
+    );
+}
+
+pub async fn check_removing_characters(adapter: Arc<dyn DatabaseAdapter>) {
+    let character_id = adapter.add_character(new_character_data("Conformance_Removed")).await.unwrap();
+    let count = adapter.get_characters().await.unwrap().len();
+
+    adapter.remove_character_with_id(character_id).await.unwrap();
+    assert_eq!(adapter.get_characters().await.unwrap().len(), count - 1);
+    assert_eq!(
+        adapter.get_character_by_id(character_id).await,
+        Err(DatabaseAdapterError::CharacterIdNotFound)
+    );
+    assert_eq!(
+        adapter.remove_character_with_id(character_id).await,
+        Err(DatabaseAdapterError::CharacterIdNotFound)
+    );
+
+    // IDs of removed characters are never reused
+    let next_character_id = adapter.add_character(new_character_data("Conformance_Next")).await.unwrap();
+    assert!(next_character_id > character_id);
+}
+
+pub async fn check_updating_characters(adapter: Arc<dyn DatabaseAdapter>) {
+    let character_id = adapter.add_character(new_character_data("Conformance_Updated")).await.unwrap();
+    let count = adapter.get_characters().await.unwrap().len();
+
+    let mut character = adapter.get_character_by_id(character_id).await.unwrap();
+    character.position_x = 10.0;
+    character.position_y = -7.5;
+    character.speed = 2.0;
+    adapter.update_character(character).await.unwrap();
+
+    let character = adapter.get_character_by_id(character_id).await.unwrap();
+    assert_eq!(character.name, "Conformance_Updated");
+    assert_eq!(character.position_x, 10.0);
+    assert_eq!(character.position_y, -7.5);
+    assert_eq!(character.speed, 2.0);
+    assert_eq!(adapter.get_characters().await.unwrap().len(), count);
+
+    // Update never creates character
+    let mut unknown_character = character.clone();
+    unknown_character.id = character_id + 1000;
+    assert_eq!(
+        adapter.update_character(unknown_character).await,
+        Err(DatabaseAdapterError::CharacterIdNotFound)
+    );
+    assert_eq!(
+        adapter.get_character_by_id(character_id + 1000).await,
+        Err(DatabaseAdapterError::CharacterIdNotFound)
+    );
+}
+
+pub async fn check_attaching_characters(adapter: Arc<dyn DatabaseAdapter>) {
+    add_account(&adapter, "Conformance_Owner").await;
+    add_account(&adapter, "Conformance_Other").await;
+    let character_1 = adapter.add_character(new_character_data("Conformance_1")).await.unwrap();
+    let character_2 = adapter.add_character(new_character_data("Conformance_2")).await.unwrap();
+
+    assert_eq!(
+        adapter.attach_character_to_account("Conformance_Unknown", character_1).await,
+        Err(DatabaseAdapterError::UsernameNotFound)
+    );
+    assert_eq!(
+        adapter.attach_character_to_account("Conformance_Owner", character_2 + 1000).await,
+        Err(DatabaseAdapterError::CharacterIdNotFound)
+    );
+
+    // Attachment order is kept
+    adapter.attach_character_to_account("Conformance_Owner", character_2).await.unwrap();
+    adapter.attach_character_to_account("Conformance_Owner", character_1).await.unwrap();
+    assert_eq!(
+        adapter.get_characters_of_account("Conformance_Owner").await.unwrap(),
+        vec![character_2, character_1]
+    );
+    assert_eq!(
+        adapter.get_account_by_name("Conformance_Owner").await.unwrap().characters,
+        vec![character_2, character_1]
+    );
+    let characters_data = adapter.get_characters_data_of_account("Conformance_Owner").await.unwrap();
+    assert_eq!(
+        characters_data.iter().map(|data| data.name.as_str()).collect::<Vec<_>>(),
+        vec!["Conformance_2", "Conformance_1"]
+    );
+    assert_eq!(
+        adapter.get_account_of_character(character_1).await.unwrap(),
+        Some("Conformance_Owner".to_string())
+    );
+
+    assert_eq!(
+        adapter.attach_character_to_account("Conformance_Owner", character_1).await,
+        Err(DatabaseAdapterError::CharacterAlreadyAttached)
+    );
+    assert_eq!(
+        adapter.attach_character_to_account("Conformance_Other", character_1).await,
+        Err(DatabaseAdapterError::CharacterAlreadyAttached)
+    );
+    assert!(adapter.get_characters_of_account("Conformance_Other").await.unwrap().is_empty());
+
+    assert_eq!(
+        adapter.remove_character_with_id(character_1).await,
+        Err(DatabaseAdapterError::CannotRemoveCharacterAttachedToAccount)
+    );
+    assert!(adapter.get_character_by_id(character_1).await.is_ok());
+
+    assert_eq!(
+        adapter.get_characters_of_account("Conformance_Unknown").await,
+        Err(DatabaseAdapterError::UsernameNotFound)
+    );
+    assert_eq!(
+        adapter.get_characters_data_of_account("Conformance_Unknown").await,
+        Err(DatabaseAdapterError::UsernameNotFound)
+    );
+}
+
+pub async fn check_detaching_characters(adapter: Arc<dyn DatabaseAdapter>) {
+    add_account(&adapter, "Conformance_Detach").await;
+    add_account(&adapter, "Conformance_Thief").await;
+    let character_id = adapter.add_character(new_character_data("Conformance_Detached")).await.unwrap();
+
+    assert_eq!(
+        adapter.detach_character_from_account("Conformance_Detach", character_id).await,
+        Err(DatabaseAdapterError::CharacterNotAttached)
+    );
+    assert_eq!(
+        adapter.detach_character_from_account("Conformance_Detach", character_id + 1000).await,
+        Err(DatabaseAdapterError::CharacterIdNotFound)
+    );
+
+    adapter.attach_character_to_account("Conformance_Detach", character_id).await.unwrap();
+
+    assert_eq!(
+        adapter.detach_character_from_account("Conformance_Thief", character_id).await,
+        Err(DatabaseAdapterError::CharacterNotOwnedByAccount)
+    );
+    assert_eq!(
+        adapter.detach_character_from_account("Conformance_Unknown", character_id).await,
+        Err(DatabaseAdapterError::CharacterNotOwnedByAccount)
+    );
+    assert_eq!(
+        adapter.get_characters_of_account("Conformance_Detach").await.unwrap(),
+        vec![character_id]
+    );
+
+    adapter.detach_character_from_account("Conformance_Detach", character_id).await.unwrap();
+    assert!(adapter.get_characters_of_account("Conformance_Detach").await.unwrap().is_empty());
+    assert_eq!(adapter.get_account_of_character(character_id).await.unwrap(), None);
+
+    // Detached character can be removed or attached again
+    adapter.attach_character_to_account("Conformance_Thief", character_id).await.unwrap();
+    adapter.detach_character_from_account("Conformance_Thief", character_id).await.unwrap();
+    adapter.remove_character_with_id(character_id).await.unwrap();
+}
+
+pub async fn check_creating_character_for_account(adapter: Arc<dyn DatabaseAdapter>) {
+    add_account(&adapter, "Conformance_Creator").await;
+    let characters_count = adapter.get_characters().await.unwrap().len();
+
+    let character_id = adapter
+        .create_character_for_account("Conformance_Creator", new_character_data("Conformance_Created"))
+        .await
+        .unwrap();
+    assert_eq!(adapter.get_characters().await.unwrap().len(), characters_count + 1);
+    assert_eq!(
+        adapter.get_characters_of_account("Conformance_Creator").await.unwrap(),
+        vec![character_id]
+    );
+    assert_eq!(
+        adapter.get_character_by_id(character_id).await.unwrap().name,
+        "Conformance_Created"
+    );
+
+    // Failed attach leaves no trace, not even consumed ID
+    assert_eq!(
+        adapter.create_character_for_account("Conformance_Unknown", new_character_data("Conformance_Lost")).await,
+        Err(DatabaseAdapterError::UsernameNotFound)
+    );
+    assert_eq!(adapter.get_characters().await.unwrap().len(), characters_count + 1);
+    let next_character_id = adapter.add_character(new_character_data("Conformance_Next")).await.unwrap();
+    assert_eq!(next_character_id, character_id + 1);
+}
+
+pub async fn check_removing_account_keeps_characters(adapter: Arc<dyn DatabaseAdapter>) {
+    add_account(&adapter, "Conformance_Leaving").await;
+    let character_id = adapter.add_character(new_character_data("Conformance_Orphan")).await.unwrap();
+    adapter.attach_character_to_account("Conformance_Leaving", character_id).await.unwrap();
+
+    adapter.remove_account_with_username("Conformance_Leaving").await.unwrap();
+
+    // Only attachment is broken
+    assert_eq!(adapter.get_account_of_character(character_id).await.unwrap(), None);
+    assert_eq!(adapter.get_character_by_id(character_id).await.unwrap().name, "Conformance_Orphan");
+
+    // Recreated account does not inherit characters
+    add_account(&adapter, "Conformance_Leaving").await;
+    assert!(adapter.get_characters_of_account("Conformance_Leaving").await.unwrap().is_empty());
+
+    adapter.remove_character_with_id(character_id).await.unwrap();
+}
+
+pub async fn check_revoking_tokens(adapter: Arc<dyn DatabaseAdapter>) {
+    assert!(!adapter.is_token_revoked("Conformance_Token1").await.unwrap());
+
+    adapter.revoke_token("Conformance_Token1", 1000).await.unwrap();
+    adapter.revoke_token("Conformance_Token2", 3000).await.unwrap();
+    assert!(adapter.is_token_revoked("Conformance_Token1").await.unwrap());
+    assert!(adapter.is_token_revoked("Conformance_Token2").await.unwrap());
+    assert!(!adapter.is_token_revoked("Conformance_Token3").await.unwrap());
+
+    // Revoking again is allowed and never shortens revocation
+    adapter.revoke_token("Conformance_Token1", 2000).await.unwrap();
+    adapter.revoke_token("Conformance_Token1", 500).await.unwrap();
+
+    adapter.prune_revoked_tokens(1500).await.unwrap();
+    assert!(adapter.is_token_revoked("Conformance_Token1").await.unwrap());
+
+    assert!(adapter.prune_revoked_tokens(2500).await.unwrap() >= 1);
+    assert!(!adapter.is_token_revoked("Conformance_Token1").await.unwrap());
+    assert!(adapter.is_token_revoked("Conformance_Token2").await.unwrap());
+
+    // Nothing expired left
+    assert_eq!(adapter.prune_revoked_tokens(2500).await.unwrap(), 0);
+}
+
+fn refresh_token_data(token: &str, username: &str, family_id: &str, expires_at: u64) -> RefreshTokenData {
+    RefreshTokenData {
+        token: token.to_string(),
+        username: username.to_string(),
+        family_id: family_id.to_string(),
+        expires_at,
+        rotated: false,
+    }
+}
+
+pub async fn check_refresh_tokens(adapter: Arc<dyn DatabaseAdapter>) {
+    add_account(&adapter, "Conformance_Refresh").await;
+
+    let refresh_token = refresh_token_data("Conformance_Refresh1", "Conformance_Refresh", "Conformance_Family1", 1000);
+    adapter.add_refresh_token(refresh_token.clone()).await.unwrap();
+    assert_eq!(adapter.get_refresh_token("Conformance_Refresh1").await.unwrap(), refresh_token);
+    assert_eq!(
+        adapter.get_refresh_token("Conformance_RefreshUnknown").await,
+        Err(DatabaseAdapterError::RefreshTokenNotFound)
+    );
+
+    // Token needs owner
+    assert_eq!(
+        adapter.add_refresh_token(refresh_token_data("Conformance_Refresh2", "Conformance_Unknown", "Conformance_Family2", 1000)).await,
+        Err(DatabaseAdapterError::UsernameNotFound)
+    );
+    assert_eq!(
+        adapter.get_refresh_token("Conformance_Refresh2").await,
+        Err(DatabaseAdapterError::RefreshTokenNotFound)
+    );
+
+    // Pruning drops only expired
+    adapter.add_refresh_token(refresh_token_data("Conformance_Refresh3", "Conformance_Refresh", "Conformance_Family3", 3000)).await.unwrap();
+    assert!(adapter.prune_refresh_tokens(2000).await.unwrap() >= 1);
+    assert_eq!(
+        adapter.get_refresh_token("Conformance_Refresh1").await,
+        Err(DatabaseAdapterError::RefreshTokenNotFound)
+    );
+    assert!(adapter.get_refresh_token("Conformance_Refresh3").await.is_ok());
+
+    // Removing all tokens of account
+    adapter.add_refresh_token(refresh_token_data("Conformance_Refresh4", "Conformance_Refresh", "Conformance_Family4", 3000)).await.unwrap();
+    assert_eq!(adapter.remove_refresh_tokens_of_account("Conformance_Refresh").await.unwrap(), 2);
+    assert_eq!(
+        adapter.get_refresh_token("Conformance_Refresh4").await,
+        Err(DatabaseAdapterError::RefreshTokenNotFound)
+    );
+
+    // Removing account removes its tokens
+    adapter.add_refresh_token(refresh_token_data("Conformance_Refresh5", "Conformance_Refresh", "Conformance_Family5", 3000)).await.unwrap();
+    adapter.remove_account_with_username("Conformance_Refresh").await.unwrap();
+    assert_eq!(
+        adapter.get_refresh_token("Conformance_Refresh5").await,
+        Err(DatabaseAdapterError::RefreshTokenNotFound)
+    );
+}
+
+pub async fn check_rotating_refresh_tokens(adapter: Arc<dyn DatabaseAdapter>) {
+    add_account(&adapter, "Conformance_Rotate").await;
+    let first = refresh_token_data("Conformance_Rotate1", "Conformance_Rotate", "Conformance_RotateFamily", 5000);
+    let second = refresh_token_data("Conformance_Rotate2", "Conformance_Rotate", "Conformance_RotateFamily", 5000);
+    let third = refresh_token_data("Conformance_Rotate3", "Conformance_Rotate", "Conformance_RotateFamily", 5000);
+    adapter.add_refresh_token(first.clone()).await.unwrap();
+
+    adapter.rotate_refresh_token(&first.token, second.clone()).await.unwrap();
+    assert!(adapter.get_refresh_token(&first.token).await.unwrap().rotated);
+    assert_eq!(adapter.get_refresh_token(&second.token).await.unwrap(), second);
+
+    // Rotated token cannot be rotated again, successor is not added
+    assert_eq!(
+        adapter.rotate_refresh_token(&first.token, third.clone()).await,
+        Err(DatabaseAdapterError::RefreshTokenReused)
+    );
+    assert_eq!(
+        adapter.get_refresh_token(&third.token).await,
+        Err(DatabaseAdapterError::RefreshTokenNotFound)
+    );
+
+    assert_eq!(
+        adapter.rotate_refresh_token("Conformance_RotateUnknown", third.clone()).await,
+        Err(DatabaseAdapterError::RefreshTokenNotFound)
+    );
+
+    // Whole family is removed at once
+    assert_eq!(adapter.remove_refresh_token_family("Conformance_RotateFamily").await.unwrap(), 2);
+    assert_eq!(
+        adapter.get_refresh_token(&second.token).await,
+        Err(DatabaseAdapterError::RefreshTokenNotFound)
+    );
+}
+
+fn jwt_key_data(key_id: &str, created_at: u64) -> JwtKeyData {
+    JwtKeyData {
+        key_id: key_id.to_string(),
+        algorithm: "EdDSA".to_string(),
+        private_key: vec![1, 2, 3],
+        public_key: vec![4, 5, 6],
+        created_at,
+        retired_at: None,
+    }
+}
+
+/// Should be run after other checks, it removes all keys present before
+pub async fn check_rotating_jwt_keys(adapter: Arc<dyn DatabaseAdapter>) {
+    let initial_count = adapter.get_jwt_keys().await.unwrap().len();
+
+    let first_key = jwt_key_data("Conformance_Key1", 1000);
+    let second_key = jwt_key_data("Conformance_Key2", 2000);
+    adapter.rotate_jwt_key(first_key.clone()).await.unwrap();
+    adapter.rotate_jwt_key(second_key.clone()).await.unwrap();
+
+    let jwt_keys = adapter.get_jwt_keys().await.unwrap();
+    assert_eq!(jwt_keys.len(), initial_count + 2);
+    assert!(jwt_keys[..initial_count].iter().all(|key| key.retired_at.is_some_and(|retired_at| retired_at <= 1000)));
+    assert_eq!(jwt_keys[initial_count], JwtKeyData { retired_at: Some(2000), ..first_key.clone() });
+    assert_eq!(jwt_keys[initial_count + 1], second_key);
+
+    // Key ID is unique, failed rotation changes nothing
+    assert_eq!(
+        adapter.rotate_jwt_key(jwt_key_data("Conformance_Key1", 3000)).await,
+        Err(DatabaseAdapterError::JwtKeyIdAlreadyExists)
+    );
+    assert!(adapter.get_jwt_keys().await.unwrap().last().unwrap().is_active());
+
+    assert_eq!(adapter.remove_jwt_keys_retired_before(1500).await.unwrap(), initial_count);
+    assert_eq!(adapter.remove_jwt_keys_retired_before(5000).await.unwrap(), 1);
+
+    // Active key stays
+    assert_eq!(adapter.get_jwt_keys().await.unwrap(), vec![second_key]);
+}
@@ -0,0 +1,23 @@
+use serde::{Deserialize, Serialize};
+
+/// Key material format depends on `algorithm`:
+/// - `RS256` - PEM encoded private and public key,
+/// - `EdDSA` - PKCS#8 DER private key and raw 32 bytes of Ed25519 public key.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JwtKeyData {
+    /// Put into `kid` header of signed tokens
+    pub key_id: String,
+    pub algorithm: String,
+    pub private_key: Vec<u8>,
+    pub public_key: Vec<u8>,
+    /// UTC timestamp
+    pub created_at: u64,
+    /// UTC timestamp since key is no longer used for signing, only for verifying
+    pub retired_at: Option<u64>,
+}
+
+impl JwtKeyData {
+    pub fn is_active(&self) -> bool {
+        self.retired_at.is_none()
+    }
+}
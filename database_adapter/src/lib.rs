@@ -1,11 +1,17 @@
 pub mod account;
 pub mod test;
 pub mod character;
+pub mod sqlite;
+pub mod conformance;
+pub mod refresh_token;
+pub mod jwt_key;
 
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 pub use account::AccountData;
 use crate::character::{CharacterData, CharacterId, NewCharacterData};
+use crate::jwt_key::JwtKeyData;
+use crate::refresh_token::RefreshTokenData;
 
 #[derive(Debug, thiserror::Error, Serialize, Deserialize, PartialEq, Clone)]
 pub enum DatabaseAdapterError {
@@ -41,6 +47,27 @@ pub enum DatabaseAdapterError {
 
     #[error("Character not owned by account")]
     CharacterNotOwnedByAccount,
+
+    #[error("Refresh token not found")]
+    RefreshTokenNotFound,
+
+    #[error("Refresh token expired")]
+    RefreshTokenExpired,
+
+    #[error("Refresh token was already used")]
+    RefreshTokenReused,
+
+    #[error("JWT key ID already exists")]
+    JwtKeyIdAlreadyExists,
+
+    #[error("Storage error, reason = '{0}'")]
+    StorageError(String),
+
+    #[error("Unsupported schema version {found}, newest supported is {supported}")]
+    UnsupportedSchemaVersion {
+        found: u32,
+        supported: u32,
+    },
 }
 
 pub type  DatabaseAdapterResult<T> = Result<T, DatabaseAdapterError>;
@@ -68,6 +95,13 @@ pub trait DatabaseAdapter: Send + Sync {
 
     async fn add_character(&self, new_character: NewCharacterData) -> DatabaseAdapterResult<CharacterId>;
 
+    /// Adds character and attaches it to account as single operation,
+    /// on failure no character is left behind
+    async fn create_character_for_account(&self, username: &str, new_character: NewCharacterData) -> DatabaseAdapterResult<CharacterId>;
+
+    /// Writes back state of already existing character
+    async fn update_character(&self, character: CharacterData) -> DatabaseAdapterResult<()>;
+
     async fn get_account_of_character(&self, character_id: CharacterId) -> DatabaseAdapterResult<Option<String>>;
 
     // Requires removing attachment if attach to an account
@@ -81,7 +115,40 @@ pub trait DatabaseAdapter: Send + Sync {
 
     async fn get_characters_of_account(&self, username: &str) -> DatabaseAdapterResult<Vec<CharacterId>>;
 
-    async fn get_jwt_private_key(&self)  -> DatabaseAdapterResult<Vec<u8>>;
+    /// Ordered from the oldest
+    async fn get_jwt_keys(&self) -> DatabaseAdapterResult<Vec<JwtKeyData>>;
+
+    /// Adds key as the only active one, previously active keys get retired at `new_key.created_at`
+    async fn rotate_jwt_key(&self, new_key: JwtKeyData) -> DatabaseAdapterResult<()>;
+
+    /// Active key is never removed. Returns number of removed keys.
+    async fn remove_jwt_keys_retired_before(&self, before: u64) -> DatabaseAdapterResult<usize>;
+
+    /// Token must be treated as revoked at least until `expires_at` (UTC timestamp),
+    /// revoking already revoked token is not an error
+    async fn revoke_token(&self, token_id: &str, expires_at: u64) -> DatabaseAdapterResult<()>;
+
+    async fn is_token_revoked(&self, token_id: &str) -> DatabaseAdapterResult<bool>;
+
+    /// Forgets revoked tokens expired before `now`, they are rejected by expiration anyway.
+    /// Returns number of forgotten tokens.
+    async fn prune_revoked_tokens(&self, now: u64) -> DatabaseAdapterResult<usize>;
+
+    /// Token must belong to existing account, removing account removes its tokens
+    async fn add_refresh_token(&self, refresh_token: RefreshTokenData) -> DatabaseAdapterResult<()>;
+
+    async fn get_refresh_token(&self, token: &str) -> DatabaseAdapterResult<RefreshTokenData>;
+
+    /// Marks `token` as rotated and adds its successor as single operation.
+    /// Fails with `RefreshTokenReused` if `token` was already rotated, then nothing is added.
+    async fn rotate_refresh_token(&self, token: &str, new_refresh_token: RefreshTokenData) -> DatabaseAdapterResult<()>;
+
+    /// Returns number of removed tokens
+    async fn remove_refresh_token_family(&self, family_id: &str) -> DatabaseAdapterResult<usize>;
+
+    /// Returns number of removed tokens
+    async fn remove_refresh_tokens_of_account(&self, username: &str) -> DatabaseAdapterResult<usize>;
 
-    async fn get_jwt_public_key(&self)  -> DatabaseAdapterResult<Vec<u8>>;
+    /// Removes tokens expired before `now`, rotated ones included. Returns number of removed tokens.
+    async fn prune_refresh_tokens(&self, now: u64) -> DatabaseAdapterResult<usize>;
 }
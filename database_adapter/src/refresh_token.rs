@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RefreshTokenData {
+    /// SHA-256 of token given to client, raw token is never stored
+    pub token: String,
+    pub username: String,
+    /// Shared by all tokens rotated from the same login, reuse of any of them invalidates whole family
+    pub family_id: String,
+    /// UTC timestamp
+    pub expires_at: u64,
+    /// Already exchanged for newer token, kept only to detect reuse
+    pub rotated: bool,
+}
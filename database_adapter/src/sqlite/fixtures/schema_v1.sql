@@ -0,0 +1,48 @@
+-- Database as written by schema version 1, do not modify.
+PRAGMA user_version = 1;
+
+CREATE TABLE accounts (
+    username        TEXT PRIMARY KEY NOT NULL,
+    hashed_password TEXT NOT NULL
+);
+
+CREATE TABLE characters (
+    id          INTEGER PRIMARY KEY NOT NULL,
+    name        TEXT NOT NULL,
+    position_x  REAL NOT NULL,
+    position_y  REAL NOT NULL,
+    speed       REAL NOT NULL
+);
+
+CREATE TABLE account_characters (
+    id              INTEGER PRIMARY KEY AUTOINCREMENT,
+    username        TEXT NOT NULL REFERENCES accounts(username) ON DELETE CASCADE,
+    character_id    INTEGER NOT NULL UNIQUE REFERENCES characters(id)
+);
+
+CREATE TABLE counters (
+    name    TEXT PRIMARY KEY NOT NULL,
+    value   INTEGER NOT NULL
+);
+
+CREATE TABLE jwt_keys (
+    id          INTEGER PRIMARY KEY CHECK (id = 0),
+    private_key BLOB NOT NULL,
+    public_key  BLOB NOT NULL
+);
+
+-- Password: 'Password1'
+INSERT INTO accounts (username, hashed_password) VALUES
+    ('Account1', '$argon2id$v=19$m=19456,t=2,p=1$zGQ2Kz2F5C+LrZvfBY48bg$hW6ja+uNatyQ48RUX7MzfdlNVcB5qiQWeWhdeNFCNAw'),
+    ('Account2', '$argon2id$v=19$m=19456,t=2,p=1$zGQ2Kz2F5C+LrZvfBY48bg$hW6ja+uNatyQ48RUX7MzfdlNVcB5qiQWeWhdeNFCNAw');
+
+-- Character 1 was removed
+INSERT INTO characters (id, name, position_x, position_y, speed) VALUES
+    (0, 'Janusz', 0.0, 0.0, 1.0),
+    (2, 'Raspberry', -2.0, 3.5, 1.2);
+
+INSERT INTO account_characters (username, character_id) VALUES
+    ('Account1', 2),
+    ('Account1', 0);
+
+INSERT INTO counters (name, value) VALUES ('next_character_id', 3);
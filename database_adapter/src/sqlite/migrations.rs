@@ -0,0 +1,229 @@
+use rusqlite::Connection;
+use crate::{DatabaseAdapterError, DatabaseAdapterResult};
+
+pub type SchemaVersion = u32;
+
+pub struct Migration {
+    pub version: SchemaVersion,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+/// Ordered by version, each version is greater than previous one by one.
+/// Never edit already released migrations, append new one instead.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Accounts, characters and JWT keys",
+        // `IF NOT EXISTS` adopts databases created before versioning was introduced
+        sql: "
+            CREATE TABLE IF NOT EXISTS accounts (
+                username        TEXT PRIMARY KEY NOT NULL,
+                hashed_password TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS characters (
+                id          INTEGER PRIMARY KEY NOT NULL,
+                name        TEXT NOT NULL,
+                position_x  REAL NOT NULL,
+                position_y  REAL NOT NULL,
+                speed       REAL NOT NULL
+            );
+
+            -- Keeps attachment order, character can belong to at most one account
+            CREATE TABLE IF NOT EXISTS account_characters (
+                id              INTEGER PRIMARY KEY AUTOINCREMENT,
+                username        TEXT NOT NULL REFERENCES accounts(username) ON DELETE CASCADE,
+                character_id    INTEGER NOT NULL UNIQUE REFERENCES characters(id)
+            );
+
+            -- IDs are never reused, even after character removal
+            CREATE TABLE IF NOT EXISTS counters (
+                name    TEXT PRIMARY KEY NOT NULL,
+                value   INTEGER NOT NULL
+            );
+            INSERT OR IGNORE INTO counters (name, value) VALUES ('next_character_id', 0);
+
+            CREATE TABLE IF NOT EXISTS jwt_keys (
+                id          INTEGER PRIMARY KEY CHECK (id = 0),
+                private_key BLOB NOT NULL,
+                public_key  BLOB NOT NULL
+            );
+        ",
+    },
+    Migration {
+        version: 2,
+        description: "Index characters of account lookup",
+        sql: "
+            CREATE INDEX account_characters_username ON account_characters (username);
+        ",
+    },
+    Migration {
+        version: 3,
+        description: "Revoked JWT tokens",
+        sql: "
+            CREATE TABLE revoked_tokens (
+                token_id    TEXT PRIMARY KEY NOT NULL,
+                expires_at  INTEGER NOT NULL
+            );
+            CREATE INDEX revoked_tokens_expires_at ON revoked_tokens (expires_at);
+        ",
+    },
+    Migration {
+        version: 4,
+        description: "Refresh tokens",
+        sql: "
+            CREATE TABLE refresh_tokens (
+                token       TEXT PRIMARY KEY NOT NULL,
+                username    TEXT NOT NULL REFERENCES accounts(username) ON DELETE CASCADE,
+                family_id   TEXT NOT NULL,
+                expires_at  INTEGER NOT NULL,
+                rotated     INTEGER NOT NULL
+            );
+            CREATE INDEX refresh_tokens_username ON refresh_tokens (username);
+            CREATE INDEX refresh_tokens_family_id ON refresh_tokens (family_id);
+        ",
+    },
+    Migration {
+        version: 5,
+        description: "Multiple JWT keys",
+        sql: "
+            CREATE TABLE jwt_signing_keys (
+                key_id      TEXT PRIMARY KEY NOT NULL,
+                algorithm   TEXT NOT NULL,
+                private_key BLOB NOT NULL,
+                public_key  BLOB NOT NULL,
+                created_at  INTEGER NOT NULL,
+                retired_at  INTEGER
+            );
+
+            -- The only key so far was signing tokens with 'test' key ID
+            INSERT INTO jwt_signing_keys (key_id, algorithm, private_key, public_key, created_at, retired_at)
+                SELECT 'test', 'RS256', private_key, public_key, 0, NULL FROM jwt_keys;
+            DROP TABLE jwt_keys;
+        ",
+    },
+    Migration {
+        version: 6,
+        description: "Hashed refresh tokens",
+        // Stored tokens were raw and can't be hashed here, their accounts have to log in again
+        sql: "
+            DELETE FROM refresh_tokens;
+        ",
+    },
+    Migration {
+        version: 7,
+        description: "Retire legacy JWT key",
+        // Private key of 'test' is committed to repository, it only verifies tokens issued before
+        // and new active key gets generated on load
+        sql: "
+            UPDATE jwt_signing_keys SET retired_at = CAST(strftime('%s', 'now') AS INTEGER)
+                WHERE key_id = 'test' AND retired_at IS NULL;
+        ",
+    },
+];
+
+pub fn latest_version() -> SchemaVersion {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+/// Stored in SQLite header, fresh database has version 0.
+pub fn get_schema_version(connection: &Connection) -> DatabaseAdapterResult<SchemaVersion> {
+    Ok(connection.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+/// Refuses database written by newer build, it could be silently damaged by old code.
+pub fn check_schema_version(connection: &Connection) -> DatabaseAdapterResult<SchemaVersion> {
+    let found = get_schema_version(connection)?;
+    let supported = latest_version();
+    if found > supported {
+        Err(DatabaseAdapterError::UnsupportedSchemaVersion { found, supported })
+    } else {
+        Ok(found)
+    }
+}
+
+pub fn get_pending_migrations(connection: &Connection) -> DatabaseAdapterResult<Vec<&'static Migration>> {
+    let current_version = check_schema_version(connection)?;
+    Ok(MIGRATIONS
+        .iter()
+        .filter(|migration| migration.version > current_version)
+        .collect())
+}
+
+/// Applies pending migrations up to `target_version`, every migration in its own transaction.
+/// Returns versions applied.
+pub fn migrate_to(connection: &mut Connection, target_version: SchemaVersion) -> DatabaseAdapterResult<Vec<SchemaVersion>> {
+    let mut applied = Vec::new();
+    for migration in get_pending_migrations(connection)? {
+        if migration.version > target_version {
+            break;
+        }
+
+        tracing::info!("Applying migration {} '{}'", migration.version, migration.description);
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration.sql)?;
+        transaction.pragma_update(None, "user_version", migration.version)?;
+        transaction.commit()?;
+        applied.push(migration.version);
+    }
+    Ok(applied)
+}
+
+pub fn run_pending_migrations(connection: &mut Connection) -> DatabaseAdapterResult<Vec<SchemaVersion>> {
+    migrate_to(connection, latest_version())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version as usize, index + 1, "Migration '{}'", migration.description);
+        }
+    }
+
+    #[test]
+    fn test_running_migrations_step_by_step() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        assert_eq!(get_schema_version(&connection).unwrap(), 0);
+        assert_eq!(get_pending_migrations(&connection).unwrap().len(), MIGRATIONS.len());
+
+        assert_eq!(migrate_to(&mut connection, 1).unwrap(), vec![1]);
+        assert_eq!(get_schema_version(&connection).unwrap(), 1);
+
+        let applied = run_pending_migrations(&mut connection).unwrap();
+        assert_eq!(applied.first(), Some(&2));
+        assert_eq!(get_schema_version(&connection).unwrap(), latest_version());
+        assert!(get_pending_migrations(&connection).unwrap().is_empty());
+
+        // Nothing left to do
+        assert!(run_pending_migrations(&mut connection).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_refusing_newer_schema() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        connection.pragma_update(None, "user_version", latest_version() + 1).unwrap();
+
+        let expected_err = DatabaseAdapterError::UnsupportedSchemaVersion {
+            found: latest_version() + 1,
+            supported: latest_version(),
+        };
+        assert_eq!(check_schema_version(&connection), Err(expected_err.clone()));
+        assert_eq!(run_pending_migrations(&mut connection), Err(expected_err));
+    }
+
+    #[test]
+    fn test_failed_migration_is_rolled_back() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate_to(&mut connection, 1).unwrap();
+
+        // Makes migration 2 fail
+        connection.execute_batch("CREATE INDEX account_characters_username ON counters (name);").unwrap();
+        assert!(matches!(run_pending_migrations(&mut connection), Err(DatabaseAdapterError::StorageError(_))));
+        assert_eq!(get_schema_version(&connection).unwrap(), 1);
+    }
+}
@@ -0,0 +1,803 @@
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OptionalExtension};
+use crate::{AccountData, DatabaseAdapter, DatabaseAdapterError, DatabaseAdapterResult};
+use crate::character::{CharacterData, CharacterId, NewCharacterData};
+use crate::jwt_key::JwtKeyData;
+use crate::refresh_token::RefreshTokenData;
+use crate::sqlite::migrations::SchemaVersion;
+
+pub mod migrations;
+
+impl From<rusqlite::Error> for DatabaseAdapterError {
+    fn from(err: rusqlite::Error) -> Self {
+        DatabaseAdapterError::StorageError(err.to_string())
+    }
+}
+
+/// Persistent adapter storing data in SQLite database file (or in memory).
+pub struct SqliteAdapter {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteAdapter {
+    pub async fn open<P: AsRef<Path>>(path: P) -> DatabaseAdapterResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        Self::spawn_blocking(move || Self::with_connection(Connection::open(path)?)).await
+    }
+
+    pub async fn open_in_memory() -> DatabaseAdapterResult<Self> {
+        Self::spawn_blocking(|| Self::with_connection(Connection::open_in_memory()?)).await
+    }
+
+    /// Brings schema to the latest version, fails if database was created by newer version.
+    fn with_connection(mut connection: Connection) -> DatabaseAdapterResult<Self> {
+        connection.pragma_update(None, "foreign_keys", true)?;
+        migrations::run_pending_migrations(&mut connection)?;
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    pub async fn get_schema_version(&self) -> DatabaseAdapterResult<SchemaVersion> {
+        self.run(|connection| migrations::get_schema_version(connection)).await
+    }
+
+    /// SQLite calls block, so they are kept off async runtime workers
+    async fn spawn_blocking<T, F>(operation: F) -> DatabaseAdapterResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> DatabaseAdapterResult<T> + Send + 'static,
+    {
+        tokio::task::spawn_blocking(operation)
+            .await
+            .map_err(|e| DatabaseAdapterError::StorageError(e.to_string()))?
+    }
+
+    /// Runs `operation` with exclusive access to connection on blocking thread
+    async fn run<T, F>(&self, operation: F) -> DatabaseAdapterResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> DatabaseAdapterResult<T> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+        Self::spawn_blocking(move || {
+            let mut connection = connection
+                .lock()
+                .map_err(|e| DatabaseAdapterError::StorageError(e.to_string()))?;
+            operation(&mut connection)
+        }).await
+    }
+
+    fn read_account(connection: &Connection, username: &str) -> DatabaseAdapterResult<AccountData> {
+        let hashed_password = connection
+            .query_row(
+                "SELECT hashed_password FROM accounts WHERE username = ?1",
+                params![username],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?
+            .ok_or(DatabaseAdapterError::UsernameNotFound)?;
+
+        Ok(AccountData {
+            username: username.to_string(),
+            hashed_password,
+            characters: Self::read_characters_of_account(connection, username)?,
+        })
+    }
+
+    fn read_characters_of_account(connection: &Connection, username: &str) -> DatabaseAdapterResult<Vec<CharacterId>> {
+        let mut statement = connection.prepare(
+            "SELECT character_id FROM account_characters WHERE username = ?1 ORDER BY id",
+        )?;
+        let characters = statement
+            .query_map(params![username], |row| row.get::<_, CharacterId>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(characters)
+    }
+
+    fn read_character(connection: &Connection, character_id: CharacterId) -> DatabaseAdapterResult<CharacterData> {
+        connection
+            .query_row(
+                "SELECT id, name, position_x, position_y, speed FROM characters WHERE id = ?1",
+                params![character_id],
+                Self::character_from_row,
+            )
+            .optional()?
+            .ok_or(DatabaseAdapterError::CharacterIdNotFound)
+    }
+
+    fn read_owner_of_character(connection: &Connection, character_id: CharacterId) -> DatabaseAdapterResult<Option<String>> {
+        Ok(connection
+            .query_row(
+                "SELECT username FROM account_characters WHERE character_id = ?1",
+                params![character_id],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?)
+    }
+
+    fn account_exists(connection: &Connection, username: &str) -> DatabaseAdapterResult<bool> {
+        Ok(connection
+            .query_row(
+                "SELECT 1 FROM accounts WHERE username = ?1",
+                params![username],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some())
+    }
+
+    /// Allocates ID, should be called within transaction
+    fn insert_character(connection: &Connection, new_character: NewCharacterData) -> DatabaseAdapterResult<CharacterId> {
+        let assigned_character_id = connection.query_row(
+            "SELECT value FROM counters WHERE name = 'next_character_id'",
+            [],
+            |row| row.get::<_, CharacterId>(0),
+        )?;
+        connection.execute(
+            "UPDATE counters SET value = value + 1 WHERE name = 'next_character_id'",
+            [],
+        )?;
+
+        let character = new_character.into_with_id(assigned_character_id);
+        let inserted = connection.execute(
+            "INSERT OR IGNORE INTO characters (id, name, position_x, position_y, speed) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![character.id, character.name, character.position_x, character.position_y, character.speed],
+        )?;
+
+        if inserted == 1 {
+            Ok(assigned_character_id)
+        } else {
+            Err(DatabaseAdapterError::CharacterAlreadyExists)
+        }
+    }
+
+    fn insert_attachment(connection: &Connection, username: &str, character_id: CharacterId) -> DatabaseAdapterResult<()> {
+        connection.execute(
+            "INSERT INTO account_characters (username, character_id) VALUES (?1, ?2)",
+            params![username, character_id],
+        )?;
+        Ok(())
+    }
+
+    fn character_from_row(row: &rusqlite::Row) -> rusqlite::Result<CharacterData> {
+        Ok(CharacterData {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            position_x: row.get(2)?,
+            position_y: row.get(3)?,
+            speed: row.get(4)?,
+        })
+    }
+
+    fn insert_refresh_token(connection: &Connection, refresh_token: &RefreshTokenData) -> DatabaseAdapterResult<()> {
+        if !Self::account_exists(connection, &refresh_token.username)? {
+            return Err(DatabaseAdapterError::UsernameNotFound);
+        }
+        connection.execute(
+            "INSERT INTO refresh_tokens (token, username, family_id, expires_at, rotated) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                refresh_token.token,
+                refresh_token.username,
+                refresh_token.family_id,
+                refresh_token.expires_at,
+                refresh_token.rotated
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DatabaseAdapter for SqliteAdapter {
+    async fn get_accounts(&self) -> DatabaseAdapterResult<Vec<AccountData>> {
+        self.run(|connection| {
+            let mut statement = connection.prepare("SELECT username FROM accounts")?;
+            let usernames = statement
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            usernames
+                .iter()
+                .map(|username| Self::read_account(connection, username))
+                .collect()
+        }).await
+    }
+
+    async fn get_account_by_name(&self, username: &str) -> DatabaseAdapterResult<AccountData> {
+        let username = username.to_string();
+        self.run(move |connection| Self::read_account(connection, &username)).await
+    }
+
+    async fn add_account(&self, new_account: AccountData) -> DatabaseAdapterResult<()> {
+        self.run(move |connection| {
+            let inserted = connection.execute(
+                "INSERT OR IGNORE INTO accounts (username, hashed_password) VALUES (?1, ?2)",
+                params![new_account.username, new_account.hashed_password],
+            )?;
+
+            if inserted == 1 {
+                Ok(())
+            } else {
+                Err(DatabaseAdapterError::UsernameAlreadyExists)
+            }
+        }).await
+    }
+
+    async fn remove_account_with_username(&self, username: &str) -> DatabaseAdapterResult<()> {
+        let username = username.to_string();
+        self.run(move |connection| {
+            // Attachments are removed by cascade, characters stay
+            let removed = connection.execute(
+                "DELETE FROM accounts WHERE username = ?1",
+                params![username],
+            )?;
+
+            if removed == 1 {
+                Ok(())
+            } else {
+                Err(DatabaseAdapterError::UsernameNotFound)
+            }
+        }).await
+    }
+
+    async fn is_password_matching(
+        &self,
+        username: &str,
+        password_plaintext: &str,
+    ) -> DatabaseAdapterResult<bool> {
+        let account = self.get_account_by_name(username).await?;
+        // Hashing is as blocking as querying
+        let password_plaintext = password_plaintext.to_string();
+        Self::spawn_blocking(move || account.verify(&password_plaintext)).await
+    }
+
+    async fn change_password(
+        &self,
+        username: &str,
+        old_password_plaintext: &str,
+        new_password_plaintext: &str,
+    ) -> DatabaseAdapterResult<()> {
+        let username = username.to_string();
+        let old_password_plaintext = old_password_plaintext.to_string();
+        let new_password_plaintext = new_password_plaintext.to_string();
+        self.run(move |connection| {
+            let mut account = Self::read_account(connection, &username)?;
+            if !account.verify(&old_password_plaintext)? {
+                return Err(DatabaseAdapterError::BadPassword);
+            }
+
+            account.set_password(&new_password_plaintext)?;
+            connection.execute(
+                "UPDATE accounts SET hashed_password = ?1 WHERE username = ?2",
+                params![account.hashed_password, username],
+            )?;
+            Ok(())
+        }).await
+    }
+
+    async fn get_accounts_count(&self) -> DatabaseAdapterResult<usize> {
+        self.run(|connection| Ok(connection.query_row(
+            "SELECT COUNT(*) FROM accounts",
+            [],
+            |row| row.get::<_, usize>(0),
+        )?)).await
+    }
+
+    async fn get_characters(&self) -> DatabaseAdapterResult<Vec<CharacterData>> {
+        self.run(|connection| {
+            let mut statement = connection.prepare(
+                "SELECT id, name, position_x, position_y, speed FROM characters",
+            )?;
+            let characters = statement
+                .query_map([], Self::character_from_row)?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(characters)
+        }).await
+    }
+
+    async fn get_character_by_id(&self, character_id: CharacterId) -> DatabaseAdapterResult<CharacterData> {
+        self.run(move |connection| Self::read_character(connection, character_id)).await
+    }
+
+    async fn add_character(&self, new_character: NewCharacterData) -> DatabaseAdapterResult<CharacterId> {
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+            let character_id = Self::insert_character(&transaction, new_character)?;
+            transaction.commit()?;
+            Ok(character_id)
+        }).await
+    }
+
+    async fn create_character_for_account(&self, username: &str, new_character: NewCharacterData) -> DatabaseAdapterResult<CharacterId> {
+        let username = username.to_string();
+        self.run(move |connection| {
+            // Dropped without commit on any error - rolled back
+            let transaction = connection.transaction()?;
+
+            if !Self::account_exists(&transaction, &username)? {
+                return Err(DatabaseAdapterError::UsernameNotFound);
+            }
+
+            let character_id = Self::insert_character(&transaction, new_character)?;
+            Self::insert_attachment(&transaction, &username, character_id)?;
+            transaction.commit()?;
+            Ok(character_id)
+        }).await
+    }
+
+    async fn update_character(&self, character: CharacterData) -> DatabaseAdapterResult<()> {
+        self.run(move |connection| {
+            let updated = connection.execute(
+                "UPDATE characters SET name = ?2, position_x = ?3, position_y = ?4, speed = ?5 WHERE id = ?1",
+                params![character.id, character.name, character.position_x, character.position_y, character.speed],
+            )?;
+
+            if updated == 1 {
+                Ok(())
+            } else {
+                Err(DatabaseAdapterError::CharacterIdNotFound)
+            }
+        }).await
+    }
+
+    async fn get_account_of_character(&self, character_id: CharacterId) -> DatabaseAdapterResult<Option<String>> {
+        self.run(move |connection| Self::read_owner_of_character(connection, character_id)).await
+    }
+
+    async fn remove_character_with_id(&self, character_id: CharacterId) -> DatabaseAdapterResult<()> {
+        self.run(move |connection| {
+            if Self::read_owner_of_character(connection, character_id)?.is_some() {
+                return Err(DatabaseAdapterError::CannotRemoveCharacterAttachedToAccount);
+            }
+
+            let removed = connection.execute(
+                "DELETE FROM characters WHERE id = ?1",
+                params![character_id],
+            )?;
+
+            if removed == 1 {
+                Ok(())
+            } else {
+                Err(DatabaseAdapterError::CharacterIdNotFound)
+            }
+        }).await
+    }
+
+    async fn attach_character_to_account(&self, username: &str, character_id: CharacterId) -> DatabaseAdapterResult<()> {
+        let username = username.to_string();
+        self.run(move |connection| {
+            // Character should exist
+            let _ = Self::read_character(connection, character_id)?;
+
+            // Check if already attached to any account
+            if Self::read_owner_of_character(connection, character_id)?.is_some() {
+                return Err(DatabaseAdapterError::CharacterAlreadyAttached);
+            }
+
+            if !Self::account_exists(connection, &username)? {
+                return Err(DatabaseAdapterError::UsernameNotFound);
+            }
+
+            Self::insert_attachment(connection, &username, character_id)
+        }).await
+    }
+
+    async fn detach_character_from_account(&self, username: &str, character_id: CharacterId) -> DatabaseAdapterResult<()> {
+        let username = username.to_string();
+        self.run(move |connection| {
+            // Character should exist
+            let _ = Self::read_character(connection, character_id)?;
+
+            match Self::read_owner_of_character(connection, character_id)? {
+                None => Err(DatabaseAdapterError::CharacterNotAttached),
+                Some(owner) if owner != username => Err(DatabaseAdapterError::CharacterNotOwnedByAccount),
+                Some(_) => {
+                    connection.execute(
+                        "DELETE FROM account_characters WHERE character_id = ?1",
+                        params![character_id],
+                    )?;
+                    Ok(())
+                }
+            }
+        }).await
+    }
+
+    async fn get_characters_data_of_account(&self, username: &str) -> DatabaseAdapterResult<Vec<CharacterData>> {
+        let username = username.to_string();
+        self.run(move |connection| {
+            Self::read_account(connection, &username)?
+                .characters
+                .into_iter()
+                .map(|character_id| Self::read_character(connection, character_id))
+                .collect()
+        }).await
+    }
+
+    async fn get_characters_of_account(&self, username: &str) -> DatabaseAdapterResult<Vec<CharacterId>> {
+        let username = username.to_string();
+        self.run(move |connection| Ok(Self::read_account(connection, &username)?.characters)).await
+    }
+
+    async fn get_jwt_keys(&self) -> DatabaseAdapterResult<Vec<JwtKeyData>> {
+        self.run(|connection| {
+            let mut statement = connection.prepare(
+                "SELECT key_id, algorithm, private_key, public_key, created_at, retired_at
+                 FROM jwt_signing_keys ORDER BY created_at, rowid",
+            )?;
+            let jwt_keys = statement
+                .query_map([], |row| Ok(JwtKeyData {
+                    key_id: row.get(0)?,
+                    algorithm: row.get(1)?,
+                    private_key: row.get(2)?,
+                    public_key: row.get(3)?,
+                    created_at: row.get(4)?,
+                    retired_at: row.get(5)?,
+                }))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(jwt_keys)
+        }).await
+    }
+
+    async fn rotate_jwt_key(&self, new_key: JwtKeyData) -> DatabaseAdapterResult<()> {
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+
+            transaction.execute(
+                "UPDATE jwt_signing_keys SET retired_at = ?1 WHERE retired_at IS NULL",
+                params![new_key.created_at],
+            )?;
+            let inserted = transaction.execute(
+                "INSERT OR IGNORE INTO jwt_signing_keys (key_id, algorithm, private_key, public_key, created_at, retired_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    new_key.key_id,
+                    new_key.algorithm,
+                    new_key.private_key,
+                    new_key.public_key,
+                    new_key.created_at,
+                    new_key.retired_at
+                ],
+            )?;
+            if inserted == 0 {
+                return Err(DatabaseAdapterError::JwtKeyIdAlreadyExists);
+            }
+
+            transaction.commit()?;
+            Ok(())
+        }).await
+    }
+
+    async fn remove_jwt_keys_retired_before(&self, before: u64) -> DatabaseAdapterResult<usize> {
+        self.run(move |connection| Ok(connection.execute(
+            "DELETE FROM jwt_signing_keys WHERE retired_at < ?1",
+            params![before],
+        )?)).await
+    }
+
+    async fn revoke_token(&self, token_id: &str, expires_at: u64) -> DatabaseAdapterResult<()> {
+        let token_id = token_id.to_string();
+        self.run(move |connection| {
+            connection.execute(
+                "INSERT INTO revoked_tokens (token_id, expires_at) VALUES (?1, ?2)
+                 ON CONFLICT (token_id) DO UPDATE SET expires_at = max(expires_at, excluded.expires_at)",
+                params![token_id, expires_at],
+            )?;
+            Ok(())
+        }).await
+    }
+
+    async fn is_token_revoked(&self, token_id: &str) -> DatabaseAdapterResult<bool> {
+        let token_id = token_id.to_string();
+        self.run(move |connection| Ok(connection
+            .query_row(
+                "SELECT 1 FROM revoked_tokens WHERE token_id = ?1",
+                params![token_id],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some())).await
+    }
+
+    async fn prune_revoked_tokens(&self, now: u64) -> DatabaseAdapterResult<usize> {
+        self.run(move |connection| Ok(connection.execute(
+            "DELETE FROM revoked_tokens WHERE expires_at < ?1",
+            params![now],
+        )?)).await
+    }
+
+    async fn add_refresh_token(&self, refresh_token: RefreshTokenData) -> DatabaseAdapterResult<()> {
+        self.run(move |connection| Self::insert_refresh_token(connection, &refresh_token)).await
+    }
+
+    async fn get_refresh_token(&self, token: &str) -> DatabaseAdapterResult<RefreshTokenData> {
+        let token = token.to_string();
+        self.run(move |connection| connection
+            .query_row(
+                "SELECT token, username, family_id, expires_at, rotated FROM refresh_tokens WHERE token = ?1",
+                params![token],
+                |row| Ok(RefreshTokenData {
+                    token: row.get(0)?,
+                    username: row.get(1)?,
+                    family_id: row.get(2)?,
+                    expires_at: row.get(3)?,
+                    rotated: row.get(4)?,
+                }),
+            )
+            .optional()?
+            .ok_or(DatabaseAdapterError::RefreshTokenNotFound)).await
+    }
+
+    async fn rotate_refresh_token(&self, token: &str, new_refresh_token: RefreshTokenData) -> DatabaseAdapterResult<()> {
+        let token = token.to_string();
+        self.run(move |connection| {
+            let transaction = connection.transaction()?;
+
+            let rotated = transaction
+                .query_row(
+                    "SELECT rotated FROM refresh_tokens WHERE token = ?1",
+                    params![token],
+                    |row| row.get::<_, bool>(0),
+                )
+                .optional()?
+                .ok_or(DatabaseAdapterError::RefreshTokenNotFound)?;
+            if rotated {
+                return Err(DatabaseAdapterError::RefreshTokenReused);
+            }
+
+            transaction.execute(
+                "UPDATE refresh_tokens SET rotated = 1 WHERE token = ?1",
+                params![token],
+            )?;
+            Self::insert_refresh_token(&transaction, &new_refresh_token)?;
+            transaction.commit()?;
+            Ok(())
+        }).await
+    }
+
+    async fn remove_refresh_token_family(&self, family_id: &str) -> DatabaseAdapterResult<usize> {
+        let family_id = family_id.to_string();
+        self.run(move |connection| Ok(connection.execute(
+            "DELETE FROM refresh_tokens WHERE family_id = ?1",
+            params![family_id],
+        )?)).await
+    }
+
+    async fn remove_refresh_tokens_of_account(&self, username: &str) -> DatabaseAdapterResult<usize> {
+        let username = username.to_string();
+        self.run(move |connection| Ok(connection.execute(
+            "DELETE FROM refresh_tokens WHERE username = ?1",
+            params![username],
+        )?)).await
+    }
+
+    async fn prune_refresh_tokens(&self, now: u64) -> DatabaseAdapterResult<usize> {
+        self.run(move |connection| Ok(connection.execute(
+            "DELETE FROM refresh_tokens WHERE expires_at < ?1",
+            params![now],
+        )?)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::*;
+    use crate::conformance::run_conformance;
+    use crate::test::{test_jwt_key, TEST_JWT_KEY_ID, TEST_JWT_PRIVATE_KEY, TEST_JWT_PUBLIC_KEY};
+
+    fn new_character_data(name: &str) -> NewCharacterData {
+        NewCharacterData {
+            name: name.to_string(),
+            position_x: 1.0,
+            position_y: -2.0,
+            speed: 1.5,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_conformance() {
+        run_conformance(Arc::new(SqliteAdapter::open_in_memory().await.unwrap())).await;
+    }
+
+    #[tokio::test]
+    async fn test_adding_and_removing_accounts() {
+        let db_adapter = SqliteAdapter::open_in_memory().await.unwrap();
+        assert_eq!(db_adapter.get_accounts_count().await.unwrap(), 0);
+
+        db_adapter.add_account(AccountData::new("User1".to_string(), "Password12345!@#").unwrap()).await.unwrap();
+        db_adapter.add_account(AccountData::new("User2".to_string(), "Password12345!@#").unwrap()).await.unwrap();
+        assert_eq!(db_adapter.get_accounts_count().await.unwrap(), 2);
+
+        let result = db_adapter.add_account(AccountData::new("User1".to_string(), "Other").unwrap()).await;
+        assert_eq!(result, Err(DatabaseAdapterError::UsernameAlreadyExists));
+        assert_eq!(db_adapter.get_accounts_count().await.unwrap(), 2);
+
+        assert_eq!(db_adapter.remove_account_with_username("User").await, Err(DatabaseAdapterError::UsernameNotFound));
+        db_adapter.remove_account_with_username("User1").await.unwrap();
+        assert_eq!(db_adapter.get_accounts_count().await.unwrap(), 1);
+        assert_eq!(db_adapter.get_account_by_name("User1").await, Err(DatabaseAdapterError::UsernameNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_changing_password() {
+        let db_adapter = SqliteAdapter::open_in_memory().await.unwrap();
+        db_adapter.add_account(AccountData::new("User1".to_string(), "Password1").unwrap()).await.unwrap();
+
+        db_adapter.change_password("User1", "Password1", "Password2").await.unwrap();
+        assert!(db_adapter.is_password_matching("User1", "Password2").await.unwrap());
+        assert!(!db_adapter.is_password_matching("User1", "Password1").await.unwrap());
+
+        assert_eq!(
+            db_adapter.change_password("User1", "Password1", "Password3").await,
+            Err(DatabaseAdapterError::BadPassword)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_characters_attachment() {
+        let db_adapter = SqliteAdapter::open_in_memory().await.unwrap();
+        db_adapter.add_account(AccountData::new("User1".to_string(), "Password1").unwrap()).await.unwrap();
+        db_adapter.add_account(AccountData::new("User2".to_string(), "Password1").unwrap()).await.unwrap();
+
+        let character_1 = db_adapter.add_character(new_character_data("Bob")).await.unwrap();
+        let character_2 = db_adapter.add_character(new_character_data("Alice")).await.unwrap();
+        assert_eq!((character_1, character_2), (0, 1));
+
+        db_adapter.attach_character_to_account("User1", character_2).await.unwrap();
+        db_adapter.attach_character_to_account("User1", character_1).await.unwrap();
+        assert_eq!(db_adapter.get_characters_of_account("User1").await.unwrap(), vec![character_2, character_1]);
+        assert_eq!(db_adapter.get_account_of_character(character_1).await.unwrap(), Some("User1".to_string()));
+
+        assert_eq!(db_adapter.attach_character_to_account("User2", character_1).await, Err(DatabaseAdapterError::CharacterAlreadyAttached));
+        assert_eq!(db_adapter.attach_character_to_account("User3", 1234).await, Err(DatabaseAdapterError::CharacterIdNotFound));
+        assert_eq!(db_adapter.detach_character_from_account("User2", character_1).await, Err(DatabaseAdapterError::CharacterNotOwnedByAccount));
+        assert_eq!(db_adapter.remove_character_with_id(character_1).await, Err(DatabaseAdapterError::CannotRemoveCharacterAttachedToAccount));
+
+        db_adapter.detach_character_from_account("User1", character_1).await.unwrap();
+        assert_eq!(db_adapter.detach_character_from_account("User1", character_1).await, Err(DatabaseAdapterError::CharacterNotAttached));
+        db_adapter.remove_character_with_id(character_1).await.unwrap();
+        assert_eq!(db_adapter.get_character_by_id(character_1).await, Err(DatabaseAdapterError::CharacterIdNotFound));
+
+        // Removed IDs are not reused
+        let character_3 = db_adapter.add_character(new_character_data("Eve")).await.unwrap();
+        assert_eq!(character_3, 2);
+
+        // Removing account breaks attachment only
+        db_adapter.remove_account_with_username("User1").await.unwrap();
+        assert_eq!(db_adapter.get_account_of_character(character_2).await.unwrap(), None);
+        assert_eq!(db_adapter.get_character_by_id(character_2).await.unwrap().name, "Alice");
+    }
+
+    #[tokio::test]
+    async fn test_creating_character_for_account_rolls_back_on_failure() {
+        let db_adapter = SqliteAdapter::open_in_memory().await.unwrap();
+        db_adapter.add_account(AccountData::new("User1".to_string(), "Password1").unwrap()).await.unwrap();
+
+        // Fail in between inserting character and attaching it
+        db_adapter.connection.lock().unwrap().execute_batch("
+            CREATE TRIGGER injected_failure BEFORE INSERT ON account_characters
+            BEGIN SELECT RAISE(FAIL, 'injected failure'); END;
+        ").unwrap();
+
+        let result = db_adapter.create_character_for_account("User1", new_character_data("Bob")).await;
+        assert!(matches!(result, Err(DatabaseAdapterError::StorageError(_))), "{result:?}");
+        assert!(db_adapter.get_characters().await.unwrap().is_empty());
+        assert!(db_adapter.get_characters_of_account("User1").await.unwrap().is_empty());
+
+        db_adapter.connection.lock().unwrap().execute_batch("DROP TRIGGER injected_failure;").unwrap();
+
+        // Allocated ID was rolled back as well
+        let character_id = db_adapter.create_character_for_account("User1", new_character_data("Bob")).await.unwrap();
+        assert_eq!(character_id, 0);
+        assert_eq!(db_adapter.get_characters_of_account("User1").await.unwrap(), vec![character_id]);
+    }
+
+    #[tokio::test]
+    async fn test_data_persists_after_reopening() {
+        let path = std::env::temp_dir().join(format!("database_adapter_test_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        {
+            let db_adapter = SqliteAdapter::open(&path).await.unwrap();
+            db_adapter.add_account(AccountData::new("User1".to_string(), "Password1").unwrap()).await.unwrap();
+            let character_id = db_adapter.add_character(new_character_data("Bob")).await.unwrap();
+            db_adapter.attach_character_to_account("User1", character_id).await.unwrap();
+            db_adapter.rotate_jwt_key(test_jwt_key()).await.unwrap();
+            db_adapter.revoke_token("Token1", 4_000_000_000).await.unwrap();
+        }
+
+        {
+            let db_adapter = SqliteAdapter::open(&path).await.unwrap();
+            assert!(db_adapter.is_password_matching("User1", "Password1").await.unwrap());
+            let characters = db_adapter.get_characters_data_of_account("User1").await.unwrap();
+            assert_eq!(characters.len(), 1);
+            assert_eq!(characters[0].name, "Bob");
+            assert_eq!(characters[0].position_y, -2.0);
+            assert_eq!(db_adapter.get_jwt_keys().await.unwrap(), vec![test_jwt_key()]);
+            assert!(db_adapter.is_token_revoked("Token1").await.unwrap());
+
+            // Counter is persisted as well
+            assert_eq!(db_adapter.add_character(new_character_data("Alice")).await.unwrap(), 1);
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_opening_old_schema_version() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(include_str!("fixtures/schema_v1.sql")).unwrap();
+        assert_eq!(migrations::get_schema_version(&connection).unwrap(), 1);
+
+        let db_adapter = SqliteAdapter::with_connection(connection).unwrap();
+        assert_eq!(db_adapter.get_schema_version().await.unwrap(), migrations::latest_version());
+
+        // Contents survived upgrade
+        assert_eq!(db_adapter.get_accounts_count().await.unwrap(), 2);
+        assert!(db_adapter.is_password_matching("Account1", "Password1").await.unwrap());
+        assert_eq!(db_adapter.get_characters_of_account("Account1").await.unwrap(), vec![2, 0]);
+        assert_eq!(db_adapter.get_characters_of_account("Account2").await.unwrap(), vec![]);
+        let character = db_adapter.get_character_by_id(2).await.unwrap();
+        assert_eq!(character.name, "Raspberry");
+        assert_eq!((character.position_x, character.position_y, character.speed), (-2.0, 3.5, 1.2));
+        assert_eq!(db_adapter.get_character_by_id(1).await, Err(DatabaseAdapterError::CharacterIdNotFound));
+        assert_eq!(db_adapter.add_character(new_character_data("Tuna")).await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_opening_newer_schema_version_should_fail() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection.pragma_update(None, "user_version", migrations::latest_version() + 1).unwrap();
+
+        let result = SqliteAdapter::with_connection(connection);
+        assert!(matches!(result, Err(DatabaseAdapterError::UnsupportedSchemaVersion { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_fresh_database_has_no_jwt_keys() {
+        let db_adapter = SqliteAdapter::open_in_memory().await.unwrap();
+        assert!(db_adapter.get_jwt_keys().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_single_jwt_key_is_migrated() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrations::migrate_to(&mut connection, 4).unwrap();
+        connection.execute(
+            "INSERT INTO jwt_keys (id, private_key, public_key) VALUES (0, ?1, ?2)",
+            params![TEST_JWT_PRIVATE_KEY, TEST_JWT_PUBLIC_KEY],
+        ).unwrap();
+
+        let db_adapter = SqliteAdapter::with_connection(connection).unwrap();
+        let jwt_keys = db_adapter.get_jwt_keys().await.unwrap();
+        assert_eq!(jwt_keys.len(), 1);
+
+        // Only verifies tokens issued before migration, nothing signs with it
+        let migrated_key = &jwt_keys[0];
+        assert_eq!(migrated_key.key_id, TEST_JWT_KEY_ID);
+        assert_eq!((migrated_key.private_key.as_slice(), migrated_key.public_key.as_slice()), (TEST_JWT_PRIVATE_KEY, TEST_JWT_PUBLIC_KEY));
+        assert!(!migrated_key.is_active());
+        assert!(migrated_key.retired_at.unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_active_legacy_jwt_key_gets_retired() {
+        // Database upgraded by build which kept legacy key active
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrations::migrate_to(&mut connection, 6).unwrap();
+        connection.execute(
+            "INSERT INTO jwt_signing_keys (key_id, algorithm, private_key, public_key, created_at, retired_at)
+             VALUES (?1, 'RS256', ?2, ?3, 0, NULL)",
+            params![TEST_JWT_KEY_ID, TEST_JWT_PRIVATE_KEY, TEST_JWT_PUBLIC_KEY],
+        ).unwrap();
+
+        let db_adapter = SqliteAdapter::with_connection(connection).unwrap();
+        let jwt_keys = db_adapter.get_jwt_keys().await.unwrap();
+        assert_eq!(jwt_keys.len(), 1);
+        assert!(!jwt_keys[0].is_active());
+    }
+}
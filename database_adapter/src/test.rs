@@ -1,8 +1,26 @@
 use crate::{AccountData, DatabaseAdapter, DatabaseAdapterError, DatabaseAdapterResult};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use async_trait::async_trait;
 use tokio::sync::Mutex;
 use crate::character::{CharacterData, CharacterId, NewCharacterData};
+use crate::jwt_key::JwtKeyData;
+use crate::refresh_token::RefreshTokenData;
+
+pub const TEST_JWT_PRIVATE_KEY: &[u8] = include_bytes!("jwt.key");
+pub const TEST_JWT_PUBLIC_KEY: &[u8] = include_bytes!("jwt.key.pub");
+pub const TEST_JWT_KEY_ID: &str = "test";
+
+/// Active from the beginning of time, so tokens signed with it are accepted without setup
+pub fn test_jwt_key() -> JwtKeyData {
+    JwtKeyData {
+        key_id: TEST_JWT_KEY_ID.to_string(),
+        algorithm: "RS256".to_string(),
+        private_key: TEST_JWT_PRIVATE_KEY.to_vec(),
+        public_key: TEST_JWT_PUBLIC_KEY.to_vec(),
+        created_at: 0,
+        retired_at: None,
+    }
+}
 
 struct CharactersManager {
     pub characters: HashSet<CharacterData>,
@@ -16,11 +34,25 @@ impl CharactersManager {
             new_character_id: 0,
         }
     }
+
+    pub fn add(&mut self, new_character: NewCharacterData) -> DatabaseAdapterResult<CharacterId> {
+        let assigned_character_id = self.new_character_id;
+        self.new_character_id += 1;
+
+        if self.characters.insert(new_character.into_with_id(assigned_character_id)) {
+            Ok(assigned_character_id)
+        } else {
+            Err(DatabaseAdapterError::CharacterAlreadyExists)
+        }
+    }
 }
 
 pub struct DatabaseTestAdapter {
     accounts: Mutex<HashSet<AccountData>>,
     characters_manager: Mutex<CharactersManager>,
+    revoked_tokens: Mutex<HashMap<String, u64>>,
+    refresh_tokens: Mutex<HashMap<String, RefreshTokenData>>,
+    jwt_keys: Mutex<Vec<JwtKeyData>>,
 }
 
 #[async_trait]
@@ -51,6 +83,7 @@ impl DatabaseAdapter for DatabaseTestAdapter {
     async fn remove_account_with_username(&self, username: &str) -> DatabaseAdapterResult<()> {
         let mut guard = self.accounts.lock().await;
         if guard.remove(username) {
+            self.refresh_tokens.lock().await.retain(|_, refresh_token| refresh_token.username != username);
             Ok(())
         } else {
             Err(DatabaseAdapterError::UsernameNotFound)
@@ -72,20 +105,17 @@ impl DatabaseAdapter for DatabaseTestAdapter {
         old_password_plaintext: &str,
         new_password_plaintext: &str,
     ) -> DatabaseAdapterResult<()> {
-        let old_password_is_matching = self
-            .is_password_matching(username, old_password_plaintext)
-            .await?;
-        if old_password_is_matching {
-            // Account already found, should not fail
-            let new_account = {
-                let mut tmp_account = self.get_account_by_name(username).await?;
-                tmp_account.set_password(new_password_plaintext)?;
-                tmp_account
-            };
-
-            //Update in HashSet: remove-insert again
-            self.remove_account_with_username(username).await?;
-            self.add_account(new_account).await
+        // Account is updated in place, lock is held so it never disappears from the set
+        let mut guard = self.accounts.lock().await;
+        let mut account = guard
+            .get(username)
+            .cloned()
+            .ok_or(DatabaseAdapterError::UsernameNotFound)?;
+
+        if account.verify(old_password_plaintext)? {
+            account.set_password(new_password_plaintext)?;
+            guard.replace(account);
+            Ok(())
         } else {
             Err(DatabaseAdapterError::BadPassword)
         }
@@ -118,14 +148,34 @@ impl DatabaseAdapter for DatabaseTestAdapter {
 
 
     async fn add_character(&self, new_character: NewCharacterData) -> DatabaseAdapterResult<CharacterId> {
-        let mut guard = self.characters_manager.lock().await;
-        let assigned_character_id = guard.new_character_id;
-        guard.new_character_id += 1;
+        self.characters_manager.lock().await.add(new_character)
+    }
 
-        if guard.characters.insert(new_character.into_with_id(assigned_character_id)) {
-            Ok(assigned_character_id)
+    async fn create_character_for_account(&self, username: &str, new_character: NewCharacterData) -> DatabaseAdapterResult<CharacterId> {
+        let mut accounts = self.accounts.lock().await;
+        let mut characters_manager = self.characters_manager.lock().await;
+
+        let new_character_id_backup = characters_manager.new_character_id;
+        let character_id = characters_manager.add(new_character)?;
+
+        match Self::attach_locked(&mut accounts, &characters_manager, username, character_id) {
+            Ok(()) => Ok(character_id),
+            Err(e) => {
+                // Roll back, leave no orphaned character
+                characters_manager.characters.remove(&character_id);
+                characters_manager.new_character_id = new_character_id_backup;
+                Err(e)
+            }
+        }
+    }
+
+    async fn update_character(&self, character: CharacterData) -> DatabaseAdapterResult<()> {
+        let mut guard = self.characters_manager.lock().await;
+        if guard.characters.contains(&character.id) {
+            guard.characters.replace(character);
+            Ok(())
         } else {
-            Err(DatabaseAdapterError::CharacterAlreadyExists)
+            Err(DatabaseAdapterError::CharacterIdNotFound)
         }
     }
 
@@ -153,48 +203,39 @@ impl DatabaseAdapter for DatabaseTestAdapter {
     }
 
     async fn attach_character_to_account(&self, username: &str, character_id: CharacterId) -> DatabaseAdapterResult<()> {
-        // Character should exist
-        let _ = self.get_character_by_id(character_id).await?;
-
-        // Check if already attached to any account
-        let maybe_owner = self.get_account_of_character(character_id).await?;
-        if maybe_owner.is_some() {
-            return Err(DatabaseAdapterError::CharacterAlreadyAttached);
-        }
-
-        // Find and modify the account
-        let mut account = self.get_account_by_name(username).await?;
-        // Add character_id from account.characters
-        if account.characters.contains(&character_id) {
-            // Shouldn't happen, but safe to check
-            return Err(DatabaseAdapterError::CharacterAlreadyAttached);
-        } else {
-            account.characters.push(character_id);
-        }
-
-        //Update in HashSet: remove-insert again
-        self.remove_account_with_username(username).await?;
-        self.add_account(account).await
+        let mut accounts = self.accounts.lock().await;
+        let characters_manager = self.characters_manager.lock().await;
+        Self::attach_locked(&mut accounts, &characters_manager, username, character_id)
     }
 
     async fn detach_character_from_account(&self, username: &str, character_id: CharacterId) -> DatabaseAdapterResult<()> {
+        let mut accounts = self.accounts.lock().await;
+        let characters_manager = self.characters_manager.lock().await;
+
         // Character should exist
-        let _ = self.get_character_by_id(character_id).await?;
+        if !characters_manager.characters.contains(&character_id) {
+            return Err(DatabaseAdapterError::CharacterIdNotFound);
+        }
 
         // Check if already attached to any account
-        let maybe_owner = self.get_account_of_character(character_id).await?;
+        let maybe_owner = accounts
+            .iter()
+            .find(|account_data| account_data.characters.contains(&character_id));
         match maybe_owner {
             None => {
                 return Err(DatabaseAdapterError::CharacterNotAttached);
             }
-            Some(owner) if owner != username => {
+            Some(owner) if owner.username != username => {
                 return Err(DatabaseAdapterError::CharacterNotOwnedByAccount);
             }
             _ => {}
         }
 
         // Find and modify the account
-        let mut account = self.get_account_by_name(username).await?;
+        let mut account = accounts
+            .get(username)
+            .cloned()
+            .ok_or(DatabaseAdapterError::UsernameNotFound)?;
         // Remove character_id from account.characters
         if let Some(pos) = account.characters.iter().position(|id| *id == character_id) {
             account.characters.remove(pos);
@@ -203,10 +244,10 @@ impl DatabaseAdapter for DatabaseTestAdapter {
             return Err(DatabaseAdapterError::CharacterNotOwnedByAccount);
         }
 
-        //Update in HashSet: remove-insert again
-        self.remove_account_with_username(username).await?;
-        self.add_account(account).await
+        accounts.replace(account);
+        Ok(())
     }
+
     async fn get_characters_data_of_account(&self, username: &str) -> DatabaseAdapterResult<Vec<CharacterData>> {
         let characters_ids = self.get_characters_of_account(username).await?;
         let mut characters_data = Vec::with_capacity(characters_ids.len());
@@ -224,20 +265,134 @@ impl DatabaseAdapter for DatabaseTestAdapter {
     }
 
 
-    async fn get_jwt_private_key(&self)  -> DatabaseAdapterResult<Vec<u8>> {
-        Ok(include_bytes!("jwt.key").to_vec())
+    async fn get_jwt_keys(&self) -> DatabaseAdapterResult<Vec<JwtKeyData>> {
+        Ok(self.jwt_keys.lock().await.clone())
     }
 
-    async fn get_jwt_public_key(&self)  -> DatabaseAdapterResult<Vec<u8>> {
-        Ok(include_bytes!("jwt.key.pub").to_vec())
+    async fn rotate_jwt_key(&self, new_key: JwtKeyData) -> DatabaseAdapterResult<()> {
+        let mut jwt_keys = self.jwt_keys.lock().await;
+        if jwt_keys.iter().any(|key| key.key_id == new_key.key_id) {
+            return Err(DatabaseAdapterError::JwtKeyIdAlreadyExists);
+        }
+
+        for key in jwt_keys.iter_mut().filter(|key| key.is_active()) {
+            key.retired_at = Some(new_key.created_at);
+        }
+        jwt_keys.push(new_key);
+        Ok(())
+    }
+
+    async fn remove_jwt_keys_retired_before(&self, before: u64) -> DatabaseAdapterResult<usize> {
+        let mut jwt_keys = self.jwt_keys.lock().await;
+        let count_before = jwt_keys.len();
+        jwt_keys.retain(|key| key.retired_at.is_none_or(|retired_at| retired_at >= before));
+        Ok(count_before - jwt_keys.len())
+    }
+
+    async fn revoke_token(&self, token_id: &str, expires_at: u64) -> DatabaseAdapterResult<()> {
+        let mut revoked_tokens = self.revoked_tokens.lock().await;
+        let revoked_until = revoked_tokens.entry(token_id.to_string()).or_insert(expires_at);
+        *revoked_until = (*revoked_until).max(expires_at);
+        Ok(())
+    }
+
+    async fn is_token_revoked(&self, token_id: &str) -> DatabaseAdapterResult<bool> {
+        Ok(self.revoked_tokens.lock().await.contains_key(token_id))
+    }
+
+    async fn prune_revoked_tokens(&self, now: u64) -> DatabaseAdapterResult<usize> {
+        let mut revoked_tokens = self.revoked_tokens.lock().await;
+        let count_before = revoked_tokens.len();
+        revoked_tokens.retain(|_, expires_at| *expires_at >= now);
+        Ok(count_before - revoked_tokens.len())
+    }
+
+    async fn add_refresh_token(&self, refresh_token: RefreshTokenData) -> DatabaseAdapterResult<()> {
+        let accounts = self.accounts.lock().await;
+        if !accounts.contains(refresh_token.username.as_str()) {
+            return Err(DatabaseAdapterError::UsernameNotFound);
+        }
+        self.refresh_tokens.lock().await.insert(refresh_token.token.clone(), refresh_token);
+        Ok(())
+    }
+
+    async fn get_refresh_token(&self, token: &str) -> DatabaseAdapterResult<RefreshTokenData> {
+        self.refresh_tokens.lock().await
+            .get(token)
+            .cloned()
+            .ok_or(DatabaseAdapterError::RefreshTokenNotFound)
+    }
+
+    async fn rotate_refresh_token(&self, token: &str, new_refresh_token: RefreshTokenData) -> DatabaseAdapterResult<()> {
+        let mut refresh_tokens = self.refresh_tokens.lock().await;
+        let refresh_token = refresh_tokens.get_mut(token).ok_or(DatabaseAdapterError::RefreshTokenNotFound)?;
+        if refresh_token.rotated {
+            return Err(DatabaseAdapterError::RefreshTokenReused);
+        }
+        refresh_token.rotated = true;
+        refresh_tokens.insert(new_refresh_token.token.clone(), new_refresh_token);
+        Ok(())
+    }
+
+    async fn remove_refresh_token_family(&self, family_id: &str) -> DatabaseAdapterResult<usize> {
+        let mut refresh_tokens = self.refresh_tokens.lock().await;
+        let count_before = refresh_tokens.len();
+        refresh_tokens.retain(|_, refresh_token| refresh_token.family_id != family_id);
+        Ok(count_before - refresh_tokens.len())
+    }
+
+    async fn remove_refresh_tokens_of_account(&self, username: &str) -> DatabaseAdapterResult<usize> {
+        let mut refresh_tokens = self.refresh_tokens.lock().await;
+        let count_before = refresh_tokens.len();
+        refresh_tokens.retain(|_, refresh_token| refresh_token.username != username);
+        Ok(count_before - refresh_tokens.len())
+    }
+
+    async fn prune_refresh_tokens(&self, now: u64) -> DatabaseAdapterResult<usize> {
+        let mut refresh_tokens = self.refresh_tokens.lock().await;
+        let count_before = refresh_tokens.len();
+        refresh_tokens.retain(|_, refresh_token| refresh_token.expires_at >= now);
+        Ok(count_before - refresh_tokens.len())
     }
 }
 
 impl DatabaseTestAdapter {
+    /// Caller holds both locks, so check and update happen atomically
+    fn attach_locked(
+        accounts: &mut HashSet<AccountData>,
+        characters_manager: &CharactersManager,
+        username: &str,
+        character_id: CharacterId,
+    ) -> DatabaseAdapterResult<()> {
+        // Character should exist
+        if !characters_manager.characters.contains(&character_id) {
+            return Err(DatabaseAdapterError::CharacterIdNotFound);
+        }
+
+        // Check if already attached to any account
+        if accounts.iter().any(|account_data| account_data.characters.contains(&character_id)) {
+            return Err(DatabaseAdapterError::CharacterAlreadyAttached);
+        }
+
+        // Find and modify the account
+        let mut account = accounts
+            .get(username)
+            .cloned()
+            .ok_or(DatabaseAdapterError::UsernameNotFound)?;
+        account.characters.push(character_id);
+
+        // Replaces account in place, no remove-insert gap
+        accounts.replace(account);
+        Ok(())
+    }
+
     pub async fn new() -> Self {
         DatabaseTestAdapter {
             accounts: Mutex::new(HashSet::new()),
             characters_manager: Mutex::new(CharactersManager::new()),
+            revoked_tokens: Mutex::new(HashMap::new()),
+            refresh_tokens: Mutex::new(HashMap::new()),
+            jwt_keys: Mutex::new(vec![test_jwt_key()]),
         }
     }
 
@@ -249,36 +404,47 @@ impl DatabaseTestAdapter {
         db.add_account(AccountData::new("Account2".to_string(), "1234").unwrap()).await.unwrap();
 
         // Characters
-        let _ = db.add_character(NewCharacterData {
+        let janusz_id = db.add_character(NewCharacterData {
             name: "Janusz".to_string(),
             position_x: 0.0,
             position_y: 0.0,
             speed: 1.0
         }).await.unwrap();
 
-        let _ = db.add_character(NewCharacterData {
+        let tuna_id = db.add_character(NewCharacterData {
             name: "Tuna".to_string(),
             position_x: 0.0,
             position_y: 1.0,
             speed: 2.0
         }).await.unwrap();
 
-        let _ = db.add_character(NewCharacterData {
+        let raspberry_id = db.add_character(NewCharacterData {
             name: "Raspberry".to_string(),
             position_x: -2.0,
             position_y: 0.0,
             speed: 1.2
         }).await.unwrap();
 
+        db.attach_character_to_account("Account1", janusz_id).await.unwrap();
+        db.attach_character_to_account("Account1", tuna_id).await.unwrap();
+        db.attach_character_to_account("Account2", raspberry_id).await.unwrap();
+
         db
     }
 }
 
 #[cfg(test)]
 mod tests_accounts {
+    use std::sync::Arc;
     use super::*;
+    use crate::conformance::run_conformance;
     use crate::DatabaseAdapter;
 
+    #[tokio::test]
+    async fn test_conformance() {
+        run_conformance(Arc::new(DatabaseTestAdapter::new().await)).await;
+    }
+
     #[tokio::test]
     async fn test_appending_accounts_and_counting() {
         let db_adapter = DatabaseTestAdapter::new().await;
@@ -459,6 +625,36 @@ mod tests_accounts {
         assert_eq!(db_adapter.get_characters_of_account("User1").await.unwrap(),  vec![]);
     }
 
+    #[tokio::test]
+    async fn test_creating_character_for_not_existing_account_leaves_no_character() {
+        let db_adapter = DatabaseTestAdapter::new().await;
+
+        let new_character_data = NewCharacterData {
+            name: "Bob123".to_string(),
+            position_x: 0.0,
+            position_y: 0.0,
+            speed: 1.0
+        };
+        // Character gets added, then attaching it to missing account fails and it has to be rolled back
+        assert_eq!(db_adapter.create_character_for_account("User1", new_character_data).await, Err(DatabaseAdapterError::UsernameNotFound));
+        assert_eq!(db_adapter.get_characters().await.unwrap(), vec![]);
+        assert_eq!(db_adapter.get_character_by_id(0).await, Err(DatabaseAdapterError::CharacterIdNotFound));
+
+        let account_1 = AccountData::new("User1".to_string(), "Password12345!@#").unwrap();
+        db_adapter.add_account(account_1).await.unwrap();
+
+        let new_character_data = NewCharacterData {
+            name: "Bob123".to_string(),
+            position_x: 0.0,
+            position_y: 0.0,
+            speed: 1.0
+        };
+        let new_character_id = db_adapter.create_character_for_account("User1", new_character_data).await.unwrap();
+        // Id of rolled back character is given again
+        assert_eq!(new_character_id, 0);
+        assert_eq!(db_adapter.get_characters_of_account("User1").await.unwrap(),  vec![new_character_id]);
+    }
+
     #[tokio::test]
     async fn test_removing_account_with_attached_character() {
         let db_adapter = DatabaseTestAdapter::new().await;
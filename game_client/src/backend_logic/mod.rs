@@ -64,7 +64,7 @@ impl BackendLogic {
                                 let _ = response.send(status);
                             },
                             BackendRequest::LoginToAccount { login_data, response } => {
-                                let status = account_manager_client.request_login_to_account(login_data.username, login_data.password).await;
+                                let status = account_manager_client.start_session(login_data.username, login_data.password).await;
                                 let _ = response.send(status);
                             }
                         }
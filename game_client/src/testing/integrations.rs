@@ -5,7 +5,7 @@ mod tests {
     use accounts_manager::AccountsManagerServer;
     use database_adapter::test::DatabaseTestAdapter;
     use game_server::client::GameClient;
-    use game_server::GameServer;
+    use game_server::{GameServer, GameServerOptions};
     use std::sync::Arc;
 
     #[tokio::test]
@@ -20,12 +20,19 @@ mod tests {
             // Database, moved to not be used directly
             let database_adapter = Arc::new(DatabaseTestAdapter::new().await);
 
-            (
-                AccountsManagerServer::run(database_adapter.clone())
-                    .await
-                    .unwrap(),
-                GameServer::run(database_adapter).await.unwrap(),
-            )
+            let accounts_server = AccountsManagerServer::run(database_adapter.clone())
+                .await
+                .unwrap();
+            // Game server verifies tokens with keys published by accounts server
+            let game_server_options = GameServerOptions {
+                accounts_manager_address: accounts_server.get_address().to_string(),
+                ..Default::default()
+            };
+            let game_server = GameServer::run_with_options(database_adapter, game_server_options)
+                .await
+                .unwrap();
+
+            (accounts_server, game_server)
         };
 
         // Accounts related server & client
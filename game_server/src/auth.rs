@@ -0,0 +1,186 @@
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use chrono::Utc;
+use accounts_manager::app_data::AccountManagerClaims;
+use accounts_manager::client::AccountsManagerClient;
+use accounts_manager::jwt_keys::{JwtVerifier, VerifyingKey, VerifyingKeysSource};
+use database_adapter::{DatabaseAdapter, DatabaseAdapterError, DatabaseAdapterResult};
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("Session not authenticated")]
+    NotAuthenticated,
+
+    #[error("Session already authenticated")]
+    AlreadyAuthenticated,
+
+    #[error("Invalid token, reason: '{0}'")]
+    InvalidToken(String),
+
+    #[error("Token revoked")]
+    TokenRevoked,
+
+    #[error("Token expired")]
+    TokenExpired,
+
+    #[error("Keys of accounts manager unavailable, reason: '{0}'")]
+    KeysUnavailable(String),
+
+    #[error(transparent)]
+    DatabaseAdapterError(#[from] DatabaseAdapterError),
+}
+
+pub type AuthResult<T> = Result<T, AuthError>;
+
+fn invalid_token(reason: impl ToString) -> AuthError {
+    AuthError::InvalidToken(reason.to_string())
+}
+
+/// Published keys are fetched again after that time, so keys expired at accounts manager stop verifying here too
+pub const JWKS_MAX_AGE: Duration = Duration::from_secs(60);
+
+/// Keys published by accounts manager, only these which still verify tokens
+pub struct JwksSource {
+    client: AccountsManagerClient,
+}
+
+#[async_trait]
+impl VerifyingKeysSource for JwksSource {
+    async fn get_verifying_keys(&self) -> DatabaseAdapterResult<Vec<VerifyingKey>> {
+        self.client
+            .request_jwks().await
+            .map_err(|e| DatabaseAdapterError::JwtError(e.to_string()))?
+            .keys
+            .iter()
+            .map(|jwk| VerifyingKey::from_jwk(jwk, None))
+            .collect()
+    }
+}
+
+/// Verifies access tokens issued by accounts manager with keys from its JWKS endpoint,
+/// so game server never gets to signing keys. Revocations are read from shared database.
+pub struct AccessTokenVerifier {
+    verifier: JwtVerifier<JwksSource>,
+    database_adapter: Arc<dyn DatabaseAdapter>,
+}
+
+impl AccessTokenVerifier {
+    /// Accounts manager not reachable yet is not an error, keys get fetched with the first token then
+    pub async fn new(accounts_manager_address: &str, database_adapter: Arc<dyn DatabaseAdapter>) -> AuthResult<Self> {
+        let client = AccountsManagerClient::new(accounts_manager_address)
+            .map_err(|e| AuthError::KeysUnavailable(e.to_string()))?;
+        let verifier = JwtVerifier::new(JwksSource { client }, Some(JWKS_MAX_AGE));
+        if let Err(e) = verifier.reload().await {
+            tracing::warn!("Could not fetch keys of accounts manager at {accounts_manager_address}, reason: '{e}'");
+        }
+
+        Ok(Self { verifier, database_adapter })
+    }
+
+    pub async fn verify(&self, token: &str) -> AuthResult<AccountManagerClaims> {
+        let claims = self.verifier
+            .decode(token).await
+            .map_err(|e| match e {
+                axum_jwt_auth::Error::Configuration(reason) => AuthError::KeysUnavailable(reason),
+                e => invalid_token(e),
+            })?
+            .claims;
+
+        if self.database_adapter.is_token_revoked(&claims.jti).await? {
+            return Err(AuthError::TokenRevoked);
+        }
+
+        Ok(claims)
+    }
+
+    /// Token verified earlier could have expired or got revoked by logout since
+    pub async fn check_still_valid(&self, expires_at: u64, token_id: &str) -> AuthResult<()> {
+        if expires_at < Utc::now().timestamp() as u64 {
+            return Err(AuthError::TokenExpired);
+        }
+        if self.database_adapter.is_token_revoked(token_id).await? {
+            return Err(AuthError::TokenRevoked);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use accounts_manager::jwt_keys::{generate_jwt_key, JwtKeys};
+    use accounts_manager::{services, AccountsManagerServer};
+    use database_adapter::test::DatabaseTestAdapter;
+    use super::*;
+
+    async fn login(database_adapter: &Arc<dyn DatabaseAdapter>) -> String {
+        services::login_to_account("Account1".to_string(), "1234".to_string(), database_adapter.clone())
+            .await.unwrap()
+            .access_token
+    }
+
+    /// Verifier gets keys from accounts server sharing the database
+    async fn run_verifier(database_adapter: &Arc<dyn DatabaseAdapter>) -> (AccountsManagerServer, AccessTokenVerifier) {
+        let accounts_server = AccountsManagerServer::run(database_adapter.clone()).await.unwrap();
+        let address = accounts_server.get_address().to_string();
+        (accounts_server, AccessTokenVerifier::new(&address, database_adapter.clone()).await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_verifying_access_token() {
+        let database_adapter: Arc<dyn DatabaseAdapter> = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let (_accounts_server, verifier) = run_verifier(&database_adapter).await;
+        let token = login(&database_adapter).await;
+
+        let claims = verifier.verify(&token).await.unwrap();
+        assert_eq!(claims.iss, "Account1");
+
+        let mut tampered_token = token.clone();
+        tampered_token.pop();
+        assert!(matches!(verifier.verify(&tampered_token).await, Err(AuthError::InvalidToken(_))));
+        assert!(matches!(verifier.verify("bad").await, Err(AuthError::InvalidToken(_))));
+
+        verifier.check_still_valid(claims.exp, &claims.jti).await.unwrap();
+        assert!(matches!(verifier.check_still_valid(0, &claims.jti).await, Err(AuthError::TokenExpired)));
+
+        services::logout_account(claims.clone(), database_adapter.clone()).await.unwrap();
+        assert!(matches!(verifier.verify(&token).await, Err(AuthError::TokenRevoked)));
+        assert!(matches!(verifier.check_still_valid(claims.exp, &claims.jti).await, Err(AuthError::TokenRevoked)));
+    }
+
+    #[tokio::test]
+    async fn test_verifying_access_token_after_key_rotation() {
+        let database_adapter: Arc<dyn DatabaseAdapter> = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let (_accounts_server, verifier) = run_verifier(&database_adapter).await;
+        let old_token = login(&database_adapter).await;
+
+        JwtKeys::load(database_adapter.clone()).await.unwrap().rotate().await.unwrap();
+        let new_token = login(&database_adapter).await;
+
+        verifier.verify(&old_token).await.unwrap();
+        verifier.verify(&new_token).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_token_of_expired_key_is_rejected() {
+        let database_adapter: Arc<dyn DatabaseAdapter> = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let token = login(&database_adapter).await;
+
+        // Retired long ago, so it is not published anymore
+        database_adapter.rotate_jwt_key(generate_jwt_key(1).unwrap()).await.unwrap();
+        let (_accounts_server, verifier) = run_verifier(&database_adapter).await;
+        assert!(matches!(verifier.verify(&token).await, Err(AuthError::InvalidToken(_))));
+    }
+
+    #[tokio::test]
+    async fn test_verifying_without_accounts_manager_should_fail() {
+        let database_adapter: Arc<dyn DatabaseAdapter> = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let token = login(&database_adapter).await;
+
+        // Nothing listens there
+        let address = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().to_string();
+        let verifier = AccessTokenVerifier::new(&address, database_adapter).await.unwrap();
+        assert!(matches!(verifier.verify(&token).await, Err(AuthError::KeysUnavailable(_))));
+    }
+}
@@ -1,3 +1,36 @@
-fn main() {
+use std::sync::Arc;
+use clap::Parser;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use database_adapter::sqlite::SqliteAdapter;
+use game_server::GameServer;
+use game_server::config::{GameServerArgs, GameServerConfig};
+
+#[tokio::main]
+async fn main() {
     println!("Game server!");
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::EnvFilter::new("debug"))
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+
+    let args = GameServerArgs::parse();
+    let config = GameServerConfig::load(&args, std::env::vars()).expect("Invalid config");
+    tracing::info!("Running with {config:?}");
+
+    let database_adapter = SqliteAdapter::open(&config.database_path).await.unwrap();
+    let database_adapter = Arc::new(database_adapter);
+    let server = GameServer::run_with_options(database_adapter, config.to_options()).await.unwrap();
+
+    let ctrlc_notify = Arc::new(tokio::sync::Notify::new());
+    let ctrlc_notify_shared = ctrlc_notify.clone();
+
+    ctrlc::set_handler(move || {
+        ctrlc_notify_shared.notify_one();
+    }).expect("Error setting Ctrl-C handler");
+
+    // Wait until Ctrl+C is triggered
+    ctrlc_notify.notified().await;
+
+    server.shutdown_gracefully().await.unwrap();
 }
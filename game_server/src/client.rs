@@ -1,11 +1,19 @@
-use crate::requests::GameServerRequest;
-use crate::responses::{GameServerResponse, ResponseResult};
+use crate::codec::{Codec, CodecError, HANDSHAKE_CODEC, PROTOCOL_VERSION};
+use crate::events::GameServerEvent;
+use crate::framing::{FrameError, DEFAULT_MAX_FRAME_SIZE};
+use crate::heartbeat::{HeartbeatClock, RttEstimator};
+use crate::transport::{self, BoxedFrameReader, BoxedFrameWriter};
+use crate::requests::{Direction, GameServerRequest, GameServerRequestMessage, RequestId};
+use crate::responses::{GameServerMessage, GameServerResponse, ResponseResult};
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::io::ErrorKind;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::{TcpStream, ToSocketAddrs};
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{broadcast, mpsc, oneshot, Mutex};
 use tokio::task::JoinHandle;
+use accounts_manager::JwtToken;
 use database_adapter::character::CharacterId;
 
 #[derive(Debug, thiserror::Error)]
@@ -17,11 +25,20 @@ pub enum GameClientError {
     OneshotRecvError(#[from] tokio::sync::oneshot::error::RecvError),
 
     #[error(transparent)]
-    SerdeJsonError(#[from] serde_json::Error),
+    CodecError(#[from] CodecError),
+
+    #[error(transparent)]
+    FrameError(#[from] FrameError),
+
+    #[error("Server rejected hello, reason: '{0}'")]
+    HelloRejected(String),
 
     #[error("Bad response")]
     BadResponse,
 
+    #[error("Session not authenticated")]
+    NotAuthenticated,
+
     #[error("Other '{0}'")]
     Other(String),
 }
@@ -30,7 +47,8 @@ pub type GameClientResult<T> = Result<T, GameClientError>;
 
 pub struct GameClientRequest {
     content: GameServerRequest,
-    response_tx: oneshot::Sender<GameServerResponse>,
+    /// `None` for requests server never responds to
+    response_tx: Option<oneshot::Sender<GameServerResponse>>,
 }
 
 impl GameClientRequest {
@@ -39,82 +57,233 @@ impl GameClientRequest {
         (
             Self {
                 content: request,
-                response_tx,
+                response_tx: Some(response_tx),
             },
             response_rx,
         )
     }
+
+    pub fn without_response(request: GameServerRequest) -> Self {
+        Self { content: request, response_tx: None }
+    }
 }
 
+type PendingRequests = Arc<Mutex<HashMap<RequestId, oneshot::Sender<GameServerResponse>>>>;
+
+/// Read synchronously, so GUI can show it every frame
+type SharedRtt = Arc<std::sync::Mutex<RttEstimator>>;
+
 pub struct GameClient {
     requests_tx: mpsc::Sender<GameClientRequest>,
+    events_tx: broadcast::Sender<GameServerEvent>,
     task: JoinHandle<()>,
+    reader_task: JoinHandle<()>,
+    heartbeat_task: JoinHandle<()>,
+    clock: HeartbeatClock,
+    rtt: SharedRtt,
 }
 
 impl GameClient {
+    const REQUESTS_QUEUE_SIZE: usize = 32;
+    /// Subscribers lagging more than this many events skip the oldest ones
+    const EVENTS_QUEUE_SIZE: usize = 256;
+    const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
     pub async fn connect<A: ToSocketAddrs + Debug>(addr: A) -> GameClientResult<Self> {
+        Self::connect_with_codec(addr, Codec::default()).await
+    }
+
+    pub async fn connect_with_codec<A: ToSocketAddrs + Debug>(addr: A, codec: Codec) -> GameClientResult<Self> {
         tracing::info!("Client attempts to connect to server {addr:?}...");
+        let stream = TcpStream::connect(addr).await?;
+        Self::connect_with_transport(transport::split_tcp(stream), codec).await
+    }
+
+    /// Connects to server's WebSocket listener, the same way browser client does
+    pub async fn connect_websocket<A: ToSocketAddrs + Debug>(addr: A) -> GameClientResult<Self> {
+        Self::connect_websocket_with_codec(addr, Codec::default()).await
+    }
+
+    pub async fn connect_websocket_with_codec<A: ToSocketAddrs + Debug>(addr: A, codec: Codec) -> GameClientResult<Self> {
+        tracing::info!("Client attempts to connect to server {addr:?} over WebSocket...");
+        let stream = TcpStream::connect(addr).await?;
+        let url = format!("ws://{}/", stream.peer_addr()?);
+        let config = transport::websocket_config(DEFAULT_MAX_FRAME_SIZE);
+        let (websocket, _) = tokio_tungstenite::client_async_with_config(url, stream, Some(config))
+            .await
+            .map_err(FrameError::from)?;
+        Self::connect_with_transport(transport::split_websocket(websocket), codec).await
+    }
+
+    async fn connect_with_transport(
+        (mut reader, mut writer): (BoxedFrameReader, BoxedFrameWriter),
+        codec: Codec
+    ) -> GameClientResult<Self> {
+        let (requests_tx, requests_rx) = mpsc::channel::<GameClientRequest>(Self::REQUESTS_QUEUE_SIZE);
+        let (events_tx, _) = broadcast::channel(Self::EVENTS_QUEUE_SIZE);
+        let pending_requests = PendingRequests::default();
+
+        Self::hello(&mut reader, &mut writer, codec).await?;
+
+        let clock = HeartbeatClock::new();
+        let rtt = SharedRtt::default();
+        // Background tasks hold weak senders, so dropping client still closes the connection
+        let task = tokio::task::spawn(Self::writer_task(writer, requests_rx, pending_requests.clone(), codec));
+        let reader_task = tokio::task::spawn(
+            Self::reader_task(reader, pending_requests, events_tx.clone(), requests_tx.downgrade(), codec)
+        );
+        let heartbeat_task = tokio::task::spawn(Self::heartbeat_task(requests_tx.downgrade(), clock, rtt.clone()));
+
+        Ok(Self { requests_tx, events_tx, task, reader_task, heartbeat_task, clock, rtt })
+    }
+
+    /// Agrees on protocol version and codec, before any other request is sent
+    async fn hello(reader: &mut BoxedFrameReader, writer: &mut BoxedFrameWriter, codec: Codec) -> GameClientResult<()> {
+        let request = GameServerRequest::Hello { protocol_version: PROTOCOL_VERSION, codec };
+        let message_bytes = HANDSHAKE_CODEC.encode(&GameServerRequestMessage { request_id: 0, request })?;
+        writer.write_frame(&message_bytes, DEFAULT_MAX_FRAME_SIZE).await?;
+
+        let message_bytes = reader.read_frame(DEFAULT_MAX_FRAME_SIZE).await?;
+        match HANDSHAKE_CODEC.decode::<GameServerMessage>(&message_bytes)? {
+            GameServerMessage::Response { response: GameServerResponse::Hello { result, .. }, .. } => match result {
+                ResponseResult::Success => Ok(()),
+                ResponseResult::Error { message } => Err(GameClientError::HelloRejected(message)),
+            },
+            _ => Err(GameClientError::BadResponse),
+        }
+    }
+
+    /// Sends requests without waiting for responses, so many can be in flight at once
+    async fn writer_task(
+        mut writer: BoxedFrameWriter,
+        mut requests_rx: mpsc::Receiver<GameClientRequest>,
+        pending_requests: PendingRequests,
+        codec: Codec,
+    ) {
+        let mut next_request_id: RequestId = 0;
+        while let Some(request) = requests_rx.recv().await {
+            let request_id = next_request_id;
+            next_request_id += 1;
+
+            let message = GameServerRequestMessage { request_id, request: request.content };
+            let message_bytes = match codec.encode(&message) {
+                Ok(message_bytes) => message_bytes,
+                Err(e) => {
+                    // Dropping response sender informs the caller
+                    tracing::error!("Error serializing request {message:?}, reason: '{e}'");
+                    continue;
+                }
+            };
+
+            // Registered before sending, so response cannot outrun it
+            if let Some(response_tx) = request.response_tx {
+                pending_requests.lock().await.insert(request_id, response_tx);
+            }
+            if let Err(e) = writer.write_frame(&message_bytes, DEFAULT_MAX_FRAME_SIZE).await {
+                tracing::error!("Error writing request, reason: '{e}'");
+                pending_requests.lock().await.remove(&request_id);
+                break;
+            }
+        }
+        tracing::info!("Client is getting shutdown. Disconnect soon...");
+        if let Err(e) = writer.close().await {
+            tracing::debug!("Could not close connection, reason: '{e}'");
+        }
+    }
 
-        let (requests_tx, mut requests_rx) = mpsc::channel::<GameClientRequest>(1);
-
-        let mut stream = TcpStream::connect(addr).await?;
-
-        let task = tokio::task::spawn(async move {
-            loop {
-                // Process events
-                tokio::select! {
-                    request = requests_rx.recv() => {
-                        match request {
-                            Some(request) => {
-                                // Send request await response
-                                match Self::process_request(&mut stream, request.content).await {
-                                    Ok(response) => {
-                                        if request.response_tx.send(response).is_err() {
-                                            tracing::warn!("Response channel closed.");
-                                        }
-                                    },
-                                    Err(e) => {
-                                        tracing::error!("Has no response, droping channel");
-                                        // oneshot will be shut soon at drop
-                                    }
-                                }
-                            },
-                            None => {
-                                tracing::info!("Client is getting shutdown. Disconnect soon...");
-                                break;
+    /// Matches responses with requests by ID and publishes events
+    async fn reader_task(
+        mut reader: BoxedFrameReader,
+        pending_requests: PendingRequests,
+        events_tx: broadcast::Sender<GameServerEvent>,
+        requests_tx: mpsc::WeakSender<GameClientRequest>,
+        codec: Codec,
+    ) {
+        while let Ok(message_bytes) = reader.read_frame(DEFAULT_MAX_FRAME_SIZE).await {
+            match codec.decode::<GameServerMessage>(&message_bytes) {
+                Ok(GameServerMessage::Response { request_id, response }) => {
+                    match pending_requests.lock().await.remove(&request_id) {
+                        Some(response_tx) => {
+                            if response_tx.send(response).is_err() {
+                                tracing::warn!("Response channel closed.");
                             }
-                        }
+                        },
+                        None => tracing::warn!("Got response to unknown request {request_id}"),
                     }
-                }
+                },
+                Ok(GameServerMessage::Event { event }) => {
+                    // Error only means nobody listens at the moment
+                    let _ = events_tx.send(event);
+                },
+                Ok(GameServerMessage::Ping { timestamp }) => {
+                    let pong = GameClientRequest::without_response(GameServerRequest::Pong { timestamp });
+                    match requests_tx.upgrade() {
+                        Some(requests_tx) => {
+                            let _ = requests_tx.send(pong).await;
+                        },
+                        None => tracing::debug!("Client is disconnecting, ping left unanswered"),
+                    }
+                },
+                Ok(GameServerMessage::ProtocolViolation { message }) => {
+                    tracing::warn!("Server could not recognize request, reason: '{message}'");
+                },
+                Err(e) => tracing::error!("Error deserializing message: '{e}'"),
             }
-        });
+        }
 
-        Ok(Self { requests_tx, task })
+        tracing::info!("Connection closed");
+        // Callers still awaiting get error, as their response senders are dropped
+        pending_requests.lock().await.clear();
     }
 
-    async fn process_request(
-        stream: &mut TcpStream,
-        request: GameServerRequest,
-    ) -> GameClientResult<GameServerResponse> {
-        let request_bytes = serde_json::to_vec(&request).inspect_err(|err| {
-            tracing::error!("Error serializing request {request:?}. {:?}", err)
-        })?;
+    /// Keeps round trip time estimate fresh, lasts as long as the client
+    async fn heartbeat_task(requests_tx: mpsc::WeakSender<GameClientRequest>, clock: HeartbeatClock, rtt: SharedRtt) {
+        let mut interval = tokio::time::interval(Self::HEARTBEAT_INTERVAL);
+        loop {
+            interval.tick().await;
+            let Some(requests_tx) = requests_tx.upgrade() else {
+                break;
+            };
+            if let Err(e) = Self::measure_rtt(&requests_tx, clock, &rtt).await {
+                tracing::debug!("Heartbeat stopped, reason: '{e}'");
+                break;
+            }
+        }
+    }
 
-        let length = request_bytes.len() as u32;
+    async fn measure_rtt(
+        requests_tx: &mpsc::Sender<GameClientRequest>,
+        clock: HeartbeatClock,
+        rtt: &SharedRtt
+    ) -> GameClientResult<Duration> {
+        let (request, response_rx) = GameClientRequest::wrap(GameServerRequest::Ping { timestamp: clock.now() });
+        requests_tx
+            .send(request)
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        match response_rx.await? {
+            GameServerResponse::Pong { timestamp } => {
+                let sample = clock.elapsed_since(timestamp).ok_or(GameClientError::BadResponse)?;
+                rtt.lock().unwrap().update(sample);
+                Ok(sample)
+            },
+            _ => Err(GameClientError::BadResponse)
+        }
+    }
 
-        stream.write_u32_le(length).await?;
-        stream.write_all(request_bytes.as_slice()).await?;
+    /// Single round trip, also taken into the estimate
+    pub async fn ping(&self) -> GameClientResult<Duration> {
+        Self::measure_rtt(&self.requests_tx, self.clock, &self.rtt).await
+    }
 
-        let response_length = stream.read_u32_le().await?;
-        tracing::info!("Client sent request, awaiting response size: {response_length}...");
-        let mut response_buff = vec![0u8; response_length as usize];
-        stream.read_exact(&mut response_buff).await.inspect_err(|e| {
-            tracing::error!("Error reading from response stream: '{e:?}' exact size: {response_length}")
-        })?;
+    /// Smoothed round trip time, `None` until the first pong arrives
+    pub fn get_rtt(&self) -> Option<Duration> {
+        self.rtt.lock().unwrap().get_rtt()
+    }
 
-        Ok(serde_json::from_slice(&response_buff)
-            .inspect_err(|e| tracing::error!("Error deserializing response: '{e}'"))?
-        )
+    /// Events pushed by server from now on, server sends them only to authenticated sessions
+    pub fn subscribe_events(&self) -> broadcast::Receiver<GameServerEvent> {
+        self.events_tx.subscribe()
     }
 
     pub async fn make_request(
@@ -129,6 +298,17 @@ impl GameClient {
         Ok(response_rx.await?)
     }
 
+    pub async fn authenticate(&self, token: JwtToken) -> GameClientResult<()> {
+        let response = self.make_request(GameServerRequest::Authenticate { token }).await?;
+        match response {
+            GameServerResponse::Authenticate { result } => match result {
+                ResponseResult::Success => Ok(()),
+                ResponseResult::Error { message } => Err(GameClientError::Other(message)),
+            },
+            _ => Err(GameClientError::BadResponse)
+        }
+    }
+
     pub async fn attach_to_character(&self, character_id: CharacterId) -> GameClientResult<()> {
         let response = self.make_request(GameServerRequest::AttachToCharacter { character_id }).await?;
         match response {
@@ -136,6 +316,43 @@ impl GameClient {
                 ResponseResult::Success => Ok(()),
                 ResponseResult::Error { message } =>  Err(GameClientError::Other(message)),
             },
+            GameServerResponse::NotAuthenticated => Err(GameClientError::NotAuthenticated),
+            _ => Err(GameClientError::BadResponse)
+        }
+    }
+
+    pub async fn move_to(&self, x: f32, y: f32) -> GameClientResult<()> {
+        let response = self.make_request(GameServerRequest::MoveTo { x, y }).await?;
+        match response {
+            GameServerResponse::MoveTo { result } => match result {
+                ResponseResult::Success => Ok(()),
+                ResponseResult::Error { message } => Err(GameClientError::Other(message)),
+            },
+            GameServerResponse::NotAuthenticated => Err(GameClientError::NotAuthenticated),
+            _ => Err(GameClientError::BadResponse)
+        }
+    }
+
+    pub async fn step(&self, dir: Direction) -> GameClientResult<()> {
+        let response = self.make_request(GameServerRequest::Step { dir }).await?;
+        match response {
+            GameServerResponse::Step { result } => match result {
+                ResponseResult::Success => Ok(()),
+                ResponseResult::Error { message } => Err(GameClientError::Other(message)),
+            },
+            GameServerResponse::NotAuthenticated => Err(GameClientError::NotAuthenticated),
+            _ => Err(GameClientError::BadResponse)
+        }
+    }
+
+    pub async fn get_position(&self) -> GameClientResult<(f32, f32)> {
+        let response = self.make_request(GameServerRequest::GetPosition).await?;
+        match response {
+            GameServerResponse::GetPosition { result, x, y } => match result {
+                ResponseResult::Success => Ok((x, y)),
+                ResponseResult::Error { message } => Err(GameClientError::Other(message)),
+            },
+            GameServerResponse::NotAuthenticated => Err(GameClientError::NotAuthenticated),
             _ => Err(GameClientError::BadResponse)
         }
     }
@@ -144,6 +361,7 @@ impl GameClient {
         let response = self.make_request(GameServerRequest::EntitiesCount).await?;
         match response {
             GameServerResponse::EntitiesCount { count } => Ok(count),
+            GameServerResponse::NotAuthenticated => Err(GameClientError::NotAuthenticated),
             _ => Err(GameClientError::BadResponse)
         }
     }
@@ -156,8 +374,35 @@ impl GameClient {
         }
     }
 
+    pub async fn chat(&self, message: String) -> GameClientResult<()> {
+        let response = self.make_request(GameServerRequest::Chat { message }).await?;
+        match response {
+            GameServerResponse::Chat { result } => match result {
+                ResponseResult::Success => Ok(()),
+                ResponseResult::Error { message } => Err(GameClientError::Other(message)),
+            },
+            GameServerResponse::NotAuthenticated => Err(GameClientError::NotAuthenticated),
+            _ => Err(GameClientError::BadResponse)
+        }
+    }
+
+    /// Snapshot comes as event, followed by deltas
+    pub async fn request_resync_world(&self) -> GameClientResult<()> {
+        let response = self.make_request(GameServerRequest::ResyncWorld).await?;
+        match response {
+            GameServerResponse::ResyncWorld { result } => match result {
+                ResponseResult::Success => Ok(()),
+                ResponseResult::Error { message } => Err(GameClientError::Other(message)),
+            },
+            GameServerResponse::NotAuthenticated => Err(GameClientError::NotAuthenticated),
+            _ => Err(GameClientError::BadResponse)
+        }
+    }
+
     pub async fn disconnect_await_finished(self) {
+        self.heartbeat_task.abort();
         drop(self.requests_tx);
         let _ = self.task.await.expect("Finishing client's task failed");
+        self.reader_task.await.expect("Finishing client's reader task failed");
     }
 }
@@ -0,0 +1,122 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Bumped on every change of requests, responses or events.
+/// Server accepts only clients speaking the same version.
+pub const PROTOCOL_VERSION: ProtocolVersion = 2;
+
+pub type ProtocolVersion = u32;
+
+/// Hello and its response are encoded with it, whatever codec gets chosen
+pub const HANDSHAKE_CODEC: Codec = Codec::Json;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CodecError {
+    #[error(transparent)]
+    SerdeJsonError(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    BincodeError(#[from] bincode::Error),
+}
+
+pub type CodecResult<T> = Result<T, CodecError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum HandshakeError {
+    #[error("Unsupported protocol version {client_version}, server speaks version {server_version}")]
+    UnsupportedProtocolVersion {
+        client_version: ProtocolVersion,
+        server_version: ProtocolVersion,
+    },
+
+    #[error("Expected Hello as the first request")]
+    HelloExpected,
+
+    #[error("Hello already received")]
+    AlreadyHelloed,
+
+    #[error("No hello received in time")]
+    HelloTimeout,
+}
+
+/// Encoding of frame payloads, chosen by client in `GameServerRequest::Hello`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Codec {
+    /// Human readable, for debugging
+    Json,
+    /// Compact, for regular play
+    #[default]
+    Bincode,
+}
+
+impl Codec {
+    pub fn encode<T: Serialize>(&self, value: &T) -> CodecResult<Vec<u8>> {
+        match self {
+            Codec::Json => Ok(serde_json::to_vec(value)?),
+            Codec::Bincode => Ok(bincode::serialize(value)?),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> CodecResult<T> {
+        match self {
+            Codec::Json => Ok(serde_json::from_slice(bytes)?),
+            Codec::Bincode => Ok(bincode::deserialize(bytes)?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::GameServerEvent;
+    use crate::replication::{EntityState, MovementReplica, WorldDelta};
+    use crate::requests::{Direction, GameServerRequest, GameServerRequestMessage};
+    use crate::responses::GameServerMessage;
+    use super::*;
+
+    fn test_delta() -> GameServerMessage {
+        GameServerMessage::Event {
+            event: GameServerEvent::WorldDelta {
+                delta: WorldDelta {
+                    tick: 7,
+                    base_tick: 6,
+                    changed: vec![EntityState {
+                        entity_id: 3,
+                        name: None,
+                        position: Some((1.5, -2.0)),
+                        movement: Some(MovementReplica { target: Some((2.0, -2.0)), facing: Direction::Right, speed: 1.0 }),
+                    }],
+                    entered: vec![],
+                    left: vec![4],
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn test_encoding_and_decoding() {
+        for codec in [Codec::Json, Codec::Bincode] {
+            let bytes = codec.encode(&test_delta()).unwrap();
+            let message: GameServerMessage = codec.decode(&bytes).unwrap();
+            assert_eq!(format!("{message:?}"), format!("{:?}", test_delta()));
+
+            let request = GameServerRequestMessage { request_id: 5, request: GameServerRequest::Step { dir: Direction::Up } };
+            let bytes = codec.encode(&request).unwrap();
+            let request: GameServerRequestMessage = codec.decode(&bytes).unwrap();
+            assert_eq!(request.request_id, 5);
+            assert!(matches!(request.request, GameServerRequest::Step { dir: Direction::Up }));
+        }
+    }
+
+    #[test]
+    fn test_binary_encoding_is_compact() {
+        let json_length = Codec::Json.encode(&test_delta()).unwrap().len();
+        let bincode_length = Codec::Bincode.encode(&test_delta()).unwrap().len();
+        assert!(bincode_length < json_length, "Bincode {bincode_length} bytes, JSON {json_length} bytes");
+    }
+
+    #[test]
+    fn test_decoding_with_other_codec_should_fail() {
+        let bytes = Codec::Bincode.encode(&test_delta()).unwrap();
+        assert!(matches!(Codec::Json.decode::<GameServerMessage>(&bytes), Err(CodecError::SerdeJsonError(_))));
+    }
+}
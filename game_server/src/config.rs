@@ -0,0 +1,219 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::time::Duration;
+use serde::Deserialize;
+use accounts_manager::config::{load_layered_table, ConfigError, ConfigResult};
+use crate::game::world::{DEFAULT_TICK_DURATION, DEFAULT_VIEW_RANGE};
+use crate::session::SessionLimits;
+use crate::GameServerOptions;
+
+pub const DEFAULT_PORT: u16 = 7777;
+pub const DEFAULT_WEBSOCKET_PORT: u16 = 7778;
+/// Characters and revoked tokens are shared there with accounts server
+pub const DEFAULT_DATABASE_PATH: &str = accounts_manager::config::DEFAULT_DATABASE_PATH;
+/// `GAME_SERVER_PORT=7779` overrides `port`, `GAME_SERVER_LIMITS__IDLE_TIMEOUT_MS=5000` overrides `limits.idle_timeout_ms`
+pub const ENV_PREFIX: &str = "GAME_SERVER_";
+
+/// Settings of server binary. Defaults fit single machine deployment,
+/// tests run servers with `GameServerOptions::default()` listening on any free ports.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameServerConfig {
+    pub bind_address: IpAddr,
+    pub port: u16,
+    pub websocket_port: u16,
+    pub database_path: PathBuf,
+    pub tick_duration_ms: u64,
+    /// Half of the side of square visible around character, in tiles
+    pub view_range: f32,
+    /// Native JSON map or Tiled export, world has no bounds without it
+    pub map_path: Option<PathBuf>,
+    /// `host:port` of accounts server, tokens are verified with keys it publishes
+    pub accounts_manager_address: String,
+    pub limits: LimitsConfig,
+}
+
+impl Default for GameServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port: DEFAULT_PORT,
+            websocket_port: DEFAULT_WEBSOCKET_PORT,
+            database_path: PathBuf::from(DEFAULT_DATABASE_PATH),
+            tick_duration_ms: DEFAULT_TICK_DURATION.as_millis() as u64,
+            view_range: DEFAULT_VIEW_RANGE,
+            map_path: None,
+            accounts_manager_address: GameServerOptions::default().accounts_manager_address,
+            limits: LimitsConfig::default(),
+        }
+    }
+}
+
+/// See `SessionLimits`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub max_frame_size: usize,
+    pub max_protocol_violations: usize,
+    pub heartbeat_interval_ms: u64,
+    pub idle_timeout_ms: u64,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        let limits = SessionLimits::default();
+        Self {
+            max_frame_size: limits.max_frame_size,
+            max_protocol_violations: limits.max_protocol_violations,
+            heartbeat_interval_ms: limits.heartbeat_interval.as_millis() as u64,
+            idle_timeout_ms: limits.idle_timeout.as_millis() as u64,
+        }
+    }
+}
+
+/// Overrides both config file and environment
+#[derive(Debug, Default, clap::Parser)]
+#[command(version, about = "Game server")]
+pub struct GameServerArgs {
+    /// TOML config file
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+    #[arg(long)]
+    pub bind_address: Option<IpAddr>,
+    #[arg(short, long)]
+    pub port: Option<u16>,
+    #[arg(long)]
+    pub websocket_port: Option<u16>,
+    #[arg(long)]
+    pub database_path: Option<PathBuf>,
+    #[arg(long)]
+    pub tick_duration_ms: Option<u64>,
+    #[arg(long)]
+    pub map_path: Option<PathBuf>,
+    #[arg(long)]
+    pub accounts_manager_address: Option<String>,
+}
+
+impl GameServerConfig {
+    /// Config file given in `args` first, then environment, then `args` themselves
+    pub fn load(args: &GameServerArgs, env: impl IntoIterator<Item = (String, String)>) -> ConfigResult<Self> {
+        let table = load_layered_table(args.config.as_deref(), ENV_PREFIX, env)?;
+        let mut config: Self = toml::Value::Table(table).try_into()?;
+
+        if let Some(bind_address) = args.bind_address {
+            config.bind_address = bind_address;
+        }
+        if let Some(port) = args.port {
+            config.port = port;
+        }
+        if let Some(websocket_port) = args.websocket_port {
+            config.websocket_port = websocket_port;
+        }
+        if let Some(database_path) = &args.database_path {
+            config.database_path = database_path.clone();
+        }
+        if let Some(tick_duration_ms) = args.tick_duration_ms {
+            config.tick_duration_ms = tick_duration_ms;
+        }
+        if let Some(map_path) = &args.map_path {
+            config.map_path = Some(map_path.clone());
+        }
+        if let Some(accounts_manager_address) = &args.accounts_manager_address {
+            config.accounts_manager_address = accounts_manager_address.clone();
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Zero period would panic timers of game loop and heartbeat
+    fn validate(&self) -> ConfigResult<()> {
+        for (key, value) in [("tick_duration_ms", self.tick_duration_ms), ("limits.heartbeat_interval_ms", self.limits.heartbeat_interval_ms)] {
+            if value == 0 {
+                return Err(ConfigError::InvalidValue { key: key.to_string(), reason: "has to be positive".to_string() });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_options(&self) -> GameServerOptions {
+        GameServerOptions {
+            address: SocketAddr::new(self.bind_address, self.port),
+            websocket_address: SocketAddr::new(self.bind_address, self.websocket_port),
+            session_limits: SessionLimits {
+                max_frame_size: self.limits.max_frame_size,
+                max_protocol_violations: self.limits.max_protocol_violations,
+                heartbeat_interval: Duration::from_millis(self.limits.heartbeat_interval_ms),
+                idle_timeout: Duration::from_millis(self.limits.idle_timeout_ms),
+            },
+            tick_duration: Duration::from_millis(self.tick_duration_ms),
+            view_range: self.view_range,
+            map_path: self.map_path.clone(),
+            accounts_manager_address: self.accounts_manager_address.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn test_layering_config() {
+        let path = std::env::temp_dir().join(format!("game_server_config_test_{}.toml", std::process::id()));
+        std::fs::write(&path, r#"
+            bind_address = "0.0.0.0"
+            port = 9000
+            tick_duration_ms = 50
+            map_path = "maps/example.json"
+            accounts_manager_address = "accounts.local:8080"
+
+            [limits]
+            idle_timeout_ms = 20000
+        "#).unwrap();
+
+        let args = GameServerArgs { config: Some(path.clone()), ..Default::default() };
+        let options = GameServerConfig::load(&args, env(&[])).unwrap().to_options();
+        assert_eq!(options.address, "0.0.0.0:9000".parse().unwrap());
+        assert_eq!(options.websocket_address, SocketAddr::new(IpAddr::from([0, 0, 0, 0]), DEFAULT_WEBSOCKET_PORT));
+        assert_eq!(options.tick_duration, Duration::from_millis(50));
+        assert_eq!(options.session_limits.idle_timeout, Duration::from_secs(20));
+        assert_eq!(options.session_limits.heartbeat_interval, SessionLimits::default().heartbeat_interval);
+        assert_eq!(options.map_path, Some(PathBuf::from("maps/example.json")));
+        assert_eq!(options.accounts_manager_address, "accounts.local:8080");
+
+        // Environment overrides file
+        let vars = env(&[("GAME_SERVER_PORT", "9001"), ("GAME_SERVER_LIMITS__IDLE_TIMEOUT_MS", "30000")]);
+        let config = GameServerConfig::load(&args, vars.clone()).unwrap();
+        assert_eq!((config.port, config.limits.idle_timeout_ms), (9001, 30000));
+
+        // Command line overrides both
+        let args = GameServerArgs::try_parse_from(["game_server", "-c", path.to_str().unwrap(), "-p", "9002", "--tick-duration-ms", "16"]).unwrap();
+        let config = GameServerConfig::load(&args, vars).unwrap();
+        assert_eq!((config.port, config.tick_duration_ms, config.limits.idle_timeout_ms), (9002, 16, 30000));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_loading_bad_config_should_fail() {
+        let args = GameServerArgs::default();
+        assert_eq!(GameServerConfig::load(&args, env(&[])).unwrap(), GameServerConfig::default());
+
+        let result = GameServerConfig::load(&args, env(&[("GAME_SERVER_LIMITS__MAX_FRAME_SIZE", "-1")]));
+        assert!(matches!(result, Err(ConfigError::TomlError(_))));
+
+        let result = GameServerConfig::load(&args, env(&[("GAME_SERVER_LIMITS__UNKNOWN", "1")]));
+        assert!(matches!(result, Err(ConfigError::TomlError(_))));
+
+        let result = GameServerConfig::load(&args, env(&[("GAME_SERVER_LIMITS__HEARTBEAT_INTERVAL_MS", "0")]));
+        assert!(matches!(result, Err(ConfigError::InvalidValue { .. })));
+
+        let args = GameServerArgs { tick_duration_ms: Some(0), ..Default::default() };
+        assert!(matches!(GameServerConfig::load(&args, env(&[])), Err(ConfigError::InvalidValue { .. })));
+    }
+}
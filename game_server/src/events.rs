@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+use crate::game::entity::EntityId;
+use crate::replication::{WorldDelta, WorldSnapshot};
+
+/// Pushed by server without any request, only to authenticated sessions.
+/// Replication and entity events go only to sessions attached to character,
+/// about entities within view range of that character.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum GameServerEvent {
+    EntitySpawned {
+        entity_id: EntityId,
+        name: String,
+        x: f32,
+        y: f32,
+    },
+    EntityDespawned {
+        entity_id: EntityId,
+    },
+    /// Entity started moving towards given position
+    EntityMoving {
+        entity_id: EntityId,
+        target_x: f32,
+        target_y: f32,
+    },
+    Chat {
+        from: String,
+        message: String,
+    },
+    ServerNotice {
+        message: String,
+    },
+    /// Sent on attaching to character and on resync request
+    WorldSnapshot {
+        snapshot: WorldSnapshot,
+    },
+    WorldDelta {
+        delta: WorldDelta,
+    },
+    /// Sent after delta in which entity came into view range
+    EntityEnteredView {
+        entity_id: EntityId,
+    },
+    /// Sent after delta in which entity went out of view range, it is no longer replicated
+    EntityLeftView {
+        entity_id: EntityId,
+    },
+}
@@ -0,0 +1,78 @@
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Largest payload accepted unless configured otherwise
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum FrameError {
+    #[error(transparent)]
+    StdIoError(#[from] std::io::Error),
+
+    #[error(transparent)]
+    WebSocketError(#[from] tokio_tungstenite::tungstenite::Error),
+
+    #[error("Frame of {length} bytes exceeds limit of {max_length} bytes")]
+    FrameTooLarge {
+        length: usize,
+        max_length: usize,
+    },
+}
+
+pub type FrameResult<T> = Result<T, FrameError>;
+
+/// Frame is payload prefixed with its length as little endian `u32`.
+/// Too large frame is rejected before its payload gets allocated, stream can't be read further then.
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R, max_frame_size: usize) -> FrameResult<Vec<u8>> {
+    let length = reader.read_u32_le().await? as usize;
+    if length > max_frame_size {
+        return Err(FrameError::FrameTooLarge { length, max_length: max_frame_size });
+    }
+    let mut buffer = vec![0u8; length];
+    reader.read_exact(&mut buffer).await?;
+    Ok(buffer)
+}
+
+pub async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, payload: &[u8], max_frame_size: usize) -> FrameResult<()> {
+    let max_length = max_frame_size.min(u32::MAX as usize);
+    if payload.len() > max_length {
+        return Err(FrameError::FrameTooLarge { length: payload.len(), max_length });
+    }
+    writer.write_u32_le(payload.len() as u32).await?;
+    writer.write_all(payload).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_writing_and_reading_frames() {
+        let mut buffer = Vec::new();
+        write_frame(&mut buffer, b"Janusz", 16).await.unwrap();
+        write_frame(&mut buffer, b"", 16).await.unwrap();
+        let result = write_frame(&mut buffer, &[0u8; 17], 16).await;
+        assert!(matches!(result, Err(FrameError::FrameTooLarge { length: 17, max_length: 16 })));
+
+        let mut reader = buffer.as_slice();
+        assert_eq!(read_frame(&mut reader, 16).await.unwrap(), b"Janusz");
+        assert!(read_frame(&mut reader, 16).await.unwrap().is_empty());
+        assert!(matches!(read_frame(&mut reader, 16).await, Err(FrameError::StdIoError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_reading_malformed_frames_should_fail() {
+        // Claims 4 GiB, nothing gets allocated
+        let mut reader: &[u8] = &[0xff, 0xff, 0xff, 0xff, 1, 2, 3];
+        let result = read_frame(&mut reader, DEFAULT_MAX_FRAME_SIZE).await;
+        assert!(matches!(result, Err(FrameError::FrameTooLarge { length: 0xffff_ffff, .. })));
+
+        // Payload shorter than claimed
+        let mut reader: &[u8] = &[5, 0, 0, 0, 1, 2];
+        assert!(matches!(read_frame(&mut reader, DEFAULT_MAX_FRAME_SIZE).await, Err(FrameError::StdIoError(_))));
+
+        // Length cut off
+        let mut reader: &[u8] = &[5, 0];
+        assert!(matches!(read_frame(&mut reader, DEFAULT_MAX_FRAME_SIZE).await, Err(FrameError::StdIoError(_))));
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use crate::game::entity::EntityId;
+use crate::game::map::TileMap;
+use crate::game::tile_math::TilePosition;
+
+/// Tiles held by blocking entities. Entity holds the tile it stands on,
+/// and while moving also the tile it moves to, so no other entity can step in.
+#[derive(Debug, Default)]
+pub struct TileOccupancy {
+    tiles: HashMap<TilePosition, EntityId>,
+    entities_tiles: HashMap<EntityId, Vec<TilePosition>>,
+}
+
+impl TileOccupancy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Entity starts blocking, standing tile is held only if free
+    pub fn add_entity(&mut self, entity: EntityId, tile: TilePosition) -> bool {
+        self.entities_tiles.entry(entity).or_default();
+        self.claim(entity, tile)
+    }
+
+    pub fn remove_entity(&mut self, entity: EntityId) {
+        for tile in self.entities_tiles.remove(&entity).unwrap_or_default() {
+            self.tiles.remove(&tile);
+        }
+    }
+
+    pub fn is_blocking(&self, entity: EntityId) -> bool {
+        self.entities_tiles.contains_key(&entity)
+    }
+
+    pub fn get_occupant(&self, tile: &TilePosition) -> Option<EntityId> {
+        self.tiles.get(tile).copied()
+    }
+
+    /// Free tiles and tiles already held by entity are free for it
+    pub fn is_free_for(&self, entity: EntityId, tile: &TilePosition) -> bool {
+        self.get_occupant(tile).is_none_or(|occupant| occupant == entity)
+    }
+
+    /// Returns `false` if tile is held by other entity
+    pub fn claim(&mut self, entity: EntityId, tile: TilePosition) -> bool {
+        if !self.is_free_for(entity, &tile) {
+            return false;
+        }
+        if self.tiles.insert(tile, entity).is_none() {
+            self.entities_tiles.entry(entity).or_default().push(tile);
+        }
+        true
+    }
+
+    pub fn release(&mut self, entity: EntityId, tile: &TilePosition) {
+        if self.tiles.get(tile) == Some(&entity) {
+            self.tiles.remove(tile);
+            if let Some(tiles) = self.entities_tiles.get_mut(&entity) {
+                tiles.retain(|held_tile| held_tile != tile);
+            }
+        }
+    }
+
+    /// Releases every held tile except `tile`, which gets held
+    pub fn settle(&mut self, entity: EntityId, tile: TilePosition) {
+        let held_tiles = self.entities_tiles.get(&entity).cloned().unwrap_or_default();
+        for held_tile in held_tiles.iter().filter(|held_tile| **held_tile != tile) {
+            self.release(entity, held_tile);
+        }
+        self.claim(entity, tile);
+    }
+}
+
+/// World without map is walkable everywhere
+pub fn is_walkable(map: Option<&TileMap>, tile: &TilePosition) -> bool {
+    map.is_none_or(|map| map.is_walkable(tile.0, tile.1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claiming_tiles() {
+        let mut occupancy = TileOccupancy::new();
+        assert!(occupancy.add_entity(1, (0, 0)));
+        assert!(!occupancy.add_entity(2, (0, 0)));
+        assert!(occupancy.is_blocking(2));
+        assert!(!occupancy.is_blocking(3));
+
+        assert!(occupancy.claim(1, (1, 0)));
+        assert!(!occupancy.claim(2, (1, 0)));
+        assert!(occupancy.claim(2, (0, 1)));
+
+        occupancy.settle(1, (1, 0));
+        assert_eq!(occupancy.get_occupant(&(0, 0)), None);
+        assert_eq!(occupancy.get_occupant(&(1, 0)), Some(1));
+
+        occupancy.release(2, &(1, 0));
+        assert_eq!(occupancy.get_occupant(&(1, 0)), Some(1));
+
+        occupancy.remove_entity(1);
+        assert!(occupancy.is_free_for(2, &(1, 0)));
+        assert!(occupancy.is_free_for(2, &(0, 1)));
+        assert!(!occupancy.is_free_for(3, &(0, 1)));
+    }
+}
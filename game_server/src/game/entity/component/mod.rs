@@ -1,20 +1,15 @@
 pub mod movement_component;
 pub mod position_component;
 pub mod name_component;
+pub mod storage;
 
 pub use movement_component::MovementComponent;
 pub use position_component::PositionComponent;
 pub use name_component::NameComponent;
+pub use storage::ComponentStorage;
 
 use std::any::Any;
-use crate::game::entity::EntityId;
 use crate::game::world::World;
 
-pub trait Component {
-    fn as_any(&self) -> &dyn Any;
-
-    fn as_mut_any(&mut self) -> &mut dyn Any;
-
-    /// Force attaching to Entity
-    fn get_entity_id(&self) -> EntityId;
-}
+/// Data of single aspect of entity, kept in `ComponentStorage` by its entity
+pub trait Component: Any + Send {}
@@ -1,10 +1,10 @@
-use std::any::Any;
 use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::rc::Weak;
-use crate::game::entity::EntityId;
 use crate::game::entity::component::Component;
 use crate::game::math::Vec2F;
 use crate::game::world::World;
+use crate::requests::Direction;
 
 #[derive(Debug)]
 pub struct MovementState {
@@ -21,35 +21,33 @@ impl MovementState {
 
 #[derive(Debug)]
 pub struct MovementComponent {
-    entity_id: EntityId,
-    pub target: Option<(Vec2F, Option<MovementState>)>,
+    /// Neighbouring tiles to step on, one after another
+    pub steps: VecDeque<Vec2F>,
+    /// Progress of the first step, `None` until it starts
+    pub state: Option<MovementState>,
+    /// Direction of the last step
+    pub facing: Direction,
     pub speed: f32,
 }
 
 impl MovementComponent {
-    pub fn new(entity_id: EntityId, speed: f32) -> Self {
+    pub fn new(speed: f32) -> Self {
         Self {
-            entity_id,
-            target: None,
+            steps: VecDeque::new(),
+            state: None,
+            facing: Direction::Down,
             speed
         }
     }
 
     pub fn is_moving(&self) -> bool {
-        self.target.is_some()
-    }
-}
-
-impl Component for MovementComponent {
-    fn as_any(&self) -> &dyn Any {
-        self
+        !self.steps.is_empty()
     }
 
-    fn as_mut_any(&mut self) -> &mut dyn Any {
-        self
+    /// Tile where entity stops
+    pub fn get_destination(&self) -> Option<&Vec2F> {
+        self.steps.back()
     }
+}
 
-    fn get_entity_id(&self) -> EntityId {
-        self.entity_id
-    }
-}
\ No newline at end of file
+impl Component for MovementComponent {}
\ No newline at end of file
@@ -0,0 +1,163 @@
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use crate::game::entity::component::Component;
+use crate::game::entity::EntityId;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ComponentError {
+    #[error("Component {component} already added to entity {entity_id}")]
+    ComponentAlreadyAdded {
+        entity_id: EntityId,
+        component: &'static str,
+    },
+}
+
+pub type ComponentResult<T> = Result<T, ComponentError>;
+
+type Column<C> = HashMap<EntityId, C>;
+
+/// Components of single type, type erased so storage can hold any number of them
+trait AnyColumn: Any + Send {
+    fn remove_entity(&mut self, entity: &EntityId);
+}
+
+impl<C: Component> AnyColumn for Column<C> {
+    fn remove_entity(&mut self, entity: &EntityId) {
+        self.remove(entity);
+    }
+}
+
+/// Components of all entities, one column per component type
+#[derive(Default)]
+pub struct ComponentStorage {
+    columns: HashMap<TypeId, Box<dyn AnyColumn>>,
+}
+
+impl ComponentStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn column<C: Component>(&self) -> Option<&Column<C>> {
+        let column: &dyn Any = self.columns.get(&TypeId::of::<C>())?.as_ref();
+        column.downcast_ref()
+    }
+
+    fn column_mut<C: Component>(&mut self) -> Option<&mut Column<C>> {
+        let column: &mut dyn Any = self.columns.get_mut(&TypeId::of::<C>())?.as_mut();
+        column.downcast_mut()
+    }
+
+    pub fn insert<C: Component>(&mut self, entity: EntityId, component: C) -> ComponentResult<()> {
+        let column: &mut dyn Any = self.columns
+            .entry(TypeId::of::<C>())
+            .or_insert_with(|| Box::new(Column::<C>::new()))
+            .as_mut();
+        // Safe unwrap - column is stored under its component type
+        let column = column.downcast_mut::<Column<C>>().unwrap();
+
+        if column.contains_key(&entity) {
+            return Err(ComponentError::ComponentAlreadyAdded { entity_id: entity, component: type_name::<C>() });
+        }
+        column.insert(entity, component);
+        Ok(())
+    }
+
+    pub fn get<C: Component>(&self, entity: &EntityId) -> Option<&C> {
+        self.column::<C>()?.get(entity)
+    }
+
+    pub fn get_mut<C: Component>(&mut self, entity: &EntityId) -> Option<&mut C> {
+        self.column_mut::<C>()?.get_mut(entity)
+    }
+
+    pub fn remove<C: Component>(&mut self, entity: &EntityId) -> Option<C> {
+        self.column_mut::<C>()?.remove(entity)
+    }
+
+    /// Removes every component of entity, no matter the type
+    pub fn remove_all(&mut self, entity: &EntityId) {
+        for column in self.columns.values_mut() {
+            column.remove_entity(entity);
+        }
+    }
+
+    /// Entities having both components, borrowed mutably.
+    /// Panics if `A` and `B` are the same type.
+    pub fn query2_mut<A: Component, B: Component>(&mut self) -> impl Iterator<Item = (EntityId, &mut A, &mut B)> {
+        let [a_column, b_column] = self.columns.get_disjoint_mut([&TypeId::of::<A>(), &TypeId::of::<B>()]);
+        let a_column = a_column.and_then(|column| (column.as_mut() as &mut dyn Any).downcast_mut::<Column<A>>());
+        let b_column = b_column.and_then(|column| (column.as_mut() as &mut dyn Any).downcast_mut::<Column<B>>());
+
+        a_column
+            .into_iter()
+            .zip(b_column)
+            .flat_map(|(a_column, b_column)| {
+                let mut a_components: HashMap<EntityId, &mut A> = a_column
+                    .iter_mut()
+                    .map(|(entity, a)| (*entity, a))
+                    .collect();
+                b_column
+                    .iter_mut()
+                    .filter_map(move |(entity, b)| a_components.remove(entity).map(|a| (*entity, a, b)))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::entity::component::{MovementComponent, NameComponent, PositionComponent};
+    use crate::game::math::Vec2F;
+    use super::*;
+
+    #[test]
+    fn test_inserting_and_removing_components() {
+        let mut storage = ComponentStorage::new();
+        storage.insert(1, PositionComponent::new(Vec2F::new(1.0, 2.0))).unwrap();
+        storage.insert(1, NameComponent::new("Janusz".to_string())).unwrap();
+        storage.insert(2, NameComponent::new("Tuna".to_string())).unwrap();
+
+        let result = storage.insert(1, NameComponent::new("Tuna".to_string()));
+        assert!(matches!(result, Err(ComponentError::ComponentAlreadyAdded { entity_id: 1, .. })));
+        assert_eq!(storage.get::<NameComponent>(&1).unwrap().get_name(), "Janusz");
+        assert!(storage.get::<MovementComponent>(&1).is_none());
+
+        storage.get_mut::<PositionComponent>(&1).unwrap().set_position(Vec2F::new(3.0, 3.0));
+        assert!(storage.get::<PositionComponent>(&1).unwrap().get_position().approx_eq(&Vec2F::new(3.0, 3.0)));
+
+        assert_eq!(storage.remove::<NameComponent>(&2).unwrap().get_name(), "Tuna");
+        assert!(storage.remove::<NameComponent>(&2).is_none());
+
+        storage.remove_all(&1);
+        assert!(storage.get::<PositionComponent>(&1).is_none());
+        assert!(storage.get::<NameComponent>(&1).is_none());
+    }
+
+    #[test]
+    fn test_querying_joined_components() {
+        let mut storage = ComponentStorage::new();
+        for entity in 0..10 {
+            storage.insert(entity, PositionComponent::new(Vec2F::new(0.0, 0.0))).unwrap();
+            if entity % 2 == 0 {
+                storage.insert(entity, MovementComponent::new(1.0)).unwrap();
+            }
+        }
+        assert_eq!(storage.query2_mut::<PositionComponent, NameComponent>().count(), 0);
+
+        for (entity, movement, position) in storage.query2_mut::<MovementComponent, PositionComponent>() {
+            movement.speed = 2.0;
+            position.set_position(Vec2F::new(entity as f32, 0.0));
+        }
+
+        for entity in 0..10 {
+            let position = storage.get::<PositionComponent>(&entity).unwrap().get_position();
+            match storage.get::<MovementComponent>(&entity) {
+                Some(movement) => {
+                    assert_eq!(movement.speed, 2.0);
+                    assert!(position.approx_eq(&Vec2F::new(entity as f32, 0.0)));
+                },
+                None => assert!(position.approx_eq(&Vec2F::new(0.0, 0.0))),
+            }
+        }
+    }
+}
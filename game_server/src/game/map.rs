@@ -0,0 +1,680 @@
+//! Tile map of the world, loaded from a data file.
+//!
+//! Native format is JSON, coordinates are in tiles, `y` grows downwards:
+//!
+//! ```json
+//! {
+//!     "width": 3,
+//!     "height": 2,
+//!     "origin": [-1, 0],
+//!     "tile_types": {
+//!         "1": { "ground": "grass", "walkable": true },
+//!         "2": { "ground": "water", "walkable": false }
+//!     },
+//!     "layers": [
+//!         { "name": "ground", "tiles": [1, 1, 2, 1, 1, 2] },
+//!         { "name": "decorations", "tiles": [0, 0, 0, 0, 0, 0] }
+//!     ],
+//!     "spawn_points": [{ "name": "start", "x": 0, "y": 1 }],
+//!     "regions": [{ "name": "lake", "x": 1, "y": 0, "width": 1, "height": 2 }]
+//! }
+//! ```
+//!
+//! - `origin` - world position of the top left tile, defaults to `[0, 0]`
+//! - `layers` - `width * height` tile type IDs each, row by row, `0` means no tile
+//! - tile is walkable when any layer has a tile there and all tiles there are walkable
+//! - spawn points and regions use world positions
+//!
+//! Files with `.tmj` extension are read as Tiled JSON export, see `TileMap::from_tiled_str`.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use serde_json::Value;
+
+pub type TileTypeId = u32;
+
+/// Tile type ID meaning no tile on the layer
+pub const EMPTY_TILE: TileTypeId = 0;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MapError {
+    #[error("Could not read map file '{path}', reason: '{source}'")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Invalid map at line {line} column {column}, reason: '{message}'")]
+    Parse {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+
+    #[error("Map has no tiles")]
+    EmptyMap,
+
+    #[error("Map of {width}x{height} tiles at ({origin_x}, {origin_y}) is too large")]
+    MapTooLarge {
+        width: u32,
+        height: u32,
+        origin_x: i32,
+        origin_y: i32,
+    },
+
+    #[error("Layer '{layer}' has {actual} tiles, expected {expected}")]
+    LayerSizeMismatch {
+        layer: String,
+        expected: usize,
+        actual: usize,
+    },
+
+    #[error("Layer '{layer}' has unknown tile type {tile_type} at ({x}, {y})")]
+    UnknownTileType {
+        layer: String,
+        tile_type: TileTypeId,
+        x: i32,
+        y: i32,
+    },
+
+    #[error("Duplicated {kind} '{name}'")]
+    DuplicateName {
+        kind: &'static str,
+        name: String,
+    },
+
+    #[error("Spawn point '{name}' at ({x}, {y}) is not walkable")]
+    SpawnPointNotWalkable {
+        name: String,
+        x: i32,
+        y: i32,
+    },
+
+    #[error("Region '{name}' is empty or lays outside of the map")]
+    RegionOutOfBounds {
+        name: String,
+    },
+
+    #[error("Unsupported Tiled map, reason: '{0}'")]
+    UnsupportedTiled(String),
+
+    #[error("Tile {tile_id} of tileset '{tileset}' has invalid property '{property}', reason: '{message}'")]
+    InvalidTiledProperty {
+        tileset: String,
+        tile_id: u32,
+        property: String,
+        message: String,
+    },
+}
+
+pub type MapResult<T> = Result<T, MapError>;
+
+impl From<serde_json::Error> for MapError {
+    fn from(e: serde_json::Error) -> Self {
+        MapError::Parse { line: e.line(), column: e.column(), message: e.to_string() }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroundType {
+    Grass,
+    Dirt,
+    Sand,
+    Stone,
+    Wood,
+    Water,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct TileType {
+    pub ground: GroundType,
+    pub walkable: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MapLayer {
+    pub name: String,
+    tiles: Vec<TileTypeId>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SpawnPoint {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Region {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x
+            && y >= self.y
+            && (x as i64) < self.x as i64 + self.width as i64
+            && (y as i64) < self.y as i64 + self.height as i64
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TileMap {
+    width: u32,
+    height: u32,
+    #[serde(default)]
+    origin: (i32, i32),
+    tile_types: HashMap<TileTypeId, TileType>,
+    layers: Vec<MapLayer>,
+    #[serde(default)]
+    spawn_points: Vec<SpawnPoint>,
+    #[serde(default)]
+    regions: Vec<Region>,
+}
+
+impl TileMap {
+    /// Reads Tiled export for `.tmj` extension, native format otherwise
+    pub fn load(path: impl AsRef<Path>) -> MapResult<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|source| MapError::Io { path: path.to_path_buf(), source })?;
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("tmj") => Self::from_tiled_str(&content),
+            _ => Self::from_json_str(&content),
+        }
+    }
+
+    pub fn from_json_str(content: &str) -> MapResult<Self> {
+        let map: TileMap = serde_json::from_str(content)?;
+        map.validate()?;
+        Ok(map)
+    }
+
+    /// Reads JSON export of Tiled map editor. Supported are orthogonal, finite maps
+    /// with CSV layer data and embedded tilesets.
+    ///
+    /// - tiles need `ground` string and optionally `walkable` bool custom properties, walkable by default
+    /// - point objects are spawn points, other objects are regions, positions get aligned to tiles
+    /// - `origin_x` and `origin_y` int custom properties of map set its origin
+    pub fn from_tiled_str(content: &str) -> MapResult<Self> {
+        let tiled: TiledMap = serde_json::from_str(content)?;
+        let map = tiled.into_tile_map()?;
+        map.validate()?;
+        Ok(map)
+    }
+
+    fn validate(&self) -> MapResult<()> {
+        // Positions of all tiles have to fit in i32, so no position arithmetic overflows later
+        let (min_x, min_y) = self.origin;
+        let (max_x, max_y) = (min_x as i64 + self.width as i64, min_y as i64 + self.height as i64);
+        let tiles_count = match self.width.checked_mul(self.height) {
+            Some(tiles_count) if max_x <= i32::MAX as i64 && max_y <= i32::MAX as i64 => tiles_count as usize,
+            _ => return Err(MapError::MapTooLarge { width: self.width, height: self.height, origin_x: min_x, origin_y: min_y }),
+        };
+        if tiles_count == 0 {
+            return Err(MapError::EmptyMap);
+        }
+
+        let mut layers_names = HashSet::new();
+        for layer in &self.layers {
+            if !layers_names.insert(layer.name.as_str()) {
+                return Err(MapError::DuplicateName { kind: "layer", name: layer.name.clone() });
+            }
+            if layer.tiles.len() != tiles_count {
+                return Err(MapError::LayerSizeMismatch {
+                    layer: layer.name.clone(),
+                    expected: tiles_count,
+                    actual: layer.tiles.len(),
+                });
+            }
+            let unknown_tile = layer.tiles
+                .iter()
+                .enumerate()
+                .find(|(_, tile_type)| **tile_type != EMPTY_TILE && !self.tile_types.contains_key(tile_type));
+            if let Some((index, tile_type)) = unknown_tile {
+                let (x, y) = self.index_to_position(index);
+                return Err(MapError::UnknownTileType { layer: layer.name.clone(), tile_type: *tile_type, x, y });
+            }
+        }
+
+        let mut spawn_points_names = HashSet::new();
+        for spawn_point in &self.spawn_points {
+            if !spawn_points_names.insert(spawn_point.name.as_str()) {
+                return Err(MapError::DuplicateName { kind: "spawn point", name: spawn_point.name.clone() });
+            }
+            if !self.is_walkable(spawn_point.x, spawn_point.y) {
+                return Err(MapError::SpawnPointNotWalkable {
+                    name: spawn_point.name.clone(),
+                    x: spawn_point.x,
+                    y: spawn_point.y,
+                });
+            }
+        }
+
+        let mut regions_names = HashSet::new();
+        for region in &self.regions {
+            if !regions_names.insert(region.name.as_str()) {
+                return Err(MapError::DuplicateName { kind: "region", name: region.name.clone() });
+            }
+            let fits = region.width > 0
+                && region.height > 0
+                && region.x >= min_x
+                && region.y >= min_y
+                && region.x as i64 + region.width as i64 <= max_x
+                && region.y as i64 + region.height as i64 <= max_y;
+            if !fits {
+                return Err(MapError::RegionOutOfBounds { name: region.name.clone() });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn index_to_position(&self, index: usize) -> (i32, i32) {
+        let x = (index % self.width as usize) as i32 + self.origin.0;
+        let y = (index / self.width as usize) as i32 + self.origin.1;
+        (x, y)
+    }
+
+    fn position_to_index(&self, x: i32, y: i32) -> Option<usize> {
+        let local_x = x.checked_sub(self.origin.0)?;
+        let local_y = y.checked_sub(self.origin.1)?;
+        if local_x < 0 || local_y < 0 || local_x >= self.width as i32 || local_y >= self.height as i32 {
+            return None;
+        }
+        Some(local_y as usize * self.width as usize + local_x as usize)
+    }
+
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn get_origin(&self) -> (i32, i32) {
+        self.origin
+    }
+
+    pub fn get_layers(&self) -> &[MapLayer] {
+        &self.layers
+    }
+
+    /// Tiles of all layers at position, bottom layer first
+    pub fn get_tiles(&self, x: i32, y: i32) -> impl Iterator<Item = &TileType> {
+        let index = self.position_to_index(x, y);
+        self.layers
+            .iter()
+            .filter_map(move |layer| layer.tiles.get(index?))
+            .filter_map(|tile_type| self.tile_types.get(tile_type))
+    }
+
+    /// Ground of the bottom layer tile at position
+    pub fn get_ground(&self, x: i32, y: i32) -> Option<GroundType> {
+        self.get_tiles(x, y).next().map(|tile| tile.ground)
+    }
+
+    /// Positions outside of the map and without any tile are not walkable
+    pub fn is_walkable(&self, x: i32, y: i32) -> bool {
+        let mut tiles = self.get_tiles(x, y).peekable();
+        tiles.peek().is_some() && tiles.all(|tile| tile.walkable)
+    }
+
+    pub fn get_spawn_points(&self) -> &[SpawnPoint] {
+        &self.spawn_points
+    }
+
+    pub fn get_spawn_point(&self, name: &str) -> Option<&SpawnPoint> {
+        self.spawn_points.iter().find(|spawn_point| spawn_point.name == name)
+    }
+
+    pub fn get_region(&self, name: &str) -> Option<&Region> {
+        self.regions.iter().find(|region| region.name == name)
+    }
+
+    pub fn get_regions_at(&self, x: i32, y: i32) -> impl Iterator<Item = &Region> {
+        self.regions.iter().filter(move |region| region.contains(x, y))
+    }
+}
+
+/// Flip flags stored in the highest bits of Tiled tile IDs
+const TILED_FLIP_FLAGS_MASK: u32 = 0xF000_0000;
+
+#[derive(Deserialize)]
+struct TiledMap {
+    width: u32,
+    height: u32,
+    tilewidth: u32,
+    tileheight: u32,
+    orientation: String,
+    #[serde(default)]
+    infinite: bool,
+    layers: Vec<TiledLayer>,
+    tilesets: Vec<TiledTileset>,
+    #[serde(default)]
+    properties: Vec<TiledProperty>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum TiledLayer {
+    #[serde(rename = "tilelayer")]
+    Tiles {
+        name: String,
+        data: Option<Vec<u32>>,
+        encoding: Option<String>,
+    },
+    #[serde(rename = "objectgroup")]
+    Objects {
+        objects: Vec<TiledObject>,
+    },
+    #[serde(rename = "group")]
+    Group,
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct TiledObject {
+    name: String,
+    x: f32,
+    y: f32,
+    #[serde(default)]
+    width: f32,
+    #[serde(default)]
+    height: f32,
+    #[serde(default)]
+    point: bool,
+}
+
+#[derive(Deserialize)]
+struct TiledTileset {
+    #[serde(default)]
+    name: String,
+    firstgid: u32,
+    source: Option<String>,
+    #[serde(default)]
+    tiles: Vec<TiledTile>,
+}
+
+#[derive(Deserialize)]
+struct TiledTile {
+    id: u32,
+    #[serde(default)]
+    properties: Vec<TiledProperty>,
+}
+
+#[derive(Deserialize)]
+struct TiledProperty {
+    name: String,
+    value: Value,
+}
+
+fn find_tiled_property<'a>(properties: &'a [TiledProperty], name: &str) -> Option<&'a Value> {
+    properties.iter().find(|property| property.name == name).map(|property| &property.value)
+}
+
+impl TiledTileset {
+    fn tile_types(&self) -> MapResult<impl Iterator<Item = (TileTypeId, TileType)> + '_> {
+        if let Some(source) = &self.source {
+            return Err(MapError::UnsupportedTiled(format!("external tileset '{source}'")));
+        }
+
+        let invalid_property = |tile: &TiledTile, property: &str, message: String| MapError::InvalidTiledProperty {
+            tileset: self.name.clone(),
+            tile_id: tile.id,
+            property: property.to_string(),
+            message,
+        };
+
+        let tile_types = self.tiles
+            .iter()
+            .map(|tile| {
+                let ground = find_tiled_property(&tile.properties, "ground")
+                    .ok_or_else(|| invalid_property(tile, "ground", "missing".to_string()))?;
+                let ground = GroundType::deserialize(ground)
+                    .map_err(|e| invalid_property(tile, "ground", e.to_string()))?;
+                let walkable = match find_tiled_property(&tile.properties, "walkable") {
+                    Some(walkable) => walkable
+                        .as_bool()
+                        .ok_or_else(|| invalid_property(tile, "walkable", "not a bool".to_string()))?,
+                    None => true,
+                };
+                Ok((self.firstgid + tile.id, TileType { ground, walkable }))
+            })
+            .collect::<MapResult<Vec<_>>>()?;
+        Ok(tile_types.into_iter())
+    }
+}
+
+impl TiledMap {
+    fn into_tile_map(self) -> MapResult<TileMap> {
+        if self.orientation != "orthogonal" {
+            return Err(MapError::UnsupportedTiled(format!("'{}' orientation", self.orientation)));
+        }
+        if self.infinite {
+            return Err(MapError::UnsupportedTiled("infinite map".to_string()));
+        }
+        if self.tilewidth == 0 || self.tileheight == 0 {
+            return Err(MapError::EmptyMap);
+        }
+
+        let origin_property = |name: &str| find_tiled_property(&self.properties, name)
+            .and_then(Value::as_i64)
+            .unwrap_or(0) as i32;
+        let origin = (origin_property("origin_x"), origin_property("origin_y"));
+
+        let mut tile_types = HashMap::new();
+        for tileset in &self.tilesets {
+            tile_types.extend(tileset.tile_types()?);
+        }
+
+        let tile_width = self.tilewidth as f32;
+        let tile_height = self.tileheight as f32;
+        let mut layers = Vec::new();
+        let mut spawn_points = Vec::new();
+        let mut regions = Vec::new();
+        for layer in self.layers {
+            match layer {
+                TiledLayer::Tiles { name, data, encoding } => {
+                    if encoding.is_some_and(|encoding| encoding != "csv") {
+                        return Err(MapError::UnsupportedTiled(format!("encoded data of layer '{name}'")));
+                    }
+                    let data = data.ok_or_else(|| MapError::UnsupportedTiled(format!("layer '{name}' without data")))?;
+                    let tiles = data.into_iter().map(|tile_id| tile_id & !TILED_FLIP_FLAGS_MASK).collect();
+                    layers.push(MapLayer { name, tiles });
+                },
+                TiledLayer::Objects { objects } => {
+                    for object in objects {
+                        let x = (object.x / tile_width).floor() as i32 + origin.0;
+                        let y = (object.y / tile_height).floor() as i32 + origin.1;
+                        if object.point {
+                            spawn_points.push(SpawnPoint { name: object.name, x, y });
+                        } else {
+                            let width = (object.width / tile_width).round() as u32;
+                            let height = (object.height / tile_height).round() as u32;
+                            regions.push(Region { name: object.name, x, y, width, height });
+                        }
+                    }
+                },
+                TiledLayer::Group => return Err(MapError::UnsupportedTiled("group layer".to_string())),
+                TiledLayer::Other => {},
+            }
+        }
+
+        Ok(TileMap {
+            width: self.width,
+            height: self.height,
+            origin,
+            tile_types,
+            layers,
+            spawn_points,
+            regions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_MAP: &str = r#"{
+        "width": 3,
+        "height": 2,
+        "origin": [-1, 0],
+        "tile_types": {
+            "1": { "ground": "grass", "walkable": true },
+            "2": { "ground": "water", "walkable": false },
+            "3": { "ground": "wood", "walkable": false }
+        },
+        "layers": [
+            { "name": "ground", "tiles": [1, 1, 2, 1, 1, 2] },
+            { "name": "decorations", "tiles": [0, 3, 0, 0, 0, 0] }
+        ],
+        "spawn_points": [{ "name": "start", "x": 0, "y": 1 }],
+        "regions": [{ "name": "lake", "x": 1, "y": 0, "width": 1, "height": 2 }]
+    }"#;
+
+    fn test_map_with(field: &str, value: Value) -> String {
+        let mut map: Value = serde_json::from_str(TEST_MAP).unwrap();
+        map[field] = value;
+        map.to_string()
+    }
+
+    #[test]
+    fn test_loading_map() {
+        let map = TileMap::from_json_str(TEST_MAP).unwrap();
+        assert_eq!((map.get_width(), map.get_height(), map.get_origin()), (3, 2, (-1, 0)));
+        assert_eq!(map.get_layers().len(), 2);
+
+        assert_eq!(map.get_ground(-1, 0), Some(GroundType::Grass));
+        assert_eq!(map.get_ground(1, 1), Some(GroundType::Water));
+        assert_eq!(map.get_ground(2, 0), None);
+
+        assert!(map.is_walkable(-1, 0));
+        assert!(!map.is_walkable(0, 0), "Decoration blocks grass below");
+        assert!(map.is_walkable(0, 1));
+        assert!(!map.is_walkable(1, 1));
+        assert!(!map.is_walkable(-2, 0));
+        assert!(!map.is_walkable(0, 2));
+        assert!(!map.is_walkable(i32::MAX, i32::MIN));
+
+        assert_eq!(map.get_spawn_point("start"), Some(&SpawnPoint { name: "start".to_string(), x: 0, y: 1 }));
+        assert!(map.get_spawn_point("other").is_none());
+        assert_eq!(map.get_regions_at(1, 1).map(|region| region.name.as_str()).collect::<Vec<_>>(), vec!["lake"]);
+        assert_eq!(map.get_regions_at(0, 1).count(), 0);
+        assert!(map.get_region("lake").unwrap().contains(1, 0));
+    }
+
+    #[test]
+    fn test_loading_invalid_map_should_fail() {
+        let result = TileMap::from_json_str("{\n  \"width\": 3,\n  \"height\": }");
+        assert!(matches!(result, Err(MapError::Parse { line: 3, .. })));
+
+        let result = TileMap::from_json_str(&test_map_with("width", 0.into()));
+        assert!(matches!(result, Err(MapError::EmptyMap)));
+
+        let map = test_map_with("width", 65536.into()).replace(r#""height":2"#, r#""height":65536"#);
+        let result = TileMap::from_json_str(&map);
+        assert!(matches!(result, Err(MapError::MapTooLarge { width: 65536, height: 65536, .. })));
+
+        let result = TileMap::from_json_str(&test_map_with("origin", serde_json::json!([i32::MAX - 1, 0])));
+        assert!(matches!(result, Err(MapError::MapTooLarge { width: 3, .. })));
+
+        let layers = serde_json::json!([{ "name": "ground", "tiles": [1, 1, 2] }]);
+        let result = TileMap::from_json_str(&test_map_with("layers", layers));
+        assert!(matches!(result, Err(MapError::LayerSizeMismatch { expected: 6, actual: 3, .. })));
+
+        let layers = serde_json::json!([{ "name": "ground", "tiles": [1, 1, 2, 1, 7, 2] }]);
+        let result = TileMap::from_json_str(&test_map_with("layers", layers));
+        assert!(matches!(result, Err(MapError::UnknownTileType { tile_type: 7, x: 0, y: 1, .. })));
+
+        let spawn_points = serde_json::json!([{ "name": "start", "x": 1, "y": 1 }]);
+        let result = TileMap::from_json_str(&test_map_with("spawn_points", spawn_points));
+        assert!(matches!(result, Err(MapError::SpawnPointNotWalkable { x: 1, y: 1, .. })));
+
+        let regions = serde_json::json!([{ "name": "lake", "x": 1, "y": 0, "width": 2, "height": 1 }]);
+        let result = TileMap::from_json_str(&test_map_with("regions", regions));
+        assert!(matches!(result, Err(MapError::RegionOutOfBounds { .. })));
+
+        let regions = serde_json::json!([{ "name": "lake", "x": 1, "y": 0, "width": u32::MAX, "height": 1 }]);
+        let result = TileMap::from_json_str(&test_map_with("regions", regions));
+        assert!(matches!(result, Err(MapError::RegionOutOfBounds { .. })));
+
+        let regions = serde_json::json!([
+            { "name": "lake", "x": 1, "y": 0, "width": 1, "height": 1 },
+            { "name": "lake", "x": 1, "y": 1, "width": 1, "height": 1 }
+        ]);
+        let result = TileMap::from_json_str(&test_map_with("regions", regions));
+        assert!(matches!(result, Err(MapError::DuplicateName { kind: "region", .. })));
+    }
+
+    const TEST_TILED_MAP: &str = r#"{
+        "type": "map",
+        "orientation": "orthogonal",
+        "infinite": false,
+        "width": 2,
+        "height": 2,
+        "tilewidth": 32,
+        "tileheight": 32,
+        "properties": [{ "name": "origin_x", "type": "int", "value": -1 }],
+        "tilesets": [{
+            "name": "terrain",
+            "firstgid": 1,
+            "tiles": [
+                { "id": 0, "properties": [{ "name": "ground", "type": "string", "value": "grass" }] },
+                { "id": 1, "properties": [
+                    { "name": "ground", "type": "string", "value": "stone" },
+                    { "name": "walkable", "type": "bool", "value": false }
+                ]}
+            ]
+        }],
+        "layers": [
+            { "type": "tilelayer", "name": "ground", "width": 2, "height": 2, "data": [1, 1, 2147483649, 2] },
+            { "type": "objectgroup", "name": "markers", "objects": [
+                { "name": "start", "x": 8, "y": 40, "width": 0, "height": 0, "point": true },
+                { "name": "yard", "x": 0, "y": 0, "width": 64, "height": 32 }
+            ]},
+            { "type": "imagelayer", "name": "background" }
+        ]
+    }"#;
+
+    #[test]
+    fn test_loading_tiled_map() {
+        let map = TileMap::from_tiled_str(TEST_TILED_MAP).unwrap();
+        assert_eq!(map.get_origin(), (-1, 0));
+        assert_eq!(map.get_ground(-1, 1), Some(GroundType::Grass), "Flip flags are ignored");
+        assert_eq!(map.get_ground(0, 1), Some(GroundType::Stone));
+        assert!(map.is_walkable(-1, 0));
+        assert!(!map.is_walkable(0, 1));
+
+        assert_eq!(map.get_spawn_point("start"), Some(&SpawnPoint { name: "start".to_string(), x: -1, y: 1 }));
+        assert_eq!(map.get_region("yard"), Some(&Region { name: "yard".to_string(), x: -1, y: 0, width: 2, height: 1 }));
+
+        let invalid_tiled_map = TEST_TILED_MAP.replace("\"stone\"", "\"lava\"");
+        let result = TileMap::from_tiled_str(&invalid_tiled_map);
+        assert!(matches!(result, Err(MapError::InvalidTiledProperty { tile_id: 1, .. })));
+
+        let infinite_tiled_map = TEST_TILED_MAP.replace("\"infinite\": false", "\"infinite\": true");
+        assert!(matches!(TileMap::from_tiled_str(&infinite_tiled_map), Err(MapError::UnsupportedTiled(_))));
+    }
+
+    #[test]
+    fn test_loading_example_map_file() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("maps/example.json");
+        let map = TileMap::load(&path).unwrap();
+        let spawn_point = map.get_spawn_point("start").unwrap();
+        assert!(map.is_walkable(spawn_point.x, spawn_point.y));
+
+        let result = TileMap::load(path.with_file_name("missing.json"));
+        assert!(matches!(result, Err(MapError::Io { .. })));
+    }
+}
@@ -1,18 +1,26 @@
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use database_adapter::character::CharacterId;
+use tokio::sync::{broadcast, mpsc, Mutex};
+use tokio::task::JoinHandle;
+use database_adapter::character::{CharacterData, CharacterId};
 use database_adapter::{DatabaseAdapter, DatabaseAdapterError};
+use crate::events::GameServerEvent;
 use crate::game::entity::EntityId;
-use crate::game::world::{WorldError, WorldManager};
+use crate::game::math::Vec2F;
+use crate::game::world::{WorldError, WorldManager, WorldOptions, AUTOSAVE_INTERVAL};
+use crate::requests::Direction;
 use crate::session::ConnectionSessionId;
 
 pub mod world;
 pub mod player;
 pub mod entity;
+pub mod map;
 
-mod math;
+pub mod math;
 mod tile_math;
+mod collision;
+mod pathfinding;
+mod spatial_grid;
 mod system;
 /// Ideas
 /// - client is not directly related to player
@@ -28,6 +36,32 @@ pub enum GameError {
         entity_id: EntityId,
     },
 
+    #[error("Session not attached to entity")]
+    SessionNotAttachedToEntity,
+
+    #[error("Entity is not a character")]
+    EntityIsNotCharacter {
+        entity_id: EntityId,
+    },
+
+    #[error("Chat message is empty")]
+    ChatMessageEmpty,
+
+    #[error("Chat message longer than {max_length} characters")]
+    ChatMessageTooLong {
+        max_length: usize,
+    },
+
+    #[error("Character is already in world")]
+    CharacterAlreadyInWorld {
+        character_id: CharacterId,
+    },
+
+    #[error("Entity has no position")]
+    EntityHasNoPosition {
+        entity_id: EntityId,
+    },
+
     // #[error("Character not found")]
     // CharacterNotFound,
     
@@ -40,53 +74,353 @@ pub enum GameError {
 
 pub type GameResult<T> =  Result<T, GameError>;
 
+/// Sessions lagging more than this many events skip the oldest ones
+const EVENTS_QUEUE_SIZE: usize = 256;
+pub const CHAT_MESSAGE_MAX_LENGTH: usize = 256;
+
+/// Character can be in world only once, whichever session asks for it
+#[derive(Default)]
+struct Attachments {
+    sessions_entities: HashMap<ConnectionSessionId, EntityId>,
+    characters_entities: HashMap<CharacterId, EntityId>,
+    entities_characters: HashMap<EntityId, CharacterId>,
+}
+
 pub struct Game {
     pub world_manager: WorldManager,
     pub database_adapter: Arc<dyn DatabaseAdapter>,
-    sessions_entities: Mutex<HashMap<ConnectionSessionId, EntityId>>,
+    /// Held for whole spawn and despawn, so character is never spawned twice nor read before it got saved
+    attachments: Mutex<Attachments>,
+    events_tx: broadcast::Sender<GameServerEvent>,
+    autosave_task: JoinHandle<()>,
 }
 
 impl Game {
     pub async fn new(database_adapter: Arc<dyn DatabaseAdapter>) -> Self {
-        let world_manager = WorldManager::run().await;
-        
+        Self::with_world_options(database_adapter, WorldOptions::default()).await
+    }
+
+    pub async fn with_world_options(database_adapter: Arc<dyn DatabaseAdapter>, world_options: WorldOptions) -> Self {
+        let (world_manager, autosave_rx) = WorldManager::run_with_options(world_options, AUTOSAVE_INTERVAL).await;
+        let autosave_task = tokio::spawn(Self::autosave_task(autosave_rx, database_adapter.clone()));
+        let (events_tx, _) = broadcast::channel(EVENTS_QUEUE_SIZE);
+
         Self {
             world_manager,
             database_adapter,
-            sessions_entities:  Mutex::new(HashMap::new()),
+            attachments: Mutex::new(Attachments::default()),
+            events_tx,
+            autosave_task,
         }
     }
 
-    async fn get_entity_id_of_session(&self, session_id: ConnectionSessionId) -> Option<EntityId> {
-        self.sessions_entities.lock().await.get(&session_id).copied()
+    pub fn subscribe_events(&self) -> broadcast::Receiver<GameServerEvent> {
+        self.events_tx.subscribe()
     }
 
-    async fn attach_entity_id_to_session(&self, connection_id: ConnectionSessionId, entity_id: EntityId) -> Result<(), EntityId> {
-        match self.sessions_entities.lock().await.insert(connection_id, entity_id) {
-            Some(entity_id) => Err(entity_id),
-            None => Ok(())
+    pub fn publish_event(&self, event: GameServerEvent) {
+        // Error only means nobody listens at the moment
+        let _ = self.events_tx.send(event);
+    }
+
+    /// Ends together with world, when autosave channel gets closed
+    async fn autosave_task(mut autosave_rx: mpsc::Receiver<Vec<CharacterData>>, database_adapter: Arc<dyn DatabaseAdapter>) {
+        while let Some(characters) = autosave_rx.recv().await {
+            tracing::debug!("Autosaving {} characters", characters.len());
+            for character_data in characters {
+                let character_id = character_data.id;
+                if let Err(e) = database_adapter.update_character(character_data).await {
+                    tracing::error!("Autosave of character {character_id} failed, reason: '{e}'");
+                }
+            }
         }
     }
 
-    pub async fn spawn_character_entity(&self, connection_id: ConnectionSessionId, character_id: CharacterId) -> GameResult<EntityId> {
-        if let Some(entity_id) = self.get_entity_id_of_session(connection_id).await {
-            return Err(GameError::SessionAlreadyAttachedToEntity { entity_id });
+    pub async fn get_entity_id_of_session(&self, session_id: ConnectionSessionId) -> Option<EntityId> {
+        self.attachments.lock().await.sessions_entities.get(&session_id).copied()
+    }
+
+    /// Only characters of the account session is authenticated as can be spawned
+    pub async fn spawn_character_entity(&self, connection_id: ConnectionSessionId, username: &str, character_id: CharacterId) -> GameResult<EntityId> {
+        let mut attachments = self.attachments.lock().await;
+        if let Some(entity_id) = attachments.sessions_entities.get(&connection_id) {
+            return Err(GameError::SessionAlreadyAttachedToEntity { entity_id: *entity_id });
+        }
+        // Two entities of one character would overwrite each other's progress when saved
+        if attachments.characters_entities.contains_key(&character_id) {
+            return Err(GameError::CharacterAlreadyInWorld { character_id });
+        }
+
+        if !self.database_adapter.get_characters_of_account(username).await?.contains(&character_id) {
+            return Err(DatabaseAdapterError::CharacterNotOwnedByAccount.into());
         }
 
         let character_data = self.database_adapter.get_character_by_id(character_id).await?;
+        let name = character_data.name.clone();
 
         match self.world_manager.spawn_character_entity(character_data).await {
-            Ok(spawned_entity_id) => {
-                if self.attach_entity_id_to_session(connection_id, spawned_entity_id).await.is_err() {
-                    tracing::error!("Could not attach entity to session id: {spawned_entity_id}");
-                }
+            Ok((spawned_entity_id, position)) => {
+                attachments.sessions_entities.insert(connection_id, spawned_entity_id);
+                attachments.characters_entities.insert(character_id, spawned_entity_id);
+                attachments.entities_characters.insert(spawned_entity_id, character_id);
+                self.publish_event(GameServerEvent::EntitySpawned { entity_id: spawned_entity_id, name, x: position.x, y: position.y });
                 Ok(spawned_entity_id)
             },
             Err(e) => Err(e.into())
         }
-        // Note: try refactoring,
-        // check if storing ids in hashmap is really needed,
-        // take care of cleaning upon disconnection
+    }
+
+    /// Detaches entity from session and removes it from world, character gets saved
+    pub async fn despawn_session_entity(&self, connection_id: ConnectionSessionId) -> GameResult<()> {
+        let mut attachments = self.attachments.lock().await;
+        let entity_id = *attachments.sessions_entities
+            .get(&connection_id)
+            .ok_or(GameError::SessionNotAttachedToEntity)?;
+
+        // Entity left in world stays attached, so it can be despawned again
+        let character_data = self.world_manager.despawn_entity(entity_id).await?;
+        attachments.sessions_entities.remove(&connection_id);
+        if let Some(character_id) = attachments.entities_characters.remove(&entity_id) {
+            attachments.characters_entities.remove(&character_id);
+        }
+
+        self.publish_event(GameServerEvent::EntityDespawned { entity_id });
+        if let Some(character_data) = character_data {
+            self.database_adapter.update_character(character_data).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn move_session_entity_to(&self, connection_id: ConnectionSessionId, target: Vec2F) -> GameResult<()> {
+        let entity_id = self.get_entity_id_of_session(connection_id).await
+            .ok_or(GameError::SessionNotAttachedToEntity)?;
+        let target = self.world_manager.move_entity_to(entity_id, target).await?;
+        self.publish_event(GameServerEvent::EntityMoving { entity_id, target_x: target.x, target_y: target.y });
+        Ok(())
+    }
+
+    pub async fn step_session_entity(&self, connection_id: ConnectionSessionId, direction: Direction) -> GameResult<()> {
+        let entity_id = self.get_entity_id_of_session(connection_id).await
+            .ok_or(GameError::SessionNotAttachedToEntity)?;
+        let target = self.world_manager.step_entity(entity_id, direction).await?;
+        self.publish_event(GameServerEvent::EntityMoving { entity_id, target_x: target.x, target_y: target.y });
+        Ok(())
+    }
+
+    pub fn send_chat_message(&self, from: &str, message: String) -> GameResult<()> {
+        if message.trim().is_empty() {
+            return Err(GameError::ChatMessageEmpty);
+        }
+        if message.chars().count() > CHAT_MESSAGE_MAX_LENGTH {
+            return Err(GameError::ChatMessageTooLong { max_length: CHAT_MESSAGE_MAX_LENGTH });
+        }
+
+        self.publish_event(GameServerEvent::Chat { from: from.to_string(), message });
+        Ok(())
+    }
+
+    pub async fn get_session_entity_position(&self, connection_id: ConnectionSessionId) -> GameResult<Vec2F> {
+        let entity_id = self.get_entity_id_of_session(connection_id).await
+            .ok_or(GameError::SessionNotAttachedToEntity)?;
+        self.world_manager.get_position(entity_id).await?
+            .ok_or(GameError::EntityHasNoPosition { entity_id })
+    }
+
+    pub async fn save_character_state(&self, entity_id: EntityId) -> GameResult<()> {
+        let character_data = self.world_manager
+            .export_character_data(entity_id).await?
+            .ok_or(GameError::EntityIsNotCharacter { entity_id })?;
+        self.database_adapter.update_character(character_data).await?;
+        Ok(())
+    }
+
+    pub async fn save_session_character_state(&self, connection_id: ConnectionSessionId) -> GameResult<()> {
+        let entity_id = self.get_entity_id_of_session(connection_id).await
+            .ok_or(GameError::SessionNotAttachedToEntity)?;
+        self.save_character_state(entity_id).await
+    }
+
+    /// Saves every character in world, to be called before shutdown as autosave would miss progress since its last run.
+    /// Returns count of saved characters, failed ones are only logged so the rest still get saved.
+    pub async fn save_all_characters(&self) -> GameResult<usize> {
+        let characters = self.world_manager.export_all_characters_data().await?;
+        let mut saved_count = 0;
+        for character_data in characters {
+            let character_id = character_data.id;
+            match self.database_adapter.update_character(character_data).await {
+                Ok(()) => saved_count += 1,
+                Err(e) => tracing::error!("Saving character {character_id} failed, reason: '{e}'"),
+            }
+        }
+        Ok(saved_count)
+    }
+
+    pub async fn on_session_ended(&self, connection_id: ConnectionSessionId) {
+        match self.despawn_session_entity(connection_id).await {
+            Ok(()) | Err(GameError::SessionNotAttachedToEntity) => {},
+            Err(e) => tracing::error!("Could not despawn character of session {connection_id}, reason: '{e}'"),
+        }
+    }
+}
+
+impl Drop for Game {
+    fn drop(&mut self) {
+        self.autosave_task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use database_adapter::test::DatabaseTestAdapter;
+    use super::*;
+
+    async fn await_session_entity_position(game: &Game, connection_id: ConnectionSessionId, position: Vec2F) {
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while game.get_session_entity_position(connection_id).await.unwrap() != position {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }).await.expect("Entity did not reach position");
+    }
+
+    #[tokio::test]
+    async fn test_saving_session_character_state() {
+        let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let game = Game::new(database_adapter.clone()).await;
+
+        assert!(matches!(game.save_session_character_state(0).await, Err(GameError::SessionNotAttachedToEntity)));
+
+        let entity_id = game.spawn_character_entity(0, "Account1", 1).await.unwrap();
+        game.step_session_entity(0, Direction::Right).await.unwrap();
+        await_session_entity_position(&game, 0, Vec2F::new(1.0, 1.0)).await;
+
+        game.save_session_character_state(0).await.unwrap();
+        let character_data = database_adapter.get_character_by_id(1).await.unwrap();
+        assert_eq!(character_data.name, "Tuna");
+        assert_eq!((character_data.position_x, character_data.position_y), (1.0, 1.0));
+
+        game.step_session_entity(0, Direction::Down).await.unwrap();
+        await_session_entity_position(&game, 0, Vec2F::new(1.0, 2.0)).await;
+
+        game.save_character_state(entity_id).await.unwrap();
+        let character_data = database_adapter.get_character_by_id(1).await.unwrap();
+        assert_eq!((character_data.position_x, character_data.position_y), (1.0, 2.0));
+
+        assert!(matches!(game.save_character_state(entity_id + 1).await, Err(GameError::EntityIsNotCharacter { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_saving_all_characters() {
+        let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let game = Game::new(database_adapter.clone()).await;
+        assert_eq!(game.save_all_characters().await.unwrap(), 0);
+
+        game.spawn_character_entity(0, "Account1", 1).await.unwrap();
+        game.spawn_character_entity(1, "Account2", 2).await.unwrap();
+        game.step_session_entity(0, Direction::Right).await.unwrap();
+        await_session_entity_position(&game, 0, Vec2F::new(1.0, 1.0)).await;
+
+        assert_eq!(game.save_all_characters().await.unwrap(), 2);
+        let character_data = database_adapter.get_character_by_id(1).await.unwrap();
+        assert_eq!((character_data.position_x, character_data.position_y), (1.0, 1.0));
+        let character_data = database_adapter.get_character_by_id(2).await.unwrap();
+        assert_eq!((character_data.position_x, character_data.position_y), (-2.0, 0.0));
+    }
+
+    #[tokio::test]
+    async fn test_spawning_character_of_other_account_should_fail() {
+        let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let game = Game::new(database_adapter).await;
+
+        let result = game.spawn_character_entity(0, "Account2", 1).await;
+        assert!(matches!(result, Err(GameError::DatabaseAdapterError(DatabaseAdapterError::CharacterNotOwnedByAccount))));
+        assert_eq!(game.world_manager.get_entities_count().await, 0);
+
+        game.spawn_character_entity(0, "Account2", 2).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_spawning_character_twice_should_fail() {
+        let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let game = Arc::new(Game::new(database_adapter).await);
+
+        // Sessions of the same account ask at once, only one gets the character
+        let (first, second) = tokio::join!(
+            game.spawn_character_entity(0, "Account1", 1),
+            game.spawn_character_entity(1, "Account1", 1)
+        );
+        let (entity_id, (failed_connection_id, error)) = match (first, second) {
+            (Ok(entity_id), Err(e)) => (entity_id, (1, e)),
+            (Err(e), Ok(entity_id)) => (entity_id, (0, e)),
+            results => panic!("Expected exactly one spawn, got {results:?}"),
+        };
+        assert!(matches!(error, GameError::CharacterAlreadyInWorld { character_id: 1 }));
+        assert_eq!(game.world_manager.get_entities_count().await, 1);
+        assert!(game.get_entity_id_of_session(failed_connection_id).await.is_none());
+
+        // Other character is fine, the first one again after despawn
+        game.spawn_character_entity(failed_connection_id, "Account1", 0).await.unwrap();
+        let attached_connection_id = 1 - failed_connection_id;
+        game.despawn_session_entity(attached_connection_id).await.unwrap();
+        let respawned_entity_id = game.spawn_character_entity(attached_connection_id, "Account1", 1).await.unwrap();
+        assert_ne!(respawned_entity_id, entity_id);
+    }
+
+    #[tokio::test]
+    async fn test_despawning_session_entity() {
+        let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let game = Game::new(database_adapter.clone()).await;
+        let mut events_rx = game.subscribe_events();
+
+        let entity_id = game.spawn_character_entity(0, "Account1", 1).await.unwrap();
+        events_rx.recv().await.unwrap();
+        game.step_session_entity(0, Direction::Right).await.unwrap();
+        events_rx.recv().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+        game.on_session_ended(0).await;
+        assert_eq!(events_rx.recv().await.unwrap(), GameServerEvent::EntityDespawned { entity_id });
+        assert_eq!(game.world_manager.get_entities_count().await, 0);
+        assert!(game.get_entity_id_of_session(0).await.is_none());
+        assert!(matches!(game.despawn_session_entity(0).await, Err(GameError::SessionNotAttachedToEntity)));
+
+        // Saved on the tile it was stepping onto, not in the middle of the step
+        let character_data = database_adapter.get_character_by_id(1).await.unwrap();
+        assert_eq!((character_data.position_x, character_data.position_y), (1.0, 1.0));
+
+        // Same session can attach again
+        game.spawn_character_entity(0, "Account1", 1).await.unwrap();
+        assert_eq!(game.world_manager.get_entities_count().await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_publishing_events() {
+        let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let game = Game::new(database_adapter).await;
+        let mut events_rx = game.subscribe_events();
+
+        let entity_id = game.spawn_character_entity(0, "Account1", 1).await.unwrap();
+        assert_eq!(
+            events_rx.recv().await.unwrap(),
+            GameServerEvent::EntitySpawned { entity_id, name: "Tuna".to_string(), x: 0.0, y: 1.0 }
+        );
+
+        game.step_session_entity(0, Direction::Right).await.unwrap();
+        assert_eq!(
+            events_rx.recv().await.unwrap(),
+            GameServerEvent::EntityMoving { entity_id, target_x: 1.0, target_y: 1.0 }
+        );
+
+        game.send_chat_message("Account1", "Hello".to_string()).unwrap();
+        assert_eq!(
+            events_rx.recv().await.unwrap(),
+            GameServerEvent::Chat { from: "Account1".to_string(), message: "Hello".to_string() }
+        );
+
+        assert!(matches!(game.send_chat_message("Account1", " ".to_string()), Err(GameError::ChatMessageEmpty)));
+        let too_long_message = "a".repeat(CHAT_MESSAGE_MAX_LENGTH + 1);
+        assert!(matches!(game.send_chat_message("Account1", too_long_message), Err(GameError::ChatMessageTooLong { .. })));
+        assert!(events_rx.try_recv().is_err());
     }
 }
 
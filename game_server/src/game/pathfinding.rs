@@ -0,0 +1,278 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use crate::game::tile_math::TilePosition;
+
+/// Costs scaled so diagonal step stays integer, ~sqrt(2).
+/// Wide enough for distance across the whole `i32` plane.
+type Cost = u64;
+const STRAIGHT_COST: Cost = 10;
+const DIAGONAL_COST: Cost = 14;
+
+pub const DEFAULT_MAX_EXPANDED_TILES: usize = 2048;
+/// Paths longer than the search limit allows are never found anyway
+pub const DEFAULT_MAX_DISTANCE: u32 = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiagonalMovement {
+    /// 4-neighbourhood
+    Never,
+    /// 8-neighbourhood, diagonal step needs both tiles it passes by to be passable
+    #[default]
+    NoCornerCutting,
+    /// 8-neighbourhood, diagonal step needs only its target tile to be passable
+    Always,
+}
+
+#[derive(Debug, Clone)]
+pub struct PathfindingOptions {
+    pub diagonal_movement: DiagonalMovement,
+    /// Single search gives up after expanding that many tiles, so it can't stall world tick
+    pub max_expanded_tiles: usize,
+    /// Goal further than that from start along any axis is rejected without searching, in tiles
+    pub max_distance: u32,
+}
+
+impl Default for PathfindingOptions {
+    fn default() -> Self {
+        Self {
+            diagonal_movement: DiagonalMovement::default(),
+            max_expanded_tiles: DEFAULT_MAX_EXPANDED_TILES,
+            max_distance: DEFAULT_MAX_DISTANCE,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PathfindingError {
+    #[error("No path to tile ({x}, {y})")]
+    NoPath {
+        x: i32,
+        y: i32,
+    },
+
+    #[error("No path to tile ({x}, {y}) found within {max_expanded_tiles} searched tiles")]
+    SearchLimitReached {
+        x: i32,
+        y: i32,
+        max_expanded_tiles: usize,
+    },
+
+    #[error("Tile ({x}, {y}) is further than {max_distance} tiles")]
+    TooFar {
+        x: i32,
+        y: i32,
+        max_distance: u32,
+    },
+}
+
+pub type PathfindingResult<T> = Result<T, PathfindingError>;
+
+/// Shortest path from `start` to `goal` with A*, excluding `start`.
+/// Empty if entity is already at `goal`.
+pub fn find_path(
+    start: TilePosition,
+    goal: TilePosition,
+    options: &PathfindingOptions,
+    is_passable: impl Fn(&TilePosition) -> bool
+) -> PathfindingResult<VecDeque<TilePosition>> {
+    let (x, y) = goal;
+    if start == goal {
+        return Ok(VecDeque::new());
+    }
+    if start.0.abs_diff(goal.0).max(start.1.abs_diff(goal.1)) > options.max_distance {
+        return Err(PathfindingError::TooFar { x, y, max_distance: options.max_distance });
+    }
+    if !is_passable(&goal) {
+        return Err(PathfindingError::NoPath { x, y });
+    }
+
+    let mut open = BinaryHeap::from([Reverse((heuristic(start, goal, options.diagonal_movement), start))]);
+    let mut costs: HashMap<TilePosition, Cost> = HashMap::from([(start, 0)]);
+    let mut came_from: HashMap<TilePosition, TilePosition> = HashMap::new();
+    let mut expanded_tiles = 0;
+
+    while let Some(Reverse((estimate, tile))) = open.pop() {
+        let cost = costs[&tile];
+        if estimate > cost + heuristic(tile, goal, options.diagonal_movement) {
+            // Stale entry, tile got reached cheaper meanwhile
+            continue;
+        }
+        if tile == goal {
+            return Ok(reconstruct_path(&came_from, start, goal));
+        }
+
+        expanded_tiles += 1;
+        if expanded_tiles > options.max_expanded_tiles {
+            return Err(PathfindingError::SearchLimitReached { x, y, max_expanded_tiles: options.max_expanded_tiles });
+        }
+
+        for (neighbour, step_cost) in neighbours(tile, options.diagonal_movement, &is_passable) {
+            let neighbour_cost = cost + step_cost;
+            if costs.get(&neighbour).is_none_or(|known_cost| neighbour_cost < *known_cost) {
+                costs.insert(neighbour, neighbour_cost);
+                came_from.insert(neighbour, tile);
+                let estimate = neighbour_cost + heuristic(neighbour, goal, options.diagonal_movement);
+                open.push(Reverse((estimate, neighbour)));
+            }
+        }
+    }
+
+    Err(PathfindingError::NoPath { x, y })
+}
+
+/// Cost of the shortest path on empty grid, never overestimates
+fn heuristic(from: TilePosition, to: TilePosition, diagonal_movement: DiagonalMovement) -> Cost {
+    let dx = Cost::from(from.0.abs_diff(to.0));
+    let dy = Cost::from(from.1.abs_diff(to.1));
+    match diagonal_movement {
+        DiagonalMovement::Never => (dx + dy) * STRAIGHT_COST,
+        DiagonalMovement::NoCornerCutting | DiagonalMovement::Always => {
+            dx.min(dy) * DIAGONAL_COST + dx.abs_diff(dy) * STRAIGHT_COST
+        },
+    }
+}
+
+/// Tile moved by offset, `None` past the edge of `i32` plane
+fn offset_tile(tile: TilePosition, (dx, dy): (i32, i32)) -> Option<TilePosition> {
+    Some((tile.0.checked_add(dx)?, tile.1.checked_add(dy)?))
+}
+
+/// Passable neighbouring tiles with cost of stepping onto them
+fn neighbours(
+    tile: TilePosition,
+    diagonal_movement: DiagonalMovement,
+    is_passable: &impl Fn(&TilePosition) -> bool
+) -> Vec<(TilePosition, Cost)> {
+    const STRAIGHT: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+    const DIAGONAL: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+    let is_offset_passable = |offset: (i32, i32)| offset_tile(tile, offset).is_some_and(|neighbour| is_passable(&neighbour));
+    let mut neighbours: Vec<(TilePosition, Cost)> = STRAIGHT
+        .iter()
+        .filter_map(|offset| offset_tile(tile, *offset))
+        .filter(|neighbour| is_passable(neighbour))
+        .map(|neighbour| (neighbour, STRAIGHT_COST))
+        .collect();
+
+    if diagonal_movement != DiagonalMovement::Never {
+        neighbours.extend(DIAGONAL
+            .iter()
+            .filter(|(dx, dy)| {
+                diagonal_movement == DiagonalMovement::Always
+                    || (is_offset_passable((*dx, 0)) && is_offset_passable((0, *dy)))
+            })
+            .filter_map(|offset| offset_tile(tile, *offset))
+            .filter(|neighbour| is_passable(neighbour))
+            .map(|neighbour| (neighbour, DIAGONAL_COST)));
+    }
+    neighbours
+}
+
+fn reconstruct_path(came_from: &HashMap<TilePosition, TilePosition>, start: TilePosition, goal: TilePosition) -> VecDeque<TilePosition> {
+    let mut path = VecDeque::from([goal]);
+    let mut tile = goal;
+    while let Some(previous_tile) = came_from.get(&tile).copied().filter(|previous_tile| *previous_tile != start) {
+        path.push_front(previous_tile);
+        tile = previous_tile;
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use super::*;
+
+    /// Vertical wall at `x = 1` from `y = -2` to `y = 2`
+    fn wall() -> HashSet<TilePosition> {
+        (-2..=2).map(|y| (1, y)).collect()
+    }
+
+    fn options(diagonal_movement: DiagonalMovement) -> PathfindingOptions {
+        PathfindingOptions { diagonal_movement, ..Default::default() }
+    }
+
+    fn assert_path_connected(start: TilePosition, path: &VecDeque<TilePosition>, diagonal_movement: DiagonalMovement) {
+        let mut previous_tile = start;
+        for tile in path {
+            let (dx, dy) = (tile.0.abs_diff(previous_tile.0), tile.1.abs_diff(previous_tile.1));
+            match diagonal_movement {
+                DiagonalMovement::Never => assert_eq!(dx + dy, 1, "Not a straight step: {previous_tile:?} -> {tile:?}"),
+                _ => assert!(dx <= 1 && dy <= 1 && dx + dy > 0, "Not a single step: {previous_tile:?} -> {tile:?}"),
+            }
+            previous_tile = *tile;
+        }
+    }
+
+    #[test]
+    fn test_finding_path_on_empty_grid() {
+        let path = find_path((0, 0), (3, 0), &options(DiagonalMovement::Never), |_| true).unwrap();
+        assert_eq!(path, VecDeque::from([(1, 0), (2, 0), (3, 0)]));
+
+        let path = find_path((0, 0), (2, 2), &options(DiagonalMovement::NoCornerCutting), |_| true).unwrap();
+        assert_eq!(path, VecDeque::from([(1, 1), (2, 2)]));
+
+        let path = find_path((0, 0), (2, 2), &options(DiagonalMovement::Never), |_| true).unwrap();
+        assert_eq!(path.len(), 4);
+        assert_path_connected((0, 0), &path, DiagonalMovement::Never);
+
+        assert!(find_path((5, 5), (5, 5), &options(DiagonalMovement::Never), |_| false).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_finding_path_around_wall() {
+        let wall = wall();
+        for diagonal_movement in [DiagonalMovement::Never, DiagonalMovement::NoCornerCutting, DiagonalMovement::Always] {
+            let path = find_path((0, 0), (2, 0), &options(diagonal_movement), |tile| !wall.contains(tile)).unwrap();
+            assert_eq!(path.back(), Some(&(2, 0)));
+            assert!(path.iter().all(|tile| !wall.contains(tile)));
+            assert_path_connected((0, 0), &path, diagonal_movement);
+        }
+
+        // Shortest way goes around end of the wall
+        let path = find_path((0, 2), (2, 2), &options(DiagonalMovement::Never), |tile| !wall.contains(tile)).unwrap();
+        assert_eq!(path.len(), 4);
+    }
+
+    #[test]
+    fn test_cutting_corners() {
+        // Squeezing diagonally between two blocked tiles
+        let blocked: HashSet<TilePosition> = HashSet::from([(1, 0), (0, 1)]);
+        let path = find_path((0, 0), (1, 1), &options(DiagonalMovement::Always), |tile| !blocked.contains(tile)).unwrap();
+        assert_eq!(path, VecDeque::from([(1, 1)]));
+
+        let path = find_path((0, 0), (1, 1), &options(DiagonalMovement::NoCornerCutting), |tile| !blocked.contains(tile)).unwrap();
+        assert!(path.len() > 2);
+        assert!(path.iter().all(|tile| !blocked.contains(tile)));
+        assert_path_connected((0, 0), &path, DiagonalMovement::NoCornerCutting);
+    }
+
+    #[test]
+    fn test_finding_no_path() {
+        let enclosure: HashSet<TilePosition> = HashSet::from([(4, 0), (6, 0), (5, 1), (5, -1)]);
+        let options = options(DiagonalMovement::NoCornerCutting);
+
+        let result = find_path((0, 0), (4, 0), &options, |tile| !enclosure.contains(tile));
+        assert!(matches!(result, Err(PathfindingError::NoPath { x: 4, y: 0 })));
+
+        // Bounded area is searched entirely
+        let result = find_path((0, 0), (5, 0), &options, |tile| !enclosure.contains(tile) && tile.0.abs() <= 10 && tile.1.abs() <= 10);
+        assert!(matches!(result, Err(PathfindingError::NoPath { x: 5, y: 0 })));
+
+        // Unbounded one till the limit
+        let result = find_path((0, 0), (5, 0), &options, |tile| !enclosure.contains(tile));
+        assert!(matches!(result, Err(PathfindingError::SearchLimitReached { max_expanded_tiles: DEFAULT_MAX_EXPANDED_TILES, .. })));
+    }
+
+    #[test]
+    fn test_finding_path_far_away() {
+        let options = options(DiagonalMovement::NoCornerCutting);
+        let result = find_path((0, 0), (i32::MAX, i32::MIN), &options, |_| true);
+        assert!(matches!(result, Err(PathfindingError::TooFar { x: i32::MAX, max_distance: DEFAULT_MAX_DISTANCE, .. })));
+        assert_eq!(heuristic((i32::MIN, i32::MIN), (i32::MAX, 0), DiagonalMovement::Always), 51_539_607_542);
+
+        // Edge of the plane is the end of the world
+        let path = find_path((i32::MAX - 2, i32::MAX), (i32::MAX, i32::MAX), &options, |_| true).unwrap();
+        assert_eq!(path, VecDeque::from([(i32::MAX - 1, i32::MAX), (i32::MAX, i32::MAX)]));
+    }
+}
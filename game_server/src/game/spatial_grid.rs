@@ -0,0 +1,133 @@
+use std::collections::{HashMap, HashSet};
+use crate::game::entity::EntityId;
+use crate::game::math::Vec2F;
+use crate::game::tile_math::{align_to_tile, TILE_SIZE};
+
+/// Width of square grid cell, in tiles
+pub const GRID_CELL_SIZE_TILES: f32 = 8.0;
+
+type CellKey = (i32, i32);
+
+fn cell_key(position: &Vec2F) -> CellKey {
+    let cell_size = GRID_CELL_SIZE_TILES * TILE_SIZE;
+    (align_to_tile(position.x / cell_size) as i32, align_to_tile(position.y / cell_size) as i32)
+}
+
+/// Buckets entities by position, so entities near a point are found without scanning all of them
+#[derive(Debug, Default)]
+pub struct SpatialGrid {
+    cells: HashMap<CellKey, HashSet<EntityId>>,
+    entities_cells: HashMap<EntityId, CellKey>,
+}
+
+impl SpatialGrid {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inserts entity or moves it to the cell of new position
+    pub fn update(&mut self, entity_id: EntityId, position: &Vec2F) {
+        let cell = cell_key(position);
+        match self.entities_cells.insert(entity_id, cell) {
+            Some(old_cell) if old_cell == cell => return,
+            Some(old_cell) => self.remove_from_cell(entity_id, old_cell),
+            None => {},
+        }
+        self.cells.entry(cell).or_default().insert(entity_id);
+    }
+
+    pub fn remove(&mut self, entity_id: EntityId) {
+        if let Some(cell) = self.entities_cells.remove(&entity_id) {
+            self.remove_from_cell(entity_id, cell);
+        }
+    }
+
+    fn remove_from_cell(&mut self, entity_id: EntityId, cell: CellKey) {
+        if let Some(cell_entities) = self.cells.get_mut(&cell) {
+            cell_entities.remove(&entity_id);
+            if cell_entities.is_empty() {
+                self.cells.remove(&cell);
+            }
+        }
+    }
+
+    /// Entities of all cells overlapping the square, some of them may lay outside of it
+    pub fn get_entities_near(&self, center: &Vec2F, range: f32) -> impl Iterator<Item = EntityId> + '_ {
+        let (min_x, min_y) = cell_key(&Vec2F::new(center.x - range, center.y - range));
+        let (max_x, max_y) = cell_key(&Vec2F::new(center.x + range, center.y + range));
+
+        (min_x..=max_x)
+            .flat_map(move |x| (min_y..=max_y).map(move |y| (x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic spread of positions, both negative and positive
+    fn test_position(index: u32) -> Vec2F {
+        let x = ((index * 7919) % 401) as f32 - 200.0;
+        let y = ((index * 104729) % 401) as f32 - 200.0;
+        Vec2F::new(x, y)
+    }
+
+    #[test]
+    fn test_updating_and_removing() {
+        let mut grid = SpatialGrid::new();
+        grid.update(1, &Vec2F::new(0.0, 0.0));
+        grid.update(2, &Vec2F::new(-0.5, 0.0));
+        assert_eq!(grid.entities_cells.len(), 2);
+
+        let near: HashSet<EntityId> = grid.get_entities_near(&Vec2F::new(1.0, 1.0), 2.0).collect();
+        assert_eq!(near, HashSet::from([1, 2]));
+
+        grid.update(1, &Vec2F::new(100.0, 0.0));
+        let near: HashSet<EntityId> = grid.get_entities_near(&Vec2F::new(1.0, 1.0), 2.0).collect();
+        assert_eq!(near, HashSet::from([2]));
+        assert_eq!(grid.entities_cells.len(), 2);
+        assert_eq!(grid.cells.len(), 2);
+
+        grid.remove(2);
+        grid.remove(2);
+        assert_eq!(grid.get_entities_near(&Vec2F::new(1.0, 1.0), 2.0).count(), 0);
+        assert_eq!(grid.entities_cells.len(), 1);
+        assert_eq!(grid.cells.len(), 1);
+    }
+
+    #[test]
+    fn test_finding_entities_among_thousands() {
+        const ENTITIES_COUNT: u32 = 5000;
+        const RANGE: f32 = 12.0;
+
+        let mut grid = SpatialGrid::new();
+        let positions: HashMap<EntityId, Vec2F> = (0..ENTITIES_COUNT)
+            .map(|entity_id| (entity_id, test_position(entity_id)))
+            .collect();
+        for (entity_id, position) in &positions {
+            grid.update(*entity_id, position);
+        }
+
+        for center_index in (0..ENTITIES_COUNT).step_by(97) {
+            let center = test_position(center_index);
+            let in_range = |position: &Vec2F| {
+                (position.x - center.x).abs() <= RANGE && (position.y - center.y).abs() <= RANGE
+            };
+
+            let expected: HashSet<EntityId> = positions.iter()
+                .filter(|(_, position)| in_range(position))
+                .map(|(entity_id, _)| *entity_id)
+                .collect();
+            let found: HashSet<EntityId> = grid.get_entities_near(&center, RANGE)
+                .filter(|entity_id| in_range(&positions[entity_id]))
+                .collect();
+            assert_eq!(found, expected);
+
+            // Candidates are limited to few cells
+            assert!(grid.get_entities_near(&center, RANGE).count() < ENTITIES_COUNT as usize / 10);
+        }
+    }
+}
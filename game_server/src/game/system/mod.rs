@@ -1,17 +1,19 @@
 pub mod movement_system;
 pub mod position_system;
-pub mod name_system;
-
-pub use position_system::PositionSystem;
-pub use movement_system::MovementSystem;
-pub use name_system::NameSystem;
 
 #[cfg(test)]
 mod tests {
-    use crate::game::entity::component::{MovementComponent, PositionComponent};
+    use crate::game::collision::TileOccupancy;
+    use crate::game::entity::component::{ComponentStorage, MovementComponent, PositionComponent};
+    use crate::game::entity::component::storage::ComponentError;
+    use crate::game::entity::EntityId;
+    use crate::game::map::TileMap;
     use crate::game::math::Vec2F;
+    use crate::game::pathfinding::{DiagonalMovement, PathfindingOptions};
+    use crate::game::spatial_grid::SpatialGrid;
     use crate::game::system::movement_system::MovementSystemError;
-    use crate::game::system::position_system::PositionSystemError;
+    use crate::game::tile_math::nearest_tile;
+    use crate::requests::Direction;
     use super::*;
 
     #[test]
@@ -19,51 +21,52 @@ mod tests {
         const DT: f32 = 0.25;
         const SPEED: f32 = 1.0;
         const EVERYTICK_TRANSLATION: f32 = DT * SPEED;
-        let mut position_system = PositionSystem::new();
-        let mut movement_system = MovementSystem::new();
+        let mut components = ComponentStorage::new();
+        let mut grid = SpatialGrid::new();
+        let mut occupancy = TileOccupancy::new();
 
         let target_position = Vec2F::new(1.0, 0.0);
 
         let entity_id = 1;
 
-        position_system.add_component(entity_id, PositionComponent::new(entity_id, Vec2F::new(0.0, 0.0))).unwrap();
-        movement_system.add_component(entity_id, MovementComponent::new(entity_id,1.0)).unwrap();
+        position_system::add_component(&mut components, &mut grid, entity_id, PositionComponent::new(Vec2F::new(0.0, 0.0))).unwrap();
+        components.insert(entity_id, MovementComponent::new(1.0)).unwrap();
 
-        movement_system.move_entity_to(entity_id, target_position.clone()).unwrap();
+        movement_system::step_entity(&mut components, None, &mut occupancy, entity_id, Direction::Right).unwrap();
 
         let mut tick_idx = 0;
         {
             tick_idx += 1;
-            movement_system.tick(&mut position_system, DT);
-            let pc = position_system.get_component(&entity_id).unwrap();
-            let mc = movement_system.get_component(&entity_id).unwrap();
+            movement_system::tick(&mut components, &mut grid, None, &mut occupancy, &PathfindingOptions::default(), DT);
+            let pc = components.get::<PositionComponent>(&entity_id).unwrap();
+            let mc = components.get::<MovementComponent>(&entity_id).unwrap();
             assert!(pc.get_position().approx_eq(&Vec2F::new(tick_idx as f32 * EVERYTICK_TRANSLATION, 0.0)));
             assert!(mc.is_moving());
         }
 
         {
             tick_idx += 1;
-            movement_system.tick(&mut position_system, DT);
-            let pc = position_system.get_component(&entity_id).unwrap();
-            let mc = movement_system.get_component(&entity_id).unwrap();
+            movement_system::tick(&mut components, &mut grid, None, &mut occupancy, &PathfindingOptions::default(), DT);
+            let pc = components.get::<PositionComponent>(&entity_id).unwrap();
+            let mc = components.get::<MovementComponent>(&entity_id).unwrap();
             assert!(pc.get_position().approx_eq(&Vec2F::new(tick_idx as f32 * EVERYTICK_TRANSLATION, 0.0)));
             assert!(mc.is_moving());
         }
 
         {
             tick_idx += 1;
-            movement_system.tick(&mut position_system, DT);
-            let pc = position_system.get_component(&entity_id).unwrap();
-            let mc = movement_system.get_component(&entity_id).unwrap();
+            movement_system::tick(&mut components, &mut grid, None, &mut occupancy, &PathfindingOptions::default(), DT);
+            let pc = components.get::<PositionComponent>(&entity_id).unwrap();
+            let mc = components.get::<MovementComponent>(&entity_id).unwrap();
             assert!(pc.get_position().approx_eq(&Vec2F::new(tick_idx as f32 * EVERYTICK_TRANSLATION, 0.0)));
             assert!(mc.is_moving());
         }
 
         {
             tick_idx += 1;
-            movement_system.tick(&mut position_system, DT);
-            let pc = position_system.get_component(&entity_id).unwrap();
-            let mc = movement_system.get_component(&entity_id).unwrap();
+            movement_system::tick(&mut components, &mut grid, None, &mut occupancy, &PathfindingOptions::default(), DT);
+            let pc = components.get::<PositionComponent>(&entity_id).unwrap();
+            let mc = components.get::<MovementComponent>(&entity_id).unwrap();
             assert!(pc.get_position().approx_eq(&Vec2F::new(tick_idx as f32 * EVERYTICK_TRANSLATION, 0.0)));
 
             assert!(pc.get_position().approx_eq(&target_position));
@@ -73,44 +76,63 @@ mod tests {
     }
 
     #[test]
-    fn test_moving_system_check_already_moving() {
+    fn test_queueing_steps_while_moving() {
         const DT: f32 = 0.25;
-        const SPEED: f32 = 1.0;
-        const EVERYTICK_TRANSLATION: f32 = DT * SPEED;
-        let mut position_system = PositionSystem::new();
-        let mut movement_system = MovementSystem::new();
+        let mut components = ComponentStorage::new();
+        let mut grid = SpatialGrid::new();
+        let mut occupancy = TileOccupancy::new();
+        let options = PathfindingOptions::default();
 
         let entity_id = 1;
 
-        position_system.add_component(entity_id, PositionComponent::new(entity_id, Vec2F::new(0.0, 0.0))).unwrap();
-        movement_system.add_component(entity_id, MovementComponent::new(entity_id,1.0)).unwrap();
+        position_system::add_component(&mut components, &mut grid, entity_id, PositionComponent::new(Vec2F::new(0.0, 0.0))).unwrap();
+        components.insert(entity_id, MovementComponent::new(1.0)).unwrap();
+        assert_eq!(components.get::<MovementComponent>(&entity_id).unwrap().facing, Direction::Down);
 
-        movement_system.move_entity_to(entity_id, Vec2F::new(1.0, 0.0)).unwrap();
-        assert!(movement_system.get_component(&entity_id).unwrap().is_moving());
-        assert!(matches!(movement_system.move_entity_to(entity_id, Vec2F::new(1.0, 0.0)), Err(MovementSystemError::AlreadyMoving)));
+        movement_system::step_entity(&mut components, None, &mut occupancy, entity_id, Direction::Right).unwrap();
+        movement_system::tick(&mut components, &mut grid, None, &mut occupancy, &options, DT); // 0.25
+        assert_eq!(components.get::<MovementComponent>(&entity_id).unwrap().facing, Direction::Right);
 
-        movement_system.tick(&mut position_system, DT); // 0.25
-        assert!(movement_system.get_component(&entity_id).unwrap().is_moving());
-        assert!(matches!(movement_system.move_entity_to(entity_id, Vec2F::new(1.0, 0.0)), Err(MovementSystemError::AlreadyMoving)));
+        // Held key, next steps start from the tile being entered and replace one another
+        let target = movement_system::step_entity(&mut components, None, &mut occupancy, entity_id, Direction::Right).unwrap();
+        assert!(target.approx_eq(&Vec2F::new(2.0, 0.0)));
+        let target = movement_system::step_entity(&mut components, None, &mut occupancy, entity_id, Direction::Up).unwrap();
+        assert!(target.approx_eq(&Vec2F::new(1.0, -1.0)));
+        assert_eq!(components.get::<MovementComponent>(&entity_id).unwrap().steps.len(), 2);
 
-        movement_system.tick(&mut position_system, DT); // 0.5
-        movement_system.tick(&mut position_system, DT); // 0.75
-        movement_system.tick(&mut position_system, DT); // 1.0 - stopped
-        assert!(!movement_system.get_component(&entity_id).unwrap().is_moving());
-        movement_system.move_entity_to(entity_id, Vec2F::new(1.0, 0.0)).unwrap();
+        movement_system::tick(&mut components, &mut grid, None, &mut occupancy, &options, DT); // 0.5
+        movement_system::tick(&mut components, &mut grid, None, &mut occupancy, &options, DT); // 0.75
+        movement_system::tick(&mut components, &mut grid, None, &mut occupancy, &options, DT); // 1.0 - tile reached
+        assert!(position_system::get_position(&components, &entity_id).unwrap().approx_eq(&Vec2F::new(1.0, 0.0)));
+        assert!(components.get::<MovementComponent>(&entity_id).unwrap().is_moving());
+
+        movement_system::tick(&mut components, &mut grid, None, &mut occupancy, &options, DT);
+        let mc = components.get::<MovementComponent>(&entity_id).unwrap();
+        assert_eq!(mc.facing, Direction::Up);
+        assert!(position_system::get_position(&components, &entity_id).unwrap().approx_eq(&Vec2F::new(1.0, -0.25)));
+
+        // Path replaces queued steps too, step in progress is finished first
+        movement_system::move_entity_along_path(&mut components, None, &mut occupancy, &options, entity_id, Vec2F::new(3.0, -1.0)).unwrap();
+        for _ in 0..12 {
+            movement_system::tick(&mut components, &mut grid, None, &mut occupancy, &options, DT);
+        }
+        assert!(position_system::get_position(&components, &entity_id).unwrap().approx_eq(&Vec2F::new(3.0, -1.0)));
+        let mc = components.get::<MovementComponent>(&entity_id).unwrap();
+        assert!(!mc.is_moving());
+        assert_eq!(mc.facing, Direction::Right);
     }
 
     #[test]
     fn test_entity_move_without_component_added() {
-        const DT: f32 = 0.25;
-        const SPEED: f32 = 1.0;
-        const EVERYTICK_TRANSLATION: f32 = DT * SPEED;
-        let mut position_system = PositionSystem::new();
-        let mut movement_system = MovementSystem::new();
+        let mut components = ComponentStorage::new();
+        let mut occupancy = TileOccupancy::new();
 
         let entity_id = 1;
 
-        assert!(matches!(movement_system.move_entity_to(entity_id, Vec2F::new(1.0, 0.0)), Err(MovementSystemError::NoMoveComponent)));
+        let result = movement_system::move_entity_along_path(&mut components, None, &mut occupancy, &PathfindingOptions::default(), entity_id, Vec2F::new(1.0, 0.0));
+        assert!(matches!(result, Err(MovementSystemError::NoMoveComponent)));
+        let result = movement_system::step_entity(&mut components, None, &mut occupancy, entity_id, Direction::Up);
+        assert!(matches!(result, Err(MovementSystemError::NoPositionComponent)));
     }
 
     #[test]
@@ -118,17 +140,196 @@ mod tests {
         const DT: f32 = 0.25;
         const SPEED: f32 = 1.0;
         const EVERYTICK_TRANSLATION: f32 = DT * SPEED;
-        let mut position_system = PositionSystem::new();
-        let mut movement_system = MovementSystem::new();
+        let mut components = ComponentStorage::new();
+        let mut grid = SpatialGrid::new();
 
         let entity_id = 1;
 
-        position_system.add_component(entity_id, PositionComponent::new(entity_id, Vec2F::new(0.0, 0.0))).unwrap();
-        movement_system.add_component(entity_id, MovementComponent::new(entity_id,1.0)).unwrap();
+        position_system::add_component(&mut components, &mut grid, entity_id, PositionComponent::new(Vec2F::new(0.0, 0.0))).unwrap();
+        components.insert(entity_id, MovementComponent::new(1.0)).unwrap();
+
+        let ps_result = position_system::add_component(&mut components, &mut grid, entity_id, PositionComponent::new(Vec2F::new(0.0, 0.0)));
+        assert!(matches!(ps_result, Err(ComponentError::ComponentAlreadyAdded { .. })));
+        let ms_result = components.insert(entity_id, MovementComponent::new(1.0));
+        assert!(matches!(ms_result, Err(ComponentError::ComponentAlreadyAdded { .. })));
+    }
+
+    /// 5x3 tiles from (-2, -1) to (2, 1), wall at (1, 0)
+    const WALLED_MAP: &str = r#"{
+        "width": 5,
+        "height": 3,
+        "origin": [-2, -1],
+        "tile_types": {
+            "1": { "ground": "grass", "walkable": true },
+            "2": { "ground": "stone", "walkable": false }
+        },
+        "layers": [
+            { "name": "ground", "tiles": [
+                1, 1, 1, 1, 1,
+                1, 1, 1, 2, 1,
+                1, 1, 1, 1, 1
+            ] }
+        ]
+    }"#;
+
+    fn spawn_blocking_entity(
+        components: &mut ComponentStorage,
+        grid: &mut SpatialGrid,
+        occupancy: &mut TileOccupancy,
+        entity_id: EntityId,
+        position: Vec2F
+    ) {
+        position_system::add_component(components, grid, entity_id, PositionComponent::new(position)).unwrap();
+        components.insert(entity_id, MovementComponent::new(1.0)).unwrap();
+        occupancy.add_entity(entity_id, nearest_tile(&position));
+    }
+
+    #[test]
+    fn test_stepping_into_wall_and_off_map_should_fail() {
+        let map = TileMap::from_json_str(WALLED_MAP).unwrap();
+        let mut components = ComponentStorage::new();
+        let mut grid = SpatialGrid::new();
+        let mut occupancy = TileOccupancy::new();
+        spawn_blocking_entity(&mut components, &mut grid, &mut occupancy, 1, Vec2F::new(0.0, 0.0));
+        spawn_blocking_entity(&mut components, &mut grid, &mut occupancy, 2, Vec2F::new(-2.0, 1.0));
+
+        let result = movement_system::step_entity(&mut components, Some(&map), &mut occupancy, 1, Direction::Right);
+        assert!(matches!(result, Err(MovementSystemError::TileNotWalkable { x: 1, y: 0 })));
+
+        // Beyond map edges
+        let result = movement_system::step_entity(&mut components, Some(&map), &mut occupancy, 2, Direction::Left);
+        assert!(matches!(result, Err(MovementSystemError::TileNotWalkable { x: -3, y: 1 })));
+        let result = movement_system::step_entity(&mut components, Some(&map), &mut occupancy, 2, Direction::Down);
+        assert!(matches!(result, Err(MovementSystemError::TileNotWalkable { x: -2, y: 2 })));
+        assert!(!components.get::<MovementComponent>(&2).unwrap().is_moving());
+        assert_eq!(occupancy.get_occupant(&(-3, 1)), None);
+
+        // While moving, next step starts from the tile being entered
+        movement_system::step_entity(&mut components, Some(&map), &mut occupancy, 1, Direction::Up).unwrap();
+        movement_system::tick(&mut components, &mut grid, Some(&map), &mut occupancy, &PathfindingOptions::default(), 0.25);
+        movement_system::step_entity(&mut components, Some(&map), &mut occupancy, 1, Direction::Right).unwrap();
+        let result = movement_system::step_entity(&mut components, Some(&map), &mut occupancy, 1, Direction::Up);
+        assert!(matches!(result, Err(MovementSystemError::TileNotWalkable { x: 0, y: -2 })));
+    }
+
+    #[test]
+    fn test_stepping_into_occupied_tile_should_fail() {
+        let mut components = ComponentStorage::new();
+        let mut grid = SpatialGrid::new();
+        let mut occupancy = TileOccupancy::new();
+        spawn_blocking_entity(&mut components, &mut grid, &mut occupancy, 1, Vec2F::new(0.0, 0.0));
+        spawn_blocking_entity(&mut components, &mut grid, &mut occupancy, 2, Vec2F::new(1.0, 0.0));
+        spawn_blocking_entity(&mut components, &mut grid, &mut occupancy, 3, Vec2F::new(2.0, 1.0));
+
+        let result = movement_system::step_entity(&mut components, None, &mut occupancy, 1, Direction::Right);
+        assert!(matches!(result, Err(MovementSystemError::TileOccupied { x: 1, y: 0 })));
+
+        // Tile reserved by entity stepping in can't be taken by other one
+        movement_system::step_entity(&mut components, None, &mut occupancy, 2, Direction::Right).unwrap();
+        let result = movement_system::step_entity(&mut components, None, &mut occupancy, 3, Direction::Up);
+        assert!(matches!(result, Err(MovementSystemError::TileOccupied { x: 2, y: 0 })));
+
+        // Tile left behind gets free
+        for _ in 0..4 {
+            movement_system::tick(&mut components, &mut grid, None, &mut occupancy, &PathfindingOptions::default(), 0.25);
+        }
+        assert_eq!(occupancy.get_occupant(&(1, 0)), None);
+        assert_eq!(occupancy.get_occupant(&(2, 0)), Some(2));
+        movement_system::step_entity(&mut components, None, &mut occupancy, 1, Direction::Right).unwrap();
+    }
+
+    /// Corridor from (0, 0) to (4, 0) with single side entrance at (2, 1)
+    const CORRIDOR_MAP: &str = r#"{
+        "width": 5,
+        "height": 2,
+        "tile_types": {
+            "1": { "ground": "grass", "walkable": true },
+            "2": { "ground": "stone", "walkable": false }
+        },
+        "layers": [
+            { "name": "ground", "tiles": [
+                1, 1, 1, 1, 1,
+                2, 2, 1, 2, 2
+            ] }
+        ]
+    }"#;
+
+    #[test]
+    fn test_stopping_before_tile_taken_on_the_way() {
+        let map = TileMap::from_json_str(CORRIDOR_MAP).unwrap();
+        let options = PathfindingOptions::default();
+        let mut components = ComponentStorage::new();
+        let mut grid = SpatialGrid::new();
+        let mut occupancy = TileOccupancy::new();
+        spawn_blocking_entity(&mut components, &mut grid, &mut occupancy, 1, Vec2F::new(0.0, 0.0));
+        spawn_blocking_entity(&mut components, &mut grid, &mut occupancy, 2, Vec2F::new(2.0, 1.0));
+
+        movement_system::move_entity_along_path(&mut components, Some(&map), &mut occupancy, &options, 1, Vec2F::new(4.0, 0.0)).unwrap();
+        movement_system::tick(&mut components, &mut grid, Some(&map), &mut occupancy, &options, 0.25);
+        movement_system::step_entity(&mut components, Some(&map), &mut occupancy, 2, Direction::Up).unwrap();
+        for _ in 0..20 {
+            movement_system::tick(&mut components, &mut grid, Some(&map), &mut occupancy, &options, 0.25);
+        }
+
+        // Entity 2 stepped in first, entity 1 stopped at the last free tile as there is no way around
+        assert!(position_system::get_position(&components, &1).unwrap().approx_eq(&Vec2F::new(1.0, 0.0)));
+        assert!(position_system::get_position(&components, &2).unwrap().approx_eq(&Vec2F::new(2.0, 0.0)));
+        assert!(!components.get::<MovementComponent>(&1).unwrap().is_moving());
+        assert_eq!(occupancy.get_occupant(&(1, 0)), Some(1));
+        assert_eq!(occupancy.get_occupant(&(4, 0)), None);
+    }
+
+    #[test]
+    fn test_following_path_around_wall() {
+        let map = TileMap::from_json_str(WALLED_MAP).unwrap();
+        let options = PathfindingOptions::default();
+        let mut components = ComponentStorage::new();
+        let mut grid = SpatialGrid::new();
+        let mut occupancy = TileOccupancy::new();
+        spawn_blocking_entity(&mut components, &mut grid, &mut occupancy, 1, Vec2F::new(0.0, 0.0));
+
+        let target = movement_system::move_entity_along_path(&mut components, Some(&map), &mut occupancy, &options, 1, Vec2F::new(2.0, 0.0)).unwrap();
+        assert!(target.approx_eq(&Vec2F::new(2.0, 0.0)));
+
+        for _ in 0..20 {
+            movement_system::tick(&mut components, &mut grid, Some(&map), &mut occupancy, &options, 0.25);
+            let position = position_system::get_position(&components, &1).unwrap();
+            assert_ne!(nearest_tile(position), (1, 0), "Walked through wall");
+        }
+        assert!(position_system::get_position(&components, &1).unwrap().approx_eq(&Vec2F::new(2.0, 0.0)));
+        assert!(!components.get::<MovementComponent>(&1).unwrap().is_moving());
+        assert_eq!(occupancy.get_occupant(&(2, 0)), Some(1));
+        assert_eq!(occupancy.get_occupant(&(0, 0)), None);
+
+        let result = movement_system::move_entity_along_path(&mut components, Some(&map), &mut occupancy, &options, 1, Vec2F::new(1.0, 0.0));
+        assert!(matches!(result, Err(MovementSystemError::TileNotWalkable { x: 1, y: 0 })));
+        let result = movement_system::move_entity_along_path(&mut components, Some(&map), &mut occupancy, &options, 1, Vec2F::new(5.0, 0.0));
+        assert!(matches!(result, Err(MovementSystemError::TileNotWalkable { x: 5, y: 0 })));
+    }
+
+    #[test]
+    fn test_recomputing_path_when_blocked() {
+        let options = PathfindingOptions { diagonal_movement: DiagonalMovement::Never, ..Default::default() };
+        let mut components = ComponentStorage::new();
+        let mut grid = SpatialGrid::new();
+        let mut occupancy = TileOccupancy::new();
+        spawn_blocking_entity(&mut components, &mut grid, &mut occupancy, 1, Vec2F::new(0.0, 0.0));
+        spawn_blocking_entity(&mut components, &mut grid, &mut occupancy, 2, Vec2F::new(2.0, 1.0));
+
+        movement_system::move_entity_along_path(&mut components, None, &mut occupancy, &options, 1, Vec2F::new(3.0, 0.0)).unwrap();
+        movement_system::tick(&mut components, &mut grid, None, &mut occupancy, &options, 0.25);
+        // Steps into the path right in front of entity 1
+        movement_system::step_entity(&mut components, None, &mut occupancy, 2, Direction::Up).unwrap();
+
+        for _ in 0..40 {
+            movement_system::tick(&mut components, &mut grid, None, &mut occupancy, &options, 0.25);
+        }
+        assert!(position_system::get_position(&components, &1).unwrap().approx_eq(&Vec2F::new(3.0, 0.0)));
+        assert!(position_system::get_position(&components, &2).unwrap().approx_eq(&Vec2F::new(2.0, 0.0)));
+        assert!(!components.get::<MovementComponent>(&1).unwrap().is_moving());
 
-        let ps_result = position_system.add_component(entity_id, PositionComponent::new(entity_id, Vec2F::new(0.0, 0.0)));
-        assert!(matches!(ps_result, Err(PositionSystemError::ComponentAlreadyAdded(_))));
-        let ms_result = movement_system.add_component(entity_id, MovementComponent::new(entity_id,1.0));
-        assert!(matches!(ms_result, Err(MovementSystemError::ComponentAlreadyAdded(_))));
+        // Goal held by other entity
+        let result = movement_system::move_entity_along_path(&mut components, None, &mut occupancy, &options, 1, Vec2F::new(2.0, 0.0));
+        assert!(matches!(result, Err(MovementSystemError::TileOccupied { x: 2, y: 0 })));
     }
-}
\ No newline at end of file
+}
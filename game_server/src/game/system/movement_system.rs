@@ -1,117 +1,231 @@
-use std::collections::HashMap;
 use crate::game::entity::component::movement_component::MovementState;
-use crate::game::entity::component::{MovementComponent, PositionComponent};
+use crate::game::entity::component::{ComponentStorage, MovementComponent, PositionComponent};
+use crate::game::collision::{self, TileOccupancy};
 use crate::game::entity::EntityId;
+use crate::game::map::TileMap;
 use crate::game::math::Vec2F;
-use crate::game::system::position_system::{PositionSystemError, PositionSystemResult};
-use crate::game::system::PositionSystem;
-use crate::game::tile_math::align_vec2f_to_tile;
+use crate::game::pathfinding::{self, PathfindingError, PathfindingOptions};
+use crate::game::spatial_grid::SpatialGrid;
+use crate::game::system::position_system;
+use crate::game::tile_math::{facing_direction, nearest_tile, step_translation, tile_to_position, TilePosition};
+use crate::requests::Direction;
 
 #[derive(Debug, thiserror::Error)]
 pub enum MovementSystemError {
     #[error("No move component")]
     NoMoveComponent,
 
-    #[error("Already moving")]
-    AlreadyMoving,
+    #[error("No position component")]
+    NoPositionComponent,
 
-    #[error("Component already added")]
-    ComponentAlreadyAdded(MovementComponent)
+    #[error("Tile ({x}, {y}) is not walkable")]
+    TileNotWalkable {
+        x: i32,
+        y: i32,
+    },
+
+    #[error("Tile ({x}, {y}) is occupied")]
+    TileOccupied {
+        x: i32,
+        y: i32,
+    },
+
+    #[error(transparent)]
+    PathfindingError(#[from] PathfindingError),
 }
 
 pub type MovementSystemResult<T> = Result<T, MovementSystemError>;
 
-pub struct MovementSystem {
-    components: HashMap<EntityId, MovementComponent>,
+/// Moves entities having both movement and position through their queued steps.
+/// Step always ends on tile boundary, blocking entity holds the tile it steps on since the step starts.
+pub fn tick(
+    components: &mut ComponentStorage,
+    grid: &mut SpatialGrid,
+    map: Option<&TileMap>,
+    occupancy: &mut TileOccupancy,
+    pathfinding_options: &PathfindingOptions,
+    dt: f32
+) {
+    for (eid, mc, pc) in components.query2_mut::<MovementComponent, PositionComponent>() {
+        let position = *pc.get_position();
+
+        // In the same tick step must be started and updated
+        if mc.state.is_none() && !start_step(eid, mc, &position, map, occupancy, pathfinding_options) {
+            continue;
+        }
+
+        // Safe unwraps - step started above
+        let step_target = *mc.steps.front().unwrap();
+        let state = mc.state.as_mut().unwrap();
+        state.elapsed += dt;
+        let t = (state.elapsed / state.duration).min(1.0);
+        let new_position = if t >= 1.0 {
+            // Step finished
+            mc.steps.pop_front();
+            mc.state = None;
+            occupancy.settle(eid, nearest_tile(&step_target));
+            step_target
+        } else {
+            // Translate, tile left behind gets free once entity is closer to the next one
+            let new_position = Vec2F::lerp(&state.start, &step_target, t);
+            let previous_tile = nearest_tile(&position);
+            if nearest_tile(&new_position) != previous_tile {
+                occupancy.release(eid, &previous_tile);
+            }
+            new_position
+        };
+        position_system::set_position(grid, eid, pc, new_position);
+    }
 }
 
-impl MovementSystem {
-    pub fn new() -> Self {
-        MovementSystem {
-            components: HashMap::new(),
+/// Starts the first queued step. When its tile got taken by other entity,
+/// steps are replaced by path around it. Returns `false` if there is no step to make.
+fn start_step(
+    entity_id: EntityId,
+    mc: &mut MovementComponent,
+    position: &Vec2F,
+    map: Option<&TileMap>,
+    occupancy: &mut TileOccupancy,
+    pathfinding_options: &PathfindingOptions
+) -> bool {
+    while let Some(step_target) = mc.steps.front().copied() {
+        if step_target == *position {
+            mc.steps.pop_front();
+            continue;
         }
-    }
 
-    pub fn tick(&mut self, position_system: &mut PositionSystem, dt: f32) {
-        for (eid, mc) in self.components.iter_mut() {
-            if let Some((target_position, movement_state)) = &mut mc.target {
-                let mut pc = match position_system.get_component_mut(eid) {
-                    Some(p) => p,
-                    None => {
-                        tracing::error!("Skipped entity {eid}: missing position component, cannot move!");
-                        continue;
-                    }
-                };
-
-                // In the same tick movement must be started and updated
-
-                // Attempt to start moving if not moving
-                if movement_state.is_none() {
-                    // Attempt to start moving
-                    if pc.get_position() == target_position {
-                        // Already reached target
-                        mc.target = None;
-                        continue;
-                    } else {
-                        // Start moving
-                        let translation = *target_position - *pc.get_position();
-                        let duration = translation.get_length() / mc.speed;
-                        *movement_state = Some(MovementState::new(*pc.get_position(), duration));
-                    }
-                }
-
-                // Stop or update moving
-                let state = movement_state.as_mut().unwrap();
-                // Already moving
-                state.elapsed += dt;
-                let t = (state.elapsed / state.duration).min(1.0);
-                if t >=  1.0 {
-                    // Target reached
-                    pc.set_position(*target_position);
-                    mc.target = None;
-                } else {
-                    // Translate
-                    let position = Vec2F::lerp(&state.start, &target_position, t);
-                    pc.set_position(position);
-                };
+        if reserve(occupancy, entity_id, nearest_tile(&step_target)) {
+            let translation = step_target - *position;
+            if let Some(facing) = facing_direction(&translation) {
+                mc.facing = facing;
             }
+            mc.state = Some(MovementState::new(*position, translation.get_length() / mc.speed));
+            return true;
+        }
+
+        // Safe unwrap - steps not empty
+        let destination = nearest_tile(mc.get_destination().unwrap());
+        mc.steps.clear();
+        let path = pathfinding::find_path(nearest_tile(position), destination, pathfinding_options, |tile| {
+            is_passable(map, occupancy, entity_id, tile)
+        });
+        match path {
+            // First tile of new path is free, so it gets reserved in the next iteration
+            Ok(path) => mc.steps = path.into_iter().map(tile_to_position).collect(),
+            Err(e) => tracing::debug!("Entity {entity_id} stopped, reason: '{e}'"),
         }
     }
+    false
+}
 
-    pub fn move_entity_to(&mut self, entity_id: EntityId, target: Vec2F) -> MovementSystemResult<()> {
-        let target = align_vec2f_to_tile(target);
+/// Queues step to the neighbouring tile, replacing steps queued after the current one
+pub fn step_entity(
+    components: &mut ComponentStorage,
+    map: Option<&TileMap>,
+    occupancy: &mut TileOccupancy,
+    entity_id: EntityId,
+    direction: Direction
+) -> MovementSystemResult<Vec2F> {
+    let position = *position_system::get_position(components, &entity_id)
+        .ok_or(MovementSystemError::NoPositionComponent)?;
+    let mc = components
+        .get_mut::<MovementComponent>(&entity_id)
+        .ok_or(MovementSystemError::NoMoveComponent)?;
+
+    let start = get_current_step(mc).unwrap_or(tile_to_position(nearest_tile(&position)));
+    let target = start + step_translation(direction);
+    check_destination(map, occupancy, entity_id, &target)?;
+
+    replace_steps(entity_id, mc, [target], occupancy);
+    Ok(target)
+}
 
-        let mc = match self.components.get_mut(&entity_id) {
-            Some(mc) => mc,
-            None => {
-                return Err(MovementSystemError::NoMoveComponent);
-            },
-        };
+/// Queues steps to `target` aligned to the nearest tile, along the shortest path around unwalkable tiles
+/// and tiles held by other blocking entities. Replaces steps queued after the current one.
+pub fn move_entity_along_path(
+    components: &mut ComponentStorage,
+    map: Option<&TileMap>,
+    occupancy: &mut TileOccupancy,
+    pathfinding_options: &PathfindingOptions,
+    entity_id: EntityId,
+    target: Vec2F
+) -> MovementSystemResult<Vec2F> {
+    let target = tile_to_position(nearest_tile(&target));
+
+    let mc = components
+        .get::<MovementComponent>(&entity_id)
+        .ok_or(MovementSystemError::NoMoveComponent)?;
+    let current_step = get_current_step(mc);
+
+    let position = *position_system::get_position(components, &entity_id)
+        .ok_or(MovementSystemError::NoPositionComponent)?;
+
+    check_destination(map, occupancy, entity_id, &target)?;
+
+    let start = nearest_tile(&current_step.unwrap_or(position));
+    let path = pathfinding::find_path(start, nearest_tile(&target), pathfinding_options, |tile| {
+        is_passable(map, occupancy, entity_id, tile)
+    })?;
+
+    // Safe unwrap - checked above
+    let mc = components.get_mut::<MovementComponent>(&entity_id).unwrap();
+    replace_steps(entity_id, mc, path.into_iter().map(tile_to_position), occupancy);
+    Ok(target)
+}
 
-        if mc.target.is_some() {
-            return Err(MovementSystemError::AlreadyMoving);
-        }
+/// Step already reserved, it gets finished no matter what gets queued after it
+fn get_current_step(mc: &MovementComponent) -> Option<Vec2F> {
+    mc.steps.front().copied()
+}
 
-        mc.target = Some((target, None));
+/// Tile entity stands on, or the one it steps onto while in the middle of a step
+pub fn get_settled_tile(components: &ComponentStorage, entity_id: &EntityId) -> Option<TilePosition> {
+    let position = position_system::get_position(components, entity_id)?;
+    let current_step = components
+        .get::<MovementComponent>(entity_id)
+        .filter(|mc| mc.state.is_some())
+        .and_then(get_current_step);
+    Some(nearest_tile(&current_step.unwrap_or(*position)))
+}
 
-        Ok(())
+fn check_destination(map: Option<&TileMap>, occupancy: &TileOccupancy, entity_id: EntityId, target: &Vec2F) -> MovementSystemResult<()> {
+    let tile = nearest_tile(target);
+    let (x, y) = tile;
+    if !collision::is_walkable(map, &tile) {
+        return Err(MovementSystemError::TileNotWalkable { x, y });
     }
-
-
-    pub fn get_component(&self, entity: &EntityId) -> Option<&MovementComponent> {
-        self.components.get(entity)
+    if !is_passable(map, occupancy, entity_id, &tile) {
+        return Err(MovementSystemError::TileOccupied { x, y });
     }
+    Ok(())
+}
 
-    pub fn get_component_mut(&mut self, entity: &EntityId) -> Option<&mut MovementComponent> {
-        self.components.get_mut(entity)
+/// Current step is kept. Without one, the first new step gets reserved right away,
+/// so of entities stepping into the same tile the first one wins.
+/// Steps must be checked to be passable beforehand.
+fn replace_steps(
+    entity_id: EntityId,
+    mc: &mut MovementComponent,
+    steps: impl IntoIterator<Item = Vec2F>,
+    occupancy: &mut TileOccupancy
+) {
+    if mc.is_moving() {
+        mc.steps.truncate(1);
+        mc.steps.extend(steps);
+        return;
     }
 
-    pub fn add_component(&mut self, entity: EntityId, component: MovementComponent) -> MovementSystemResult<()> {
-        if self.components.contains_key(&entity) {
-            return Err(MovementSystemError::ComponentAlreadyAdded(component))
-        } else {
-            self.components.insert(entity, component);
-            Ok(())
-        }
+    mc.steps = steps.into_iter().collect();
+    if let Some(first_step) = mc.steps.front() {
+        reserve(occupancy, entity_id, nearest_tile(first_step));
     }
-}
\ No newline at end of file
+}
+
+/// Non blocking entities need no tiles
+fn reserve(occupancy: &mut TileOccupancy, entity_id: EntityId, tile: TilePosition) -> bool {
+    !occupancy.is_blocking(entity_id) || occupancy.claim(entity_id, tile)
+}
+
+fn is_passable(map: Option<&TileMap>, occupancy: &TileOccupancy, entity_id: EntityId, tile: &TilePosition) -> bool {
+    collision::is_walkable(map, tile) && (!occupancy.is_blocking(entity_id) || occupancy.is_free_for(entity_id, tile))
+}
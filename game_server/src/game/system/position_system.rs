@@ -1,45 +1,77 @@
-use crate::game::entity::component::PositionComponent;
+use crate::game::entity::component::{ComponentStorage, PositionComponent};
+use crate::game::entity::component::storage::ComponentResult;
 use crate::game::entity::EntityId;
 use crate::game::math::Vec2F;
-use std::collections::HashMap;
+use crate::game::spatial_grid::SpatialGrid;
 
-#[derive(Debug, thiserror::Error)]
-pub enum PositionSystemError {
-    #[error("Component already added")]
-    ComponentAlreadyAdded(PositionComponent)
-}
+// Positions change only through these functions, so spatial grid stays in sync with components
 
-pub type PositionSystemResult<T> = Result<T, PositionSystemError>;
+pub fn get_position<'a>(components: &'a ComponentStorage, entity: &EntityId) -> Option<&'a Vec2F> {
+    components.get::<PositionComponent>(entity).map(|pc| pc.get_position())
+}
 
-pub struct PositionSystem {
-    components: HashMap<EntityId, PositionComponent>,
+pub fn add_component(
+    components: &mut ComponentStorage,
+    grid: &mut SpatialGrid,
+    entity: EntityId,
+    component: PositionComponent
+) -> ComponentResult<()> {
+    let position = *component.get_position();
+    components.insert(entity, component)?;
+    grid.update(entity, &position);
+    Ok(())
 }
 
-impl PositionSystem {
-    pub fn new() -> Self {
-        PositionSystem {
-            components: HashMap::new(),
-        }
-    }
+pub fn remove_component(components: &mut ComponentStorage, grid: &mut SpatialGrid, entity: &EntityId) -> Option<PositionComponent> {
+    grid.remove(*entity);
+    components.remove(entity)
+}
 
-    pub fn get_component(&self, entity: &EntityId) -> Option<&PositionComponent> {
-        self.components.get(entity)
-    }
+pub fn set_position(grid: &mut SpatialGrid, entity: EntityId, pc: &mut PositionComponent, position: Vec2F) {
+    pc.set_position(position);
+    grid.update(entity, &position);
+}
 
-    pub fn get_component_mut(&mut self, entity: &EntityId) -> Option<&mut PositionComponent> {
-        self.components.get_mut(entity)
-    }
+/// Entities within square of `2 * range` side centered at `center`
+pub fn get_entities_in_range(components: &ComponentStorage, grid: &SpatialGrid, center: &Vec2F, range: f32) -> Vec<EntityId> {
+    grid
+        .get_entities_near(center, range)
+        .filter(|entity| {
+            get_position(components, entity).is_some_and(|position| {
+                (position.x - center.x).abs() <= range && (position.y - center.y).abs() <= range
+            })
+        })
+        .collect()
+}
 
-    pub fn get_position(&self, entity: &EntityId) -> Option<&Vec2F> {
-        self.components.get(entity).map(|pc| pc.get_position())
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    pub fn add_component(&mut self, entity: EntityId, component: PositionComponent) -> PositionSystemResult<()> {
-        if self.components.contains_key(&entity) {
-            return Err(PositionSystemError::ComponentAlreadyAdded(component))
-        } else {
-            self.components.insert(entity, component);
-            Ok(())
+    #[test]
+    fn test_finding_entities_in_range() {
+        let mut components = ComponentStorage::new();
+        let mut grid = SpatialGrid::new();
+        for entity in 0..2000 {
+            let position = Vec2F::new((entity % 100) as f32, (entity / 100) as f32);
+            add_component(&mut components, &mut grid, entity, PositionComponent::new(position)).unwrap();
         }
+
+        let mut in_range = get_entities_in_range(&components, &grid, &Vec2F::new(0.0, 0.0), 1.0);
+        in_range.sort();
+        assert_eq!(in_range, vec![0, 1, 100, 101]);
+
+        // Moved entity is found at new position only
+        let pc = components.get_mut::<PositionComponent>(&101).unwrap();
+        set_position(&mut grid, 101, pc, Vec2F::new(50.0, 10.0));
+        let mut in_range = get_entities_in_range(&components, &grid, &Vec2F::new(0.0, 0.0), 1.0);
+        in_range.sort();
+        assert_eq!(in_range, vec![0, 1, 100]);
+        let in_range = get_entities_in_range(&components, &grid, &Vec2F::new(50.0, 10.0), 0.0);
+        assert_eq!(in_range.len(), 2);
+
+        remove_component(&mut components, &mut grid, &0);
+        let in_range = get_entities_in_range(&components, &grid, &Vec2F::new(0.0, 0.0), 0.0);
+        assert!(in_range.is_empty());
     }
 }
@@ -1,7 +1,11 @@
 use crate::game::math::Vec2F;
+use crate::requests::Direction;
 
 pub const TILE_SIZE: f32 = 1.0;
 
+/// Tile coordinates, in tiles
+pub type TilePosition = (i32, i32);
+
 pub const fn align_to_tile(position: f32) -> f32 {
     let int_part = position as i32;
     if position >= 0.0 {
@@ -9,12 +13,53 @@ pub const fn align_to_tile(position: f32) -> f32 {
     } else if (int_part as f32) == position {
         position // already aligned
     } else {
-        (int_part - 1) as f32 // move to lower tile
+        int_part.saturating_sub(1) as f32 // move to lower tile, saturated like the cast above
     }
 }
 
-pub const fn align_vec2f_to_tile(v: Vec2F) -> Vec2F {
-    Vec2F::new(align_to_tile(v.x),align_to_tile(v.y))
+/// Tile which center is the closest to position
+pub const fn nearest_tile(position: &Vec2F) -> TilePosition {
+    let x = align_to_tile(position.x / TILE_SIZE + 0.5) as i32;
+    let y = align_to_tile(position.y / TILE_SIZE + 0.5) as i32;
+    (x, y)
+}
+
+pub const fn tile_to_position(tile: TilePosition) -> Vec2F {
+    Vec2F::new(tile.0 as f32 * TILE_SIZE, tile.1 as f32 * TILE_SIZE)
+}
+
+/// Tiles at `radius` from center, both along rows and columns, straight neighbours before diagonal ones
+pub fn ring_tiles(center: TilePosition, radius: i32) -> Vec<TilePosition> {
+    let mut offsets: Vec<TilePosition> = (-radius..=radius)
+        .flat_map(|dy| (-radius..=radius).map(move |dx| (dx, dy)))
+        .filter(|(dx, dy)| dx.abs().max(dy.abs()) == radius)
+        .collect();
+    offsets.sort_by_key(|(dx, dy)| dx.abs() + dy.abs());
+    offsets
+        .into_iter()
+        .map(|(dx, dy)| (center.0.saturating_add(dx), center.1.saturating_add(dy)))
+        .collect()
+}
+
+/// Translation by single tile, `y` grows downwards
+pub const fn step_translation(direction: Direction) -> Vec2F {
+    match direction {
+        Direction::Up => Vec2F::new(0.0, -TILE_SIZE),
+        Direction::Down => Vec2F::new(0.0, TILE_SIZE),
+        Direction::Left => Vec2F::new(-TILE_SIZE, 0.0),
+        Direction::Right => Vec2F::new(TILE_SIZE, 0.0),
+    }
+}
+
+/// Direction entity faces when moving by `translation`, horizontal one wins for diagonals
+pub fn facing_direction(translation: &Vec2F) -> Option<Direction> {
+    if translation.x == 0.0 && translation.y == 0.0 {
+        None
+    } else if translation.x.abs() >= translation.y.abs() {
+        Some(if translation.x > 0.0 { Direction::Right } else { Direction::Left })
+    } else {
+        Some(if translation.y > 0.0 { Direction::Down } else { Direction::Up })
+    }
 }
 
 #[cfg(test)]
@@ -29,17 +74,13 @@ mod tests {
 
         assert_eq!(align_to_tile(0.0), 0.0);
 
-        let v1 = Vec2F::new(1.1, 0.0);
-        assert_eq!(align_vec2f_to_tile(v1), Vec2F::new(1.0, 0.0));
+        assert_eq!(align_to_tile(1.1), 1.0);
 
-        let v2 = Vec2F::new(1.0, 0.0);
-        assert_eq!(align_vec2f_to_tile(v2), Vec2F::new(1.0, 0.0));
+        assert_eq!(align_to_tile(1.0), 1.0);
 
-        let v3 = Vec2F::new(0.1, 0.0);
-        assert_eq!(align_vec2f_to_tile(v3), Vec2F::new(0.0, 0.0));
+        assert_eq!(align_to_tile(0.1), 0.0);
 
-        let v4 = Vec2F::new(0.0, 0.0);
-        assert_eq!(align_vec2f_to_tile(v4), Vec2F::new(0.0, 0.0));
+        assert_eq!(align_to_tile(0.0), 0.0);
     }
     #[test]
     fn test_align_to_tile_negative() {
@@ -47,16 +88,30 @@ mod tests {
 
         assert_eq!(align_to_tile(-0.2), -1.0);
 
-        let v1 = Vec2F::new(-0.1, 0.0);
-        assert_eq!(align_vec2f_to_tile(v1), Vec2F::new(-1.0, 0.0));
+        assert_eq!(align_to_tile(-0.1), -1.0);
+
+        assert_eq!(align_to_tile(-0.6), -1.0);
+
+        assert_eq!(align_to_tile(-1.0), -1.0);
+
+        assert_eq!(align_to_tile(-1.1), -2.0);
 
-        let v2 = Vec2F::new(-0.6, 0.0);
-        assert_eq!(align_vec2f_to_tile(v2), Vec2F::new(-1.0, 0.0));
+        assert_eq!(align_to_tile(-3e9), i32::MIN as f32);
+    }
 
-        let v3 = Vec2F::new(-1.0, 0.0);
-        assert_eq!(align_vec2f_to_tile(v3), Vec2F::new(-1.0, 0.0));
+    #[test]
+    fn test_ring_tiles() {
+        assert_eq!(ring_tiles((2, 3), 0), vec![(2, 3)]);
+        assert_eq!(ring_tiles((0, 0), 1), vec![(0, -1), (-1, 0), (1, 0), (0, 1), (-1, -1), (1, -1), (-1, 1), (1, 1)]);
+        assert_eq!(ring_tiles((0, 0), 2).len(), 16);
+    }
 
-        let v4 = Vec2F::new(-1.1, 0.0);
-        assert_eq!(align_vec2f_to_tile(v4), Vec2F::new(-2.0, 0.0));
+    #[test]
+    fn test_facing_direction() {
+        assert_eq!(facing_direction(&step_translation(Direction::Up)), Some(Direction::Up));
+        assert_eq!(facing_direction(&step_translation(Direction::Down)), Some(Direction::Down));
+        assert_eq!(facing_direction(&Vec2F::new(-1.0, 1.0)), Some(Direction::Left));
+        assert_eq!(facing_direction(&Vec2F::new(0.5, -1.0)), Some(Direction::Up));
+        assert_eq!(facing_direction(&Vec2F::new(0.0, 0.0)), None);
     }
-}
\ No newline at end of file
+}
@@ -1,11 +1,21 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{broadcast, mpsc, oneshot};
 use tokio::task::JoinHandle;
-use database_adapter::character::CharacterData;
-use crate::game::entity::component::{MovementComponent, NameComponent, PositionComponent};
+use database_adapter::character::{CharacterData, CharacterId};
+use crate::game::collision::{self, TileOccupancy};
+use crate::game::entity::component::{ComponentStorage, MovementComponent, NameComponent, PositionComponent};
 use crate::game::entity::EntityId;
+use crate::game::map::TileMap;
 use crate::game::math::Vec2F;
-use crate::game::system::{MovementSystem, NameSystem, PositionSystem};
+use crate::game::pathfinding::PathfindingOptions;
+use crate::game::spatial_grid::SpatialGrid;
+use crate::game::system::{movement_system, position_system};
+use crate::game::system::movement_system::{MovementSystemError, MovementSystemResult};
+use crate::game::tile_math::{nearest_tile, ring_tiles, tile_to_position, TilePosition};
+use crate::replication::{EntityState, MovementReplica, Tick, WorldDelta, WorldSnapshot};
+use crate::requests::Direction;
 
 #[derive(Debug, thiserror::Error)]
 pub enum WorldError {
@@ -14,20 +24,91 @@ pub enum WorldError {
 
     #[error(transparent)]
     RecvError(#[from]  oneshot::error::RecvError),
+
+    #[error(transparent)]
+    MovementSystemError(#[from] MovementSystemError),
+
+    #[error("Entity {entity_id} not found")]
+    EntityNotFound {
+        entity_id: EntityId,
+    },
+
+    #[error("No free tile to spawn at around ({x}, {y})")]
+    NoFreeSpawnTile {
+        x: i32,
+        y: i32,
+    },
 }
 
 pub type WorldResult<T> =  Result<T, WorldError>;
 
-const TICK_DURATION_MS: u64 = 32;
-const TICK_DT_SEC: f32 = TICK_DURATION_MS as f32 / 1000.0;
+pub const DEFAULT_TICK_DURATION: Duration = Duration::from_millis(32);
 
 const DEFAULT_CMD_TIMEOUT_MS: u64 = 1000;
 
+/// Rounded down to whole ticks, but at least one tick
+pub const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(10);
+const AUTOSAVE_QUEUE_SIZE: usize = 4;
+/// Subscribers lagging more than this many deltas miss some and have to resync
+const DELTAS_QUEUE_SIZE: usize = 64;
+
+/// Half of the side of square visible around viewer, in tiles
+pub const DEFAULT_VIEW_RANGE: f32 = 16.0;
+
+/// Character whose saved tile is taken or not walkable spawns on the closest free tile that far at most,
+/// around the saved tile first, then around spawn points of the map
+const SPAWN_SEARCH_RADIUS: i32 = 8;
+
+#[derive(Debug, Clone)]
+pub struct WorldOptions {
+    pub view_range: f32,
+    /// World without map has no bounds
+    pub map: Option<TileMap>,
+    pub pathfinding: PathfindingOptions,
+    /// Simulated time of single tick, also real time between ticks
+    pub tick_duration: Duration,
+}
+
+impl Default for WorldOptions {
+    fn default() -> Self {
+        Self {
+            view_range: DEFAULT_VIEW_RANGE,
+            map: None,
+            pathfinding: PathfindingOptions::default(),
+            tick_duration: DEFAULT_TICK_DURATION,
+        }
+    }
+}
+
+/// Deltas of single tick, by viewer entity
+pub type ViewersDeltas = HashMap<EntityId, WorldDelta>;
+
 pub enum WorldManagerCmd {
     GetEntitiesCount,
     SpawnCharacter {
         character_data: CharacterData,
-    }
+    },
+    ExportCharacter {
+        entity_id: EntityId,
+    },
+    ExportAllCharacters,
+    Despawn {
+        entity_id: EntityId,
+    },
+    MoveEntity {
+        entity_id: EntityId,
+        target: Vec2F,
+    },
+    StepEntity {
+        entity_id: EntityId,
+        direction: Direction,
+    },
+    GetPosition {
+        entity_id: EntityId,
+    },
+    GetSnapshot {
+        viewer: EntityId,
+    },
 }
 
 pub struct WorldManagerCmdWrapped {
@@ -36,42 +117,109 @@ pub struct WorldManagerCmdWrapped {
 }
 pub enum WorldManagerCmdResult {
     EntitiesCount(usize),
-    SpawnCharacter(EntityId),
+    SpawnCharacter(WorldResult<(EntityId, Vec2F)>),
+    ExportCharacter(Option<CharacterData>),
+    ExportAllCharacters(Vec<CharacterData>),
+    Despawn(WorldResult<Option<CharacterData>>),
+    MoveEntity(MovementSystemResult<Vec2F>),
+    StepEntity(MovementSystemResult<Vec2F>),
+    GetPosition(Option<Vec2F>),
+    GetSnapshot(WorldSnapshot),
 }
 pub struct WorldManager {
     handle: JoinHandle<()>,
     tx: mpsc::Sender<WorldManagerCmdWrapped>,
+    deltas_tx: broadcast::Sender<Arc<ViewersDeltas>>,
+    view_range: f32,
 }
 
 impl WorldManager {
     pub async fn run() -> Self {
+        Self::spawn_world_task(WorldOptions::default(), None)
+    }
+
+    /// Every `interval` state of all characters in world is sent for saving
+    pub async fn run_with_autosave(interval: Duration) -> (Self, mpsc::Receiver<Vec<CharacterData>>) {
+        Self::run_with_options(WorldOptions::default(), interval).await
+    }
+
+    pub async fn run_with_options(options: WorldOptions, autosave_interval: Duration) -> (Self, mpsc::Receiver<Vec<CharacterData>>) {
+        let (autosave_tx, autosave_rx) = mpsc::channel(AUTOSAVE_QUEUE_SIZE);
+        let autosave_interval_ticks = Self::ticks_in(autosave_interval, options.tick_duration);
+        (Self::spawn_world_task(options, Some((autosave_interval_ticks, autosave_tx))), autosave_rx)
+    }
+
+    /// Whole ticks within `duration`, at least one
+    fn ticks_in(duration: Duration, tick_duration: Duration) -> u64 {
+        let ticks = duration.as_nanos() / tick_duration.as_nanos().max(1);
+        u64::try_from(ticks).unwrap_or(u64::MAX).max(1)
+    }
+
+    fn spawn_world_task(options: WorldOptions, autosave: Option<(u64, mpsc::Sender<Vec<CharacterData>>)>) -> Self {
         let (tx, mut rx) = mpsc::channel::<WorldManagerCmdWrapped>(128);
+        let (deltas_tx, _) = broadcast::channel(DELTAS_QUEUE_SIZE);
+        let task_deltas_tx = deltas_tx.clone();
+        let view_range = options.view_range;
+        let tick_duration = options.tick_duration;
 
         let handle = tokio::spawn(async move {
-            let mut ticker = tokio::time::interval(Duration::from_millis(TICK_DURATION_MS));
+            let mut ticker = tokio::time::interval(tick_duration);
 
-            let mut world = World::new();
+            let mut world = World::with_options(options);
+            let mut tick_number: u64 = 0;
 
             loop {
                 tokio::select! {
                     _ = ticker.tick() => {
-                        world.tick(TICK_DT_SEC);
+                        world.tick(tick_duration.as_secs_f32());
+                        tick_number += 1;
+
+                        let deltas = world.replicate(tick_number);
+                        if !deltas.is_empty() {
+                            // Error only means nobody listens at the moment
+                            let _ = task_deltas_tx.send(Arc::new(deltas));
+                        }
+
+                        if let Some((interval_ticks, autosave_tx)) = &autosave {
+                            if tick_number.is_multiple_of(*interval_ticks) {
+                                let characters = world.export_all_characters_data();
+                                // Never block the world, next autosave will catch up
+                                if !characters.is_empty() && autosave_tx.try_send(characters).is_err() {
+                                    tracing::warn!("Autosave skipped, queue full or closed");
+                                }
+                            }
+                        }
                     },
                     cmd_wrapped = rx.recv() => match cmd_wrapped {
                         Some(cmd_wrapped) => {
                             let cmd_response = match cmd_wrapped.cmd {
                                 WorldManagerCmd::GetEntitiesCount => WorldManagerCmdResult::EntitiesCount(world.entities.len()),
                                 WorldManagerCmd::SpawnCharacter { character_data } => {
-                                    let entity_id = world.generate_new_entity();
-                                    
-                                    // Safe unwraps - newly created entity
-                                    let character_position = Vec2F::new(character_data.position_x, character_data.position_y);
-                                    world.position_system.add_component(entity_id, PositionComponent::new(entity_id, character_position)).unwrap();
-                                    world.movement_system.add_component(entity_id, MovementComponent::new(entity_id, character_data.speed)).unwrap();
-                                    world.name_system.add_component(entity_id, NameComponent::new(entity_id, character_data.name)).unwrap();
-                                    
-                                    WorldManagerCmdResult::SpawnCharacter(entity_id)
-                                }
+                                    let result = world.spawn_character(character_data).map(|entity_id| {
+                                        // Safe unwrap - spawned with position
+                                        (entity_id, *position_system::get_position(&world.components, &entity_id).unwrap())
+                                    });
+                                    WorldManagerCmdResult::SpawnCharacter(result)
+                                },
+                                WorldManagerCmd::ExportCharacter { entity_id } => {
+                                    WorldManagerCmdResult::ExportCharacter(world.export_character_data(&entity_id))
+                                },
+                                WorldManagerCmd::ExportAllCharacters => {
+                                    WorldManagerCmdResult::ExportAllCharacters(world.export_all_characters_data())
+                                },
+                                WorldManagerCmd::Despawn { entity_id } => {
+                                    WorldManagerCmdResult::Despawn(world.despawn_entity(entity_id))
+                                },
+                                WorldManagerCmd::MoveEntity { entity_id, target } => {
+                                    WorldManagerCmdResult::MoveEntity(world.move_entity_to(entity_id, target))
+                                },
+                                WorldManagerCmd::StepEntity { entity_id, direction } => {
+                                    WorldManagerCmdResult::StepEntity(world.step_entity(entity_id, direction))
+                                },
+                                WorldManagerCmd::GetPosition { entity_id } => {
+                                    WorldManagerCmdResult::GetPosition(position_system::get_position(&world.components, &entity_id).copied())
+                                },
+                                WorldManagerCmd::GetSnapshot { viewer } => WorldManagerCmdResult::GetSnapshot(world.snapshot_for_viewer(viewer)),
                             };
                             if cmd_wrapped.response.send(cmd_response).is_err() {
                                 tracing::warn!("Cmd response dropped")
@@ -86,7 +234,16 @@ impl WorldManager {
             }
         });
 
-        Self { handle, tx }
+        Self { handle, tx, deltas_tx, view_range }
+    }
+
+    /// Deltas of ticks after subscribing, for every viewer. To be applied on top of `get_snapshot`.
+    pub fn subscribe_deltas(&self) -> broadcast::Receiver<Arc<ViewersDeltas>> {
+        self.deltas_tx.subscribe()
+    }
+
+    pub fn get_view_range(&self) -> f32 {
+        self.view_range
     }
 
     pub async fn request_cmd_with_timeout(&self, cmd: WorldManagerCmd, timeout_time: Duration) -> WorldResult<WorldManagerCmdResult> {
@@ -109,43 +266,317 @@ impl WorldManager {
         }
     }
 
-    pub async fn spawn_character_entity(&self, character_data: CharacterData) -> WorldResult<EntityId> {
+    /// Returns position character got spawned at, which differs from the saved one if that was taken
+    pub async fn spawn_character_entity(&self, character_data: CharacterData) -> WorldResult<(EntityId, Vec2F)> {
         match self.request_cmd_with_default_timeout(WorldManagerCmd::SpawnCharacter { character_data }).await {
-            Ok(WorldManagerCmdResult::SpawnCharacter(entity_id)) => Ok(entity_id),
+            Ok(WorldManagerCmdResult::SpawnCharacter(result)) => result,
             Err(err) => Err(err),
             Ok(_) => panic!("Failed to spawn character entity - bad WorldManagerCmdResult"),
         }
     }
+
+    pub async fn export_character_data(&self, entity_id: EntityId) -> WorldResult<Option<CharacterData>> {
+        match self.request_cmd_with_default_timeout(WorldManagerCmd::ExportCharacter { entity_id }).await {
+            Ok(WorldManagerCmdResult::ExportCharacter(character_data)) => Ok(character_data),
+            Err(err) => Err(err),
+            Ok(_) => panic!("Failed to export character data - bad WorldManagerCmdResult"),
+        }
+    }
+
+    pub async fn export_all_characters_data(&self) -> WorldResult<Vec<CharacterData>> {
+        match self.request_cmd_with_default_timeout(WorldManagerCmd::ExportAllCharacters).await {
+            Ok(WorldManagerCmdResult::ExportAllCharacters(characters)) => Ok(characters),
+            Err(err) => Err(err),
+            Ok(_) => panic!("Failed to export characters data - bad WorldManagerCmdResult"),
+        }
+    }
+
+    /// Returns last state of despawned entity if it was a character
+    pub async fn despawn_entity(&self, entity_id: EntityId) -> WorldResult<Option<CharacterData>> {
+        match self.request_cmd_with_default_timeout(WorldManagerCmd::Despawn { entity_id }).await {
+            Ok(WorldManagerCmdResult::Despawn(result)) => result,
+            Err(err) => Err(err),
+            Ok(_) => panic!("Failed to despawn entity - bad WorldManagerCmdResult"),
+        }
+    }
+
+    /// Returns target aligned to tile
+    pub async fn move_entity_to(&self, entity_id: EntityId, target: Vec2F) -> WorldResult<Vec2F> {
+        match self.request_cmd_with_default_timeout(WorldManagerCmd::MoveEntity { entity_id, target }).await {
+            Ok(WorldManagerCmdResult::MoveEntity(result)) => Ok(result?),
+            Err(err) => Err(err),
+            Ok(_) => panic!("Failed to move entity - bad WorldManagerCmdResult"),
+        }
+    }
+
+    /// Returns target of the step
+    pub async fn step_entity(&self, entity_id: EntityId, direction: Direction) -> WorldResult<Vec2F> {
+        match self.request_cmd_with_default_timeout(WorldManagerCmd::StepEntity { entity_id, direction }).await {
+            Ok(WorldManagerCmdResult::StepEntity(result)) => Ok(result?),
+            Err(err) => Err(err),
+            Ok(_) => panic!("Failed to step entity - bad WorldManagerCmdResult"),
+        }
+    }
+
+    /// Entities visible by `viewer`, it receives deltas from now on
+    pub async fn get_snapshot(&self, viewer: EntityId) -> WorldResult<WorldSnapshot> {
+        match self.request_cmd_with_default_timeout(WorldManagerCmd::GetSnapshot { viewer }).await {
+            Ok(WorldManagerCmdResult::GetSnapshot(snapshot)) => Ok(snapshot),
+            Err(err) => Err(err),
+            Ok(_) => panic!("Failed to get snapshot - bad WorldManagerCmdResult"),
+        }
+    }
+
+    pub async fn get_position(&self, entity_id: EntityId) -> WorldResult<Option<Vec2F>> {
+        match self.request_cmd_with_default_timeout(WorldManagerCmd::GetPosition { entity_id }).await {
+            Ok(WorldManagerCmdResult::GetPosition(position)) => Ok(position),
+            Err(err) => Err(err),
+            Ok(_) => panic!("Failed to get position - bad WorldManagerCmdResult"),
+        }
+    }
 }
 
 
 pub struct World {
-    entities: Vec<EntityId>,
+    entities: HashSet<EntityId>,
     next_entity_id: EntityId,
-    position_system: PositionSystem,
-    movement_system: MovementSystem,
-    name_system: NameSystem,
+    characters_entities: HashMap<EntityId, CharacterId>,
+    components: ComponentStorage,
+    /// Index of position components
+    grid: SpatialGrid,
+    /// Tiles held by characters, they can't walk through each other
+    occupancy: TileOccupancy,
+    /// State as of last replication, deltas are computed against it
+    replicated_entities: HashMap<EntityId, EntityState>,
+    replicated_tick: Tick,
+    viewers: HashMap<EntityId, Viewer>,
+    options: WorldOptions,
+}
+
+/// Entity that receives deltas of entities around it
+struct Viewer {
+    visible: HashSet<EntityId>,
+    /// Tick of the last delta or snapshot sent to viewer
+    tick: Tick,
 }
 
 impl World {
-    pub fn new() -> Self {
+    pub fn with_options(options: WorldOptions) -> Self {
         Self {
-            entities: vec![],
+            entities: HashSet::new(),
             next_entity_id: 0,
-            position_system: PositionSystem::new(),
-            movement_system: MovementSystem::new(),
-            name_system: NameSystem::new(),
+            characters_entities: HashMap::new(),
+            components: ComponentStorage::new(),
+            grid: SpatialGrid::new(),
+            occupancy: TileOccupancy::new(),
+            replicated_entities: HashMap::new(),
+            replicated_tick: 0,
+            viewers: HashMap::new(),
+            options,
+        }
+    }
+
+    /// Character is placed on its saved tile, or the closest free one if that is taken or not walkable
+    pub fn spawn_character(&mut self, character_data: CharacterData) -> WorldResult<EntityId> {
+        let saved_tile = nearest_tile(&Vec2F::new(character_data.position_x, character_data.position_y));
+        let spawn_tile = self.find_spawn_tile(saved_tile)
+            .ok_or(WorldError::NoFreeSpawnTile { x: saved_tile.0, y: saved_tile.1 })?;
+        let entity_id = self.generate_new_entity();
+
+        // Safe unwraps - newly created entity
+        let character_position = tile_to_position(spawn_tile);
+        let position_component = PositionComponent::new(character_position);
+        position_system::add_component(&mut self.components, &mut self.grid, entity_id, position_component).unwrap();
+        self.components.insert(entity_id, MovementComponent::new(character_data.speed)).unwrap();
+        self.components.insert(entity_id, NameComponent::new(character_data.name)).unwrap();
+        self.characters_entities.insert(entity_id, character_data.id);
+        // Free tile found above
+        self.occupancy.add_entity(entity_id, spawn_tile);
+
+        Ok(entity_id)
+    }
+
+    fn find_spawn_tile(&self, saved_tile: TilePosition) -> Option<TilePosition> {
+        let map = self.options.map.as_ref();
+        let spawn_points = map
+            .into_iter()
+            .flat_map(|map| map.get_spawn_points())
+            .map(|spawn_point| (spawn_point.x, spawn_point.y));
+
+        std::iter::once(saved_tile)
+            .chain(spawn_points)
+            .find_map(|center| {
+                (0..=SPAWN_SEARCH_RADIUS)
+                    .flat_map(|radius| ring_tiles(center, radius))
+                    .find(|tile| collision::is_walkable(map, tile) && self.occupancy.get_occupant(tile).is_none())
+            })
+    }
+
+    /// Current state of character entity, `None` if entity is not a character
+    pub fn export_character_data(&self, entity_id: &EntityId) -> Option<CharacterData> {
+        let character_id = *self.characters_entities.get(entity_id)?;
+        // Saved aligned to tile, so character spawns on a whole tile again
+        let position = tile_to_position(movement_system::get_settled_tile(&self.components, entity_id)?);
+        let speed = self.components.get::<MovementComponent>(entity_id)?.speed;
+        let name = self.components.get::<NameComponent>(entity_id)?.get_name();
+
+        Some(CharacterData {
+            id: character_id,
+            name: name.to_string(),
+            position_x: position.x,
+            position_y: position.y,
+            speed,
+        })
+    }
+
+    /// Removes entity with all its components, it stops being viewer.
+    /// Viewers get it in `left` of the next delta.
+    pub fn despawn_entity(&mut self, entity_id: EntityId) -> WorldResult<Option<CharacterData>> {
+        if !self.entities.remove(&entity_id) {
+            return Err(WorldError::EntityNotFound { entity_id });
+        }
+        let character_data = self.export_character_data(&entity_id);
+
+        position_system::remove_component(&mut self.components, &mut self.grid, &entity_id);
+        self.components.remove_all(&entity_id);
+        self.characters_entities.remove(&entity_id);
+        self.occupancy.remove_entity(entity_id);
+        self.viewers.remove(&entity_id);
+        self.replicated_entities.remove(&entity_id);
+
+        Ok(character_data)
+    }
+
+    pub fn export_all_characters_data(&self) -> Vec<CharacterData> {
+        self.characters_entities
+            .keys()
+            .filter_map(|entity_id| self.export_character_data(entity_id))
+            .collect()
+    }
+
+    /// Moves entity to the neighbouring tile, after the step in progress if moving
+    pub fn step_entity(&mut self, entity_id: EntityId, direction: Direction) -> MovementSystemResult<Vec2F> {
+        movement_system::step_entity(&mut self.components, self.options.map.as_ref(), &mut self.occupancy, entity_id, direction)
+    }
+
+    /// Moves entity along path found around walls and other characters
+    pub fn move_entity_to(&mut self, entity_id: EntityId, target: Vec2F) -> MovementSystemResult<Vec2F> {
+        movement_system::move_entity_along_path(
+            &mut self.components,
+            self.options.map.as_ref(),
+            &mut self.occupancy,
+            &self.options.pathfinding,
+            entity_id,
+            target
+        )
+    }
+
+    fn get_entity_state(&self, entity_id: EntityId) -> EntityState {
+        EntityState {
+            entity_id,
+            name: self.components.get::<NameComponent>(&entity_id).map(|nc| nc.get_name().to_string()),
+            position: position_system::get_position(&self.components, &entity_id).map(|position| (position.x, position.y)),
+            movement: self.components.get::<MovementComponent>(&entity_id).map(|mc| MovementReplica {
+                target: mc.get_destination().map(|target| (target.x, target.y)),
+                facing: mc.facing,
+                speed: mc.speed,
+            }),
         }
     }
 
+    /// Replicated entities within view range of viewer
+    fn get_visible_entities(&self, viewer: EntityId) -> HashSet<EntityId> {
+        match position_system::get_position(&self.components, &viewer) {
+            Some(center) => position_system::get_entities_in_range(&self.components, &self.grid, center, self.options.view_range)
+                .into_iter()
+                .filter(|entity_id| self.replicated_entities.contains_key(entity_id))
+                .collect(),
+            None => HashSet::new(),
+        }
+    }
+
+    /// Changes since last replication, for each viewer only entities in its view range.
+    /// Viewers without changes are omitted.
+    pub fn replicate(&mut self, tick: Tick) -> ViewersDeltas {
+        let mut changes: HashMap<EntityId, EntityState> = HashMap::new();
+        for entity_id in &self.entities {
+            let state = self.get_entity_state(*entity_id);
+            let change = match self.replicated_entities.get(entity_id) {
+                Some(replicated) => EntityState {
+                    entity_id: *entity_id,
+                    name: state.name.clone().filter(|_| state.name != replicated.name),
+                    position: state.position.filter(|_| state.position != replicated.position),
+                    movement: state.movement.clone().filter(|_| state.movement != replicated.movement),
+                },
+                None => state.clone(),
+            };
+
+            if change.name.is_some() || change.position.is_some() || change.movement.is_some() {
+                changes.insert(*entity_id, change);
+            }
+            self.replicated_entities.insert(*entity_id, state);
+        }
+        self.replicated_tick = tick;
+
+        let mut deltas = ViewersDeltas::new();
+        let viewers: Vec<EntityId> = self.viewers.keys().copied().collect();
+        for viewer_id in viewers {
+            let visible = self.get_visible_entities(viewer_id);
+            let viewer = self.viewers.get_mut(&viewer_id).unwrap();
+
+            let mut entered: Vec<EntityId> = visible.difference(&viewer.visible).copied().collect();
+            let mut left: Vec<EntityId> = viewer.visible.difference(&visible).copied().collect();
+            entered.sort();
+            left.sort();
+
+            let mut changed: Vec<EntityState> = visible
+                .iter()
+                .filter_map(|entity_id| match viewer.visible.contains(entity_id) {
+                    true => changes.get(entity_id).cloned(),
+                    false => self.replicated_entities.get(entity_id).cloned(),
+                })
+                .collect();
+            changed.sort_by_key(|entity| entity.entity_id);
+
+            if !changed.is_empty() || !left.is_empty() {
+                deltas.insert(viewer_id, WorldDelta { tick, base_tick: viewer.tick, changed, entered, left });
+                viewer.tick = tick;
+            }
+            viewer.visible = visible;
+        }
+
+        deltas
+    }
+
+    /// Replicated state of entities visible by viewer, consistent with deltas sent so far.
+    /// Registers viewer or restarts its replication.
+    pub fn snapshot_for_viewer(&mut self, viewer_id: EntityId) -> WorldSnapshot {
+        let visible = self.get_visible_entities(viewer_id);
+        let mut entities: Vec<EntityState> = visible
+            .iter()
+            .filter_map(|entity_id| self.replicated_entities.get(entity_id).cloned())
+            .collect();
+        entities.sort_by_key(|entity| entity.entity_id);
+
+        self.viewers.insert(viewer_id, Viewer { visible, tick: self.replicated_tick });
+        WorldSnapshot { tick: self.replicated_tick, entities }
+    }
+
     pub fn tick(&mut self, dt: f32) {
-        self.movement_system.tick(&mut self.position_system, dt);
+        movement_system::tick(
+            &mut self.components,
+            &mut self.grid,
+            self.options.map.as_ref(),
+            &mut self.occupancy,
+            &self.options.pathfinding,
+            dt
+        );
     }
 
     pub fn generate_new_entity(&mut self) -> EntityId {
         let entity_id = self.next_entity_id;
         self.next_entity_id += 1;
-        self.entities.push(entity_id);
+        self.entities.insert(entity_id);
         entity_id
     }
 }
@@ -154,9 +585,285 @@ impl World {
 mod tests {
     use super::*;
 
+    fn test_character_data(id: CharacterId) -> CharacterData {
+        CharacterData {
+            id,
+            name: "Janusz".to_string(),
+            position_x: 0.0,
+            position_y: 0.0,
+            speed: 1.0,
+        }
+    }
+
     #[tokio::test]
     async fn test_empty_world_count_entities() {
         let world_manager = WorldManager::run().await;
         assert_eq!(world_manager.get_entities_count().await, 0);
     }
+
+    #[test]
+    fn test_exporting_moved_character() {
+        let mut world = World::with_options(WorldOptions::default());
+        let entity_id = world.spawn_character(test_character_data(7)).unwrap();
+
+        world.move_entity_to(entity_id, Vec2F::new(2.0, 0.0)).unwrap();
+        for _ in 0..10 {
+            world.tick(0.25);
+        }
+
+        let character_data = world.export_character_data(&entity_id).unwrap();
+        assert_eq!(character_data.id, 7);
+        assert_eq!(character_data.name, "Janusz");
+        assert!(Vec2F::new(character_data.position_x, character_data.position_y).approx_eq(&Vec2F::new(2.0, 0.0)));
+        assert_eq!(character_data.speed, 1.0);
+
+        assert!(world.export_character_data(&(entity_id + 1)).is_none());
+    }
+
+    #[test]
+    fn test_stepping_character() {
+        let mut world = World::with_options(WorldOptions::default());
+        let entity_id = world.spawn_character(test_character_data(7)).unwrap();
+
+        world.step_entity(entity_id, Direction::Right).unwrap();
+        world.step_entity(entity_id, Direction::Down).unwrap();
+        for _ in 0..8 {
+            world.tick(0.25);
+        }
+        assert!(position_system::get_position(&world.components, &entity_id).unwrap().approx_eq(&Vec2F::new(1.0, 1.0)));
+        world.step_entity(entity_id, Direction::Up).unwrap();
+        world.step_entity(entity_id, Direction::Up).unwrap();
+        for _ in 0..4 {
+            world.tick(0.25);
+        }
+
+        assert!(position_system::get_position(&world.components, &entity_id).unwrap().approx_eq(&Vec2F::new(1.0, 0.0)));
+        assert!(matches!(world.step_entity(entity_id + 1, Direction::Up), Err(MovementSystemError::NoPositionComponent)));
+    }
+
+    #[test]
+    fn test_stepping_from_position_between_tiles() {
+        let mut world = World::with_options(WorldOptions::default());
+        let entity_id = world.spawn_character(test_character_data(7)).unwrap();
+
+        // Closer to the tile on the right, which is the one entity holds
+        teleport(&mut world, entity_id, Vec2F::new(0.6, 0.0));
+        world.occupancy.settle(entity_id, (1, 0));
+        assert_eq!(world.step_entity(entity_id, Direction::Down).unwrap(), Vec2F::new(1.0, 1.0));
+        assert_eq!(world.move_entity_to(entity_id, Vec2F::new(2.6, 0.4)).unwrap(), Vec2F::new(3.0, 0.0));
+    }
+
+    #[test]
+    fn test_stepping_character_into_map_wall_should_fail() {
+        let map_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("maps/example.json");
+        let map = TileMap::load(map_path).unwrap();
+        let mut world = World::with_options(WorldOptions { map: Some(map), ..Default::default() });
+        // Next to the stone border of the map
+        let entity_id = world.spawn_character(CharacterData { position_x: -11.0, ..test_character_data(7) }).unwrap();
+
+        let result = world.step_entity(entity_id, Direction::Left);
+        assert!(matches!(result, Err(MovementSystemError::TileNotWalkable { x: -12, y: 0 })));
+        world.step_entity(entity_id, Direction::Right).unwrap();
+    }
+
+    #[test]
+    fn test_spawning_characters_saved_on_same_tile() {
+        let mut world = World::with_options(WorldOptions::default());
+        let entity_id = world.spawn_character(test_character_data(7)).unwrap();
+        let other_entity_id = world.spawn_character(test_character_data(8)).unwrap();
+
+        assert_eq!(position_system::get_position(&world.components, &entity_id), Some(&Vec2F::new(0.0, 0.0)));
+        assert_eq!(position_system::get_position(&world.components, &other_entity_id), Some(&Vec2F::new(0.0, -1.0)));
+        assert_eq!(world.occupancy.get_occupant(&(0, 0)), Some(entity_id));
+        assert_eq!(world.occupancy.get_occupant(&(0, -1)), Some(other_entity_id));
+    }
+
+    #[test]
+    fn test_spawning_character_saved_on_unwalkable_tile() {
+        let map_path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("maps/example.json");
+        let map = TileMap::load(map_path).unwrap();
+        let mut world = World::with_options(WorldOptions { map: Some(map), ..Default::default() });
+
+        // Stone border of the map, the tile next to it is walkable
+        let entity_id = world.spawn_character(CharacterData { position_x: -12.0, ..test_character_data(7) }).unwrap();
+        assert_eq!(position_system::get_position(&world.components, &entity_id), Some(&Vec2F::new(-11.0, 0.0)));
+
+        // Far outside of the map, moved to the first spawn point
+        let entity_id = world.spawn_character(CharacterData { position_x: 1000.0, ..test_character_data(8) }).unwrap();
+        assert_eq!(position_system::get_position(&world.components, &entity_id), Some(&Vec2F::new(0.0, 0.0)));
+    }
+
+    #[tokio::test]
+    async fn test_moving_character_through_world_manager() {
+        let world_manager = WorldManager::run().await;
+        let entity_id = world_manager.spawn_character_entity(test_character_data(3)).await.unwrap().0;
+
+        world_manager.move_entity_to(entity_id, Vec2F::new(0.0, 3.0)).await.unwrap();
+        // Replaces the rest of the path
+        world_manager.move_entity_to(entity_id, Vec2F::new(0.0, 1.0)).await.unwrap();
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        let position = world_manager.get_position(entity_id).await.unwrap().unwrap();
+        assert!(position.approx_eq(&Vec2F::new(0.0, 1.0)));
+        assert!(world_manager.get_position(entity_id + 1).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_replicating_changes() {
+        let mut world = World::with_options(WorldOptions::default());
+        assert!(world.replicate(1).is_empty());
+
+        let entity_id = world.spawn_character(test_character_data(7)).unwrap();
+        assert!(world.snapshot_for_viewer(entity_id).entities.is_empty());
+        let delta = world.replicate(2).remove(&entity_id).unwrap();
+        assert_eq!(delta.base_tick, 1);
+        assert_eq!(delta.entered, vec![entity_id]);
+        assert_eq!(delta.changed, vec![EntityState {
+            entity_id,
+            name: Some("Janusz".to_string()),
+            position: Some((0.0, 0.0)),
+            movement: Some(MovementReplica { target: None, facing: Direction::Down, speed: 1.0 }),
+        }]);
+        assert!(world.replicate(3).is_empty());
+
+        world.move_entity_to(entity_id, Vec2F::new(1.0, 0.0)).unwrap();
+        world.tick(0.5);
+        let delta = world.replicate(4).remove(&entity_id).unwrap();
+        assert_eq!(delta.base_tick, 2);
+        assert!(delta.entered.is_empty());
+        assert_eq!(delta.changed, vec![EntityState {
+            entity_id,
+            name: None,
+            position: Some((0.5, 0.0)),
+            movement: Some(MovementReplica { target: Some((1.0, 0.0)), facing: Direction::Right, speed: 1.0 }),
+        }]);
+
+        world.tick(0.5);
+        let delta = world.replicate(5).remove(&entity_id).unwrap();
+        assert_eq!(delta.changed[0].position, Some((1.0, 0.0)));
+        assert_eq!(delta.changed[0].movement, Some(MovementReplica { target: None, facing: Direction::Right, speed: 1.0 }));
+
+        let snapshot = world.snapshot_for_viewer(entity_id);
+        assert_eq!(snapshot.tick, 5);
+        assert_eq!(snapshot.entities[0].position, Some((1.0, 0.0)));
+        assert_eq!(snapshot.entities[0].name.as_deref(), Some("Janusz"));
+    }
+
+    fn teleport(world: &mut World, entity_id: EntityId, position: Vec2F) {
+        let pc = world.components.get_mut::<PositionComponent>(&entity_id).unwrap();
+        position_system::set_position(&mut world.grid, entity_id, pc, position);
+    }
+
+    #[test]
+    fn test_replicating_only_entities_in_view_range() {
+        let mut world = World::with_options(WorldOptions { view_range: 4.0, ..Default::default() });
+        let viewer_id = world.spawn_character(test_character_data(7)).unwrap();
+        let far_entity_id = world.spawn_character(CharacterData { position_x: 10.0, ..test_character_data(8) }).unwrap();
+        world.replicate(1);
+
+        let snapshot = world.snapshot_for_viewer(viewer_id);
+        assert_eq!(snapshot.entities.len(), 1);
+        assert_eq!(snapshot.entities[0].entity_id, viewer_id);
+
+        // Changes of entities out of range are not sent at all
+        teleport(&mut world, far_entity_id, Vec2F::new(9.0, 0.0));
+        assert!(world.replicate(2).is_empty());
+
+        teleport(&mut world, far_entity_id, Vec2F::new(4.0, -4.0));
+        let delta = world.replicate(3).remove(&viewer_id).unwrap();
+        assert_eq!(delta.base_tick, 1);
+        assert_eq!(delta.entered, vec![far_entity_id]);
+        assert_eq!(delta.changed[0].entity_id, far_entity_id);
+        assert_eq!(delta.changed[0].name.as_deref(), Some("Janusz"));
+
+        teleport(&mut world, far_entity_id, Vec2F::new(4.5, -4.0));
+        let delta = world.replicate(4).remove(&viewer_id).unwrap();
+        assert_eq!(delta.left, vec![far_entity_id]);
+        assert!(delta.changed.is_empty());
+    }
+
+    #[test]
+    fn test_despawning_entity() {
+        let mut world = World::with_options(WorldOptions::default());
+        let viewer_id = world.spawn_character(test_character_data(7)).unwrap();
+        let entity_id = world.spawn_character(test_character_data(8)).unwrap();
+        world.snapshot_for_viewer(viewer_id);
+        world.replicate(1);
+
+        let character_data = world.despawn_entity(entity_id).unwrap().unwrap();
+        assert_eq!(character_data.id, 8);
+        assert!(matches!(world.despawn_entity(entity_id), Err(WorldError::EntityNotFound { .. })));
+        assert!(position_system::get_position(&world.components, &entity_id).is_none());
+        assert!(world.components.get::<MovementComponent>(&entity_id).is_none());
+        assert!(world.components.get::<NameComponent>(&entity_id).is_none());
+        assert!(world.export_all_characters_data().iter().all(|character| character.id != 8));
+        assert!(!world.replicated_entities.contains_key(&entity_id));
+
+        let delta = world.replicate(2).remove(&viewer_id).unwrap();
+        assert_eq!(delta.left, vec![entity_id]);
+        assert_eq!(position_system::get_entities_in_range(&world.components, &world.grid, &Vec2F::new(0.0, 0.0), 1.0), vec![viewer_id]);
+
+        // Despawned viewer gets no more deltas
+        world.despawn_entity(viewer_id).unwrap();
+        assert!(world.replicate(3).is_empty());
+        assert!(world.entities.is_empty());
+    }
+
+    #[test]
+    fn test_replicating_to_viewer_among_thousands_of_entities() {
+        const ENTITIES_COUNT: u32 = 3000;
+        let mut world = World::with_options(WorldOptions { view_range: 8.0, ..Default::default() });
+        let viewer_id = world.spawn_character(test_character_data(0)).unwrap();
+        for character_id in 1..ENTITIES_COUNT {
+            let position_x = (character_id % 100) as f32 * 2.0 - 100.0;
+            let position_y = (character_id / 100) as f32 * 2.0 - 30.0;
+            world.spawn_character(CharacterData { position_x, position_y, ..test_character_data(character_id) }).unwrap();
+        }
+        world.replicate(1);
+
+        let snapshot = world.snapshot_for_viewer(viewer_id);
+        // Grid of 2 tiles spacing, 9x9 points fit within the square, viewer among them
+        assert_eq!(snapshot.entities.len(), 81 + 1);
+        assert!(snapshot.entities.iter().all(|entity| {
+            let (x, y) = entity.position.unwrap();
+            x.abs() <= 8.0 && y.abs() <= 8.0
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_and_deltas_from_world_manager() {
+        let world_manager = WorldManager::run().await;
+        let mut deltas_rx = world_manager.subscribe_deltas();
+        let entity_id = world_manager.spawn_character_entity(test_character_data(3)).await.unwrap().0;
+        let initial_snapshot = world_manager.get_snapshot(entity_id).await.unwrap();
+
+        let deltas = deltas_rx.recv().await.unwrap();
+        let delta = &deltas[&entity_id];
+        assert_eq!(delta.base_tick, initial_snapshot.tick);
+        assert_eq!(delta.changed[0].entity_id, entity_id);
+
+        let snapshot = world_manager.get_snapshot(entity_id).await.unwrap();
+        assert_eq!(snapshot.tick, delta.tick);
+        assert_eq!(snapshot.entities, delta.changed);
+    }
+
+    #[test]
+    fn test_autosave_interval_follows_tick_duration() {
+        assert_eq!(WorldManager::ticks_in(Duration::from_secs(10), Duration::from_millis(50)), 200);
+        assert_eq!(WorldManager::ticks_in(Duration::from_secs(10), Duration::from_millis(100)), 100);
+        assert_eq!(WorldManager::ticks_in(Duration::from_millis(10), Duration::from_millis(100)), 1);
+    }
+
+    #[tokio::test]
+    async fn test_autosave_sends_characters() {
+        let (world_manager, mut autosave_rx) = WorldManager::run_with_autosave(DEFAULT_TICK_DURATION).await;
+        let entity_id = world_manager.spawn_character_entity(test_character_data(3)).await.unwrap().0;
+
+        let characters = autosave_rx.recv().await.unwrap();
+        assert_eq!(characters.len(), 1);
+        assert_eq!(characters[0].id, 3);
+
+        let character_data = world_manager.export_character_data(entity_id).await.unwrap().unwrap();
+        assert_eq!(character_data.id, 3);
+    }
 }
\ No newline at end of file
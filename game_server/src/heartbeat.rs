@@ -0,0 +1,87 @@
+use std::time::{Duration, Instant};
+
+/// Microseconds since sender's clock got started, echoed back unchanged by the peer
+pub type Timestamp = u64;
+
+/// Source of ping timestamps, only meaningful to the side which created it
+#[derive(Debug, Clone, Copy)]
+pub struct HeartbeatClock {
+    started_at: Instant,
+}
+
+impl HeartbeatClock {
+    pub fn new() -> Self {
+        Self { started_at: Instant::now() }
+    }
+
+    pub fn now(&self) -> Timestamp {
+        self.started_at.elapsed().as_micros() as Timestamp
+    }
+
+    /// `None` for timestamp from the future, which only a misbehaving peer can echo
+    pub fn elapsed_since(&self, timestamp: Timestamp) -> Option<Duration> {
+        self.now().checked_sub(timestamp).map(Duration::from_micros)
+    }
+}
+
+impl Default for HeartbeatClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Round trip time smoothed like TCP does, so single delayed pong doesn't make it jump
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RttEstimator {
+    smoothed_rtt: Option<Duration>,
+}
+
+impl RttEstimator {
+    /// Weight of the newest sample
+    const SMOOTHING_FACTOR: f64 = 0.125;
+
+    pub fn update(&mut self, sample: Duration) {
+        self.smoothed_rtt = Some(match self.smoothed_rtt {
+            Some(smoothed_rtt) => smoothed_rtt.mul_f64(1.0 - Self::SMOOTHING_FACTOR) + sample.mul_f64(Self::SMOOTHING_FACTOR),
+            None => sample,
+        });
+    }
+
+    /// `None` until the first pong arrives
+    pub fn get_rtt(&self) -> Option<Duration> {
+        self.smoothed_rtt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimating_rtt() {
+        let mut estimator = RttEstimator::default();
+        assert_eq!(estimator.get_rtt(), None);
+
+        estimator.update(Duration::from_millis(80));
+        assert_eq!(estimator.get_rtt(), Some(Duration::from_millis(80)));
+
+        // Spike moves estimate only by its weight
+        estimator.update(Duration::from_millis(880));
+        assert_eq!(estimator.get_rtt(), Some(Duration::from_millis(180)));
+
+        for _ in 0..100 {
+            estimator.update(Duration::from_millis(20));
+        }
+        let rtt = estimator.get_rtt().unwrap();
+        assert!(rtt >= Duration::from_millis(20) && rtt < Duration::from_millis(21), "Estimate not converged: {rtt:?}");
+    }
+
+    #[test]
+    fn test_measuring_elapsed_time() {
+        let clock = HeartbeatClock::new();
+        let timestamp = clock.now();
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(clock.elapsed_since(timestamp).unwrap() >= Duration::from_millis(5));
+        assert_eq!(clock.elapsed_since(clock.now() + 1_000_000), None);
+    }
+}
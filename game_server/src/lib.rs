@@ -1,18 +1,35 @@
-use crate::session::ConnectionSession;
+use crate::session::{ConnectionSession, ConnectionSessionId, SessionLimits};
+use std::collections::HashMap;
 use std::io::ErrorKind;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::{mpsc, oneshot, Notify};
 use tokio::task::JoinHandle;
 use database_adapter::DatabaseAdapter;
+use crate::auth::AccessTokenVerifier;
+use crate::codec::{CodecError, HandshakeError};
+use crate::framing::FrameError;
+use crate::events::GameServerEvent;
 use crate::game::Game;
+use crate::game::map::TileMap;
+use crate::game::world::{WorldOptions, DEFAULT_TICK_DURATION, DEFAULT_VIEW_RANGE};
+use crate::transport::{BoxedFrameReader, BoxedFrameWriter};
 
+pub mod auth;
 pub mod client;
 pub mod session;
 pub mod requests;
 pub mod responses;
+pub mod events;
+pub mod replication;
+pub mod framing;
+pub mod codec;
+pub mod heartbeat;
+pub mod transport;
+pub mod config;
 mod testing;
 mod game;
 
@@ -25,14 +42,51 @@ pub enum GameServerError {
     OneshotRecvError(#[from] tokio::sync::oneshot::error::RecvError),
 
     #[error(transparent)]
-    SerdeJsonError(#[from] serde_json::Error),
+    CodecError(#[from] CodecError),
+
+    #[error(transparent)]
+    HandshakeError(#[from] HandshakeError),
+
+    #[error(transparent)]
+    FrameError(#[from] FrameError),
 }
 
 pub type GameServerResult<T> = Result<T, GameServerError>;
 
+#[derive(Debug, Clone)]
+pub struct GameServerOptions {
+    /// Port 0 lets system pick a free one, see `get_address`
+    pub address: SocketAddr,
+    /// Port 0 lets system pick a free one, see `get_websocket_address`
+    pub websocket_address: SocketAddr,
+    pub session_limits: SessionLimits,
+    pub tick_duration: Duration,
+    /// Half of the side of square visible around character, in tiles
+    pub view_range: f32,
+    /// World without map has no bounds
+    pub map_path: Option<PathBuf>,
+    /// `host:port` of accounts manager, tokens are verified with keys it publishes
+    pub accounts_manager_address: String,
+}
+
+impl Default for GameServerOptions {
+    fn default() -> Self {
+        Self {
+            address: SocketAddr::from(([127, 0, 0, 1], 0)),
+            websocket_address: SocketAddr::from(([127, 0, 0, 1], 0)),
+            session_limits: SessionLimits::default(),
+            tick_duration: DEFAULT_TICK_DURATION,
+            view_range: DEFAULT_VIEW_RANGE,
+            map_path: None,
+            accounts_manager_address: SocketAddr::from(([127, 0, 0, 1], accounts_manager::config::DEFAULT_PORT)).to_string(),
+        }
+    }
+}
+
 pub struct GameServer {
     task_handle: JoinHandle<()>,
     local_address: SocketAddr,
+    websocket_address: SocketAddr,
     commands_tx: mpsc::Sender<ServerCommand>,
     connection_notifications: Arc<Notify>,
 }
@@ -41,15 +95,42 @@ pub struct GameServer {
 pub enum ServerCommand {
     Shutdown,
     CountConnections(oneshot::Sender<usize>),
+    GetSessionsRtt(oneshot::Sender<HashMap<ConnectionSessionId, Option<Duration>>>),
+    SendNotice(String),
 }
 
 impl GameServer {
     const COMMANDS_QUEUE_SIZE: usize = 32;
     const SESSION_END_QUEUE_SIZE: usize = 16;
+    const WEBSOCKET_UPGRADES_QUEUE_SIZE: usize = 16;
 
     pub async fn run(database_adapter: Arc<dyn DatabaseAdapter>) -> tokio::io::Result<Self> {
-        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        Self::run_with_options(database_adapter, GameServerOptions::default()).await
+    }
+
+    pub async fn run_with_options(database_adapter: Arc<dyn DatabaseAdapter>, options: GameServerOptions) -> tokio::io::Result<Self> {
+        let map = options.map_path.as_ref()
+            .map(TileMap::load)
+            .transpose()
+            .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
+        let world_options = WorldOptions {
+            view_range: options.view_range,
+            map,
+            tick_duration: options.tick_duration,
+            ..Default::default()
+        };
+        let token_verifier = AccessTokenVerifier::new(&options.accounts_manager_address, database_adapter.clone())
+            .await
+            .map_err(std::io::Error::other)?;
+        let token_verifier = Arc::new(token_verifier);
+
+        let listener = TcpListener::bind(options.address).await?;
         let local_address = listener.local_addr()?;
+        // Browsers can't open raw TCP socket, so the same sessions are served over WebSocket too
+        let websocket_listener = TcpListener::bind(options.websocket_address).await?;
+        let websocket_address = websocket_listener.local_addr()?;
+        tracing::info!("{}({}) listening on {local_address}, WebSocket on {websocket_address}",
+            env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
         let (commands_tx, mut commands_rx) =
             mpsc::channel::<ServerCommand>(Self::COMMANDS_QUEUE_SIZE);
 
@@ -63,7 +144,8 @@ impl GameServer {
             let mut next_connection_id = 0;
             let mut connection_sessions: Vec<ConnectionSession> = Vec::new();
             let (session_end_tx, mut session_end_rx) = mpsc::channel(Self::SESSION_END_QUEUE_SIZE);
-            let game = Arc::new(Game::new(database_adapter).await);
+            let (upgraded_tx, mut upgraded_rx) = mpsc::channel(Self::WEBSOCKET_UPGRADES_QUEUE_SIZE);
+            let game = Arc::new(Game::with_world_options(database_adapter, world_options).await);
 
             let _ = task_ready_tx.send(()).is_ok();
             
@@ -75,10 +157,12 @@ impl GameServer {
                         if let Ok((stream, address)) = incomming_connection {
                             let new_connection_session = ConnectionSession::new(
                                 next_connection_id,
-                                stream,
+                                transport::split_tcp(stream),
                                 address,
                                 session_end_tx.clone(),
-                                game.clone()
+                                game.clone(),
+                                token_verifier.clone(),
+                                options.session_limits
                             ).await;
 
                             connection_sessions.push(new_connection_session);
@@ -87,6 +171,33 @@ impl GameServer {
                             tracing::warn!("connection immediately terminated");
                         }
                     },
+                    incomming_connection = websocket_listener.accept() => {
+                        if let Ok((stream, address)) = incomming_connection {
+                            // Slow upgrade must not hold accepting other connections
+                            tokio::spawn(Self::upgrade_websocket(stream, address, upgraded_tx.clone(), options.session_limits));
+                        } else {
+                            tracing::warn!("connection immediately terminated");
+                        }
+                    },
+                    upgraded_connection = upgraded_rx.recv() => {
+                        connection_notifications_shared.notify_waiters();
+
+                        // `None` never happens, this task keeps a sender
+                        if let Some((transport, address)) = upgraded_connection {
+                            let new_connection_session = ConnectionSession::new(
+                                next_connection_id,
+                                transport,
+                                address,
+                                session_end_tx.clone(),
+                                game.clone(),
+                                token_verifier.clone(),
+                                options.session_limits
+                            ).await;
+
+                            connection_sessions.push(new_connection_session);
+                            next_connection_id += 1;
+                        }
+                    },
                     dced_session_id = session_end_rx.recv() => {
                         // `None` will happen only if this task get dropped - dont care
                         if let Some(dced_session_id) = dced_session_id {
@@ -105,12 +216,28 @@ impl GameServer {
                         tracing::debug!("Commands received '{cmd:?}'");
                         match cmd {
                             ServerCommand::Shutdown => {
+                                // Characters stay in world until their sessions end, which may not happen before exit
+                                match game.save_all_characters().await {
+                                    Ok(count) => tracing::info!("Saved {count} characters before shutdown"),
+                                    Err(e) => tracing::error!("Could not save characters before shutdown, reason: '{e}'"),
+                                }
                                 break;
                             },
                             ServerCommand::CountConnections(sender) => {
                                 if let Err(_) = sender.send(connection_sessions.len()) {
                                     tracing::error!("Receiver closed before getting response");
                                 }
+                            },
+                            ServerCommand::GetSessionsRtt(sender) => {
+                                let sessions_rtt = connection_sessions.iter()
+                                    .map(|session| (session.get_id(), session.get_rtt()))
+                                    .collect();
+                                if sender.send(sessions_rtt).is_err() {
+                                    tracing::error!("Receiver closed before getting response");
+                                }
+                            },
+                            ServerCommand::SendNotice(message) => {
+                                game.publish_event(GameServerEvent::ServerNotice { message });
                             }
                         }
                     }
@@ -124,11 +251,29 @@ impl GameServer {
         Ok(Self {
             task_handle,
             local_address,
+            websocket_address,
             commands_tx,
             connection_notifications,
         })
     }
 
+    async fn upgrade_websocket(
+        stream: TcpStream,
+        address: SocketAddr,
+        upgraded_tx: mpsc::Sender<((BoxedFrameReader, BoxedFrameWriter), SocketAddr)>,
+        limits: SessionLimits
+    ) {
+        let config = transport::websocket_config(limits.max_frame_size);
+        match tokio::time::timeout(limits.idle_timeout, tokio_tungstenite::accept_async_with_config(stream, Some(config))).await {
+            Ok(Ok(websocket)) => {
+                // Error only means server is shutting down
+                let _ = upgraded_tx.send((transport::split_websocket(websocket), address)).await;
+            },
+            Ok(Err(e)) => tracing::warn!("WebSocket upgrade of {address} failed, reason: '{e}'"),
+            Err(_) => tracing::warn!("WebSocket upgrade of {address} timed out"),
+        }
+    }
+
     pub async fn shutdown_gracefully(self) -> std::io::Result<()> {
         tracing::info!("Gracefully shutting down...");
         if self
@@ -157,6 +302,10 @@ impl GameServer {
         &self.local_address
     }
 
+    pub fn get_websocket_address(&self) -> &SocketAddr {
+        &self.websocket_address
+    }
+
     pub async fn get_connections_count(&self) -> GameServerResult<usize> {
         let (commands_tx, mut commands_rx) = oneshot::channel::<usize>();
         let cmd = ServerCommand::CountConnections(commands_tx);
@@ -167,6 +316,25 @@ impl GameServer {
         Ok(commands_rx.await?)
     }
 
+    /// Round trip time of every connected session, `None` for sessions which did not answer any ping yet
+    pub async fn get_sessions_rtt(&self) -> GameServerResult<HashMap<ConnectionSessionId, Option<Duration>>> {
+        let (sessions_rtt_tx, sessions_rtt_rx) = oneshot::channel();
+        self.commands_tx
+            .send(ServerCommand::GetSessionsRtt(sessions_rtt_tx))
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        Ok(sessions_rtt_rx.await?)
+    }
+
+    /// Pushed as event to all authenticated sessions
+    pub async fn send_notice(&self, message: String) -> GameServerResult<()> {
+        self.commands_tx
+            .send(ServerCommand::SendNotice(message))
+            .await
+            .map_err(|e| std::io::Error::other(e.to_string()))?;
+        Ok(())
+    }
+
     pub async fn await_any_connection(&self) -> GameServerResult<usize> {
         // FIXME: Not really useful can miss event
         loop {
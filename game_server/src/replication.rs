@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::game::entity::EntityId;
+use crate::requests::Direction;
+
+/// Number of world tick
+pub type Tick = u64;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MovementReplica {
+    /// Tile where entity stops, `None` when standing
+    pub target: Option<(f32, f32)>,
+    pub facing: Direction,
+    pub speed: f32,
+}
+
+/// Components of an entity. In snapshot `None` means entity lacks the component,
+/// in delta it means the component did not change.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EntityState {
+    pub entity_id: EntityId,
+    pub name: Option<String>,
+    pub position: Option<(f32, f32)>,
+    pub movement: Option<MovementReplica>,
+}
+
+impl EntityState {
+    fn merge(&mut self, change: &EntityState) {
+        if let Some(name) = &change.name {
+            self.name = Some(name.clone());
+        }
+        if let Some(position) = change.position {
+            self.position = Some(position);
+        }
+        if let Some(movement) = &change.movement {
+            self.movement = Some(movement.clone());
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorldSnapshot {
+    pub tick: Tick,
+    pub entities: Vec<EntityState>,
+}
+
+/// Produced only for ticks with changes visible by the viewer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorldDelta {
+    pub tick: Tick,
+    /// Tick of the state this delta applies to, previous delta or snapshot
+    pub base_tick: Tick,
+    /// Entities that entered the view are here with all components
+    pub changed: Vec<EntityState>,
+    /// Entities that came into view range or got spawned within it
+    pub entered: Vec<EntityId>,
+    /// Entities that went out of view range or got despawned, no longer replicated
+    pub left: Vec<EntityId>,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum ReplicationError {
+    #[error("Missed deltas between tick {expected_base_tick} and {base_tick}")]
+    Gap {
+        expected_base_tick: Tick,
+        base_tick: Tick,
+    },
+
+    #[error("Awaiting snapshot")]
+    AwaitingSnapshot,
+}
+
+pub type ReplicationResult<T> = Result<T, ReplicationError>;
+
+/// Client side copy of the world, built from snapshot and kept up to date with deltas
+#[derive(Debug, Default)]
+pub struct ReplicatedWorld {
+    tick: Option<Tick>,
+    entities: HashMap<EntityId, EntityState>,
+}
+
+impl ReplicatedWorld {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn apply_snapshot(&mut self, snapshot: WorldSnapshot) {
+        self.tick = Some(snapshot.tick);
+        self.entities = snapshot.entities
+            .into_iter()
+            .map(|entity| (entity.entity_id, entity))
+            .collect();
+    }
+
+    /// Deltas older than current state are ignored. On `Gap` state is dropped,
+    /// further deltas fail with `AwaitingSnapshot` until resync.
+    pub fn apply_delta(&mut self, delta: &WorldDelta) -> ReplicationResult<()> {
+        let tick = self.tick.ok_or(ReplicationError::AwaitingSnapshot)?;
+        if delta.tick <= tick {
+            return Ok(());
+        }
+        if delta.base_tick != tick {
+            self.tick = None;
+            return Err(ReplicationError::Gap { expected_base_tick: tick, base_tick: delta.base_tick });
+        }
+
+        for change in &delta.changed {
+            self.entities
+                .entry(change.entity_id)
+                .or_insert_with(|| EntityState { entity_id: change.entity_id, ..Default::default() })
+                .merge(change);
+        }
+        for entity_id in &delta.left {
+            self.entities.remove(entity_id);
+        }
+
+        self.tick = Some(delta.tick);
+        Ok(())
+    }
+
+    pub fn get_tick(&self) -> Option<Tick> {
+        self.tick
+    }
+
+    pub fn get_entity(&self, entity_id: &EntityId) -> Option<&EntityState> {
+        self.entities.get(entity_id)
+    }
+
+    pub fn get_entities_count(&self) -> usize {
+        self.entities.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_entity_state(entity_id: EntityId) -> EntityState {
+        EntityState {
+            entity_id,
+            name: Some("Janusz".to_string()),
+            position: Some((0.0, 0.0)),
+            movement: Some(MovementReplica { target: None, facing: Direction::Down, speed: 1.0 }),
+        }
+    }
+
+    #[test]
+    fn test_applying_deltas() {
+        let mut world = ReplicatedWorld::new();
+        world.apply_snapshot(WorldSnapshot { tick: 5, entities: vec![test_entity_state(1)] });
+
+        world.apply_delta(&WorldDelta {
+            tick: 7,
+            base_tick: 5,
+            changed: vec![
+                EntityState { entity_id: 1, position: Some((0.5, 0.0)), ..Default::default() },
+                test_entity_state(2),
+            ],
+            entered: vec![2],
+            left: vec![],
+        }).unwrap();
+
+        let entity = world.get_entity(&1).unwrap();
+        assert_eq!(entity.position, Some((0.5, 0.0)));
+        assert_eq!(entity.name.as_deref(), Some("Janusz"));
+        assert_eq!(world.get_entities_count(), 2);
+
+        world.apply_delta(&WorldDelta { tick: 8, base_tick: 7, changed: vec![], entered: vec![], left: vec![1] }).unwrap();
+        assert!(world.get_entity(&1).is_none());
+        assert_eq!(world.get_tick(), Some(8));
+    }
+
+    #[test]
+    fn test_ignoring_stale_delta() {
+        let mut world = ReplicatedWorld::new();
+        world.apply_snapshot(WorldSnapshot { tick: 5, entities: vec![test_entity_state(1)] });
+
+        world.apply_delta(&WorldDelta { tick: 5, base_tick: 3, changed: vec![], entered: vec![], left: vec![1] }).unwrap();
+        assert!(world.get_entity(&1).is_some());
+        assert_eq!(world.get_tick(), Some(5));
+    }
+
+    #[test]
+    fn test_detecting_gap() {
+        let mut world = ReplicatedWorld::new();
+        let delta = WorldDelta { tick: 9, base_tick: 7, changed: vec![], entered: vec![], left: vec![] };
+        assert_eq!(world.apply_delta(&delta), Err(ReplicationError::AwaitingSnapshot));
+
+        world.apply_snapshot(WorldSnapshot { tick: 5, entities: vec![] });
+        assert_eq!(world.apply_delta(&delta), Err(ReplicationError::Gap { expected_base_tick: 5, base_tick: 7 }));
+        assert_eq!(world.apply_delta(&delta), Err(ReplicationError::AwaitingSnapshot));
+
+        world.apply_snapshot(WorldSnapshot { tick: 7, entities: vec![] });
+        world.apply_delta(&delta).unwrap();
+    }
+}
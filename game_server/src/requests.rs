@@ -1,11 +1,73 @@
 use serde::{Deserialize, Serialize};
+use accounts_manager::JwtToken;
 use database_adapter::character::CharacterId;
+use crate::codec::{Codec, ProtocolVersion};
+use crate::heartbeat::Timestamp;
+
+/// Chosen by client, unique among its requests awaiting response
+pub type RequestId = u64;
+
+/// Every frame sent by client
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GameServerRequestMessage {
+    pub request_id: RequestId,
+    pub request: GameServerRequest,
+}
+
+/// Leading part of `GameServerRequestMessage`, lets malformed request still get response
+#[derive(Debug, Deserialize)]
+pub struct GameServerRequestHeader {
+    pub request_id: RequestId,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum GameServerRequest {
+    /// Has to be the first request, encoded with `HANDSHAKE_CODEC`.
+    /// Following frames of both sides are encoded with chosen codec.
+    Hello {
+        protocol_version: ProtocolVersion,
+        codec: Codec,
+    },
     Status,
+    /// Answered with `GameServerResponse::Pong` carrying the same timestamp
+    Ping {
+        timestamp: Timestamp,
+    },
+    /// Answer to `GameServerMessage::Ping`, gets no response
+    Pong {
+        timestamp: Timestamp,
+    },
+    /// Has to be sent before any other request except `Status` and heartbeats
+    Authenticate {
+        token: JwtToken,
+    },
     EntitiesCount,
     AttachToCharacter {
         character_id: CharacterId,
     },
-}
\ No newline at end of file
+    /// Moves attached character to the tile containing given point, along path around obstacles
+    MoveTo {
+        x: f32,
+        y: f32,
+    },
+    /// Moves attached character to the neighbouring tile, queued after the current step if moving
+    Step {
+        dir: Direction,
+    },
+    /// Position of attached character
+    GetPosition,
+    /// Sent to all authenticated sessions as `GameServerEvent::Chat`
+    Chat {
+        message: String,
+    },
+    /// Asks for new `GameServerEvent::WorldSnapshot`, after gap in deltas got detected
+    ResyncWorld,
+}
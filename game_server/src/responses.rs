@@ -1,5 +1,8 @@
 use serde::{Deserialize, Serialize};
-use crate::game::entity::EntityId;
+use crate::codec::ProtocolVersion;
+use crate::events::GameServerEvent;
+use crate::heartbeat::Timestamp;
+use crate::requests::RequestId;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum ResponseResult {
@@ -11,13 +14,68 @@ pub enum ResponseResult {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum GameServerResponse {
+    /// Session gets closed right after failed hello
+    Hello {
+        result: ResponseResult,
+        protocol_version: ProtocolVersion,
+    },
+    /// Request could not be decoded, session gets closed after too many of them
+    MalformedRequest {
+        message: String,
+    },
     Status {
         info: String,
     },
+    Pong {
+        timestamp: Timestamp,
+    },
+    Authenticate {
+        result: ResponseResult,
+    },
+    /// Request requires authenticated session
+    NotAuthenticated,
     EntitiesCount {
         count: usize
     },
     AttachToCharacter {
         result: ResponseResult,
     },
-}
\ No newline at end of file
+    MoveTo {
+        result: ResponseResult,
+    },
+    Step {
+        result: ResponseResult,
+    },
+    GetPosition {
+        result: ResponseResult,
+        x: f32,
+        y: f32,
+    },
+    Chat {
+        result: ResponseResult,
+    },
+    ResyncWorld {
+        result: ResponseResult,
+    },
+}
+
+/// Every frame sent by server
+#[derive(Debug, Serialize, Deserialize)]
+pub enum GameServerMessage {
+    Response {
+        request_id: RequestId,
+        response: GameServerResponse,
+    },
+    Event {
+        event: GameServerEvent,
+    },
+    /// Sent periodically, client answers with `GameServerRequest::Pong`.
+    /// Session staying silent for too long gets closed.
+    Ping {
+        timestamp: Timestamp,
+    },
+    /// Frame not even recognized as request, so there is nothing to respond to
+    ProtocolViolation {
+        message: String,
+    },
+}
@@ -1,91 +1,527 @@
+use std::collections::HashSet;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{broadcast, mpsc};
 use tokio::task::JoinHandle;
+use accounts_manager::JwtToken;
 use database_adapter::character::CharacterId;
-use crate::{GameServerError, GameServerResult};
-use crate::game::Game;
-use crate::requests::GameServerRequest;
-use crate::responses::{GameServerResponse, ResponseResult};
+use crate::GameServerResult;
+use crate::codec::{Codec, HandshakeError, HANDSHAKE_CODEC, PROTOCOL_VERSION};
+use crate::events::GameServerEvent;
+use crate::framing::{FrameError, DEFAULT_MAX_FRAME_SIZE};
+use crate::heartbeat::{HeartbeatClock, RttEstimator, Timestamp};
+use crate::auth::{AccessTokenVerifier, AuthError};
+use crate::game::{Game, GameError};
+use crate::game::entity::EntityId;
+use crate::game::math::Vec2F;
+use crate::transport::{BoxedFrameReader, BoxedFrameWriter};
+use crate::requests::{Direction, GameServerRequest, GameServerRequestHeader, GameServerRequestMessage};
+use crate::responses::{GameServerMessage, GameServerResponse, ResponseResult};
 
 #[derive(Debug)]
 pub struct ConnectionSession {
     connection_id: ConnectionSessionId,
     address: SocketAddr,
     session_task: JoinHandle<()>,
+    rtt: Arc<Mutex<RttEstimator>>,
 }
 
 pub type ConnectionSessionId = u64;
 
+pub const DEFAULT_MAX_PROTOCOL_VIOLATIONS: usize = 8;
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(2);
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a single peer is allowed to send and how long it may stay silent
+#[derive(Debug, Clone, Copy)]
+pub struct SessionLimits {
+    /// Larger frame closes the session, its payload never gets read
+    pub max_frame_size: usize,
+    /// Undecodable requests tolerated before the session gets closed
+    pub max_protocol_violations: usize,
+    /// How often server pings the client
+    pub heartbeat_interval: Duration,
+    /// Session receiving nothing for that long gets closed, should span a few heartbeats
+    pub idle_timeout: Duration,
+}
+
+impl Default for SessionLimits {
+    fn default() -> Self {
+        Self {
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            max_protocol_violations: DEFAULT_MAX_PROTOCOL_VIOLATIONS,
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+        }
+    }
+}
+
+/// What attached entity sees, kept by replication task and used to filter entity events
+#[derive(Debug, Default)]
+struct SessionView {
+    position: Option<(f32, f32)>,
+    visible: HashSet<EntityId>,
+}
+
+impl SessionView {
+    fn is_within_range(&self, x: f32, y: f32, range: f32) -> bool {
+        self.position
+            .is_some_and(|(view_x, view_y)| (x - view_x).abs() <= range && (y - view_y).abs() <= range)
+    }
+
+    fn is_event_visible(&self, event: &GameServerEvent, range: f32) -> bool {
+        match event {
+            GameServerEvent::EntitySpawned { entity_id, x, y, .. } => {
+                self.visible.contains(entity_id) || self.is_within_range(*x, *y, range)
+            },
+            GameServerEvent::EntityDespawned { entity_id }
+            | GameServerEvent::EntityMoving { entity_id, .. } => self.visible.contains(entity_id),
+            _ => true,
+        }
+    }
+}
+
+/// Account session got authenticated as, and token which has to stay valid for session to go on
+struct SessionAuthentication {
+    username: String,
+    expires_at: u64,
+    token_id: String,
+}
+
+struct SessionState {
+    outgoing_tx: mpsc::Sender<GameServerMessage>,
+    /// Set once by successful authentication, session stays bound to this account
+    authentication: Option<SessionAuthentication>,
+    /// Set when session has to be closed after the current response
+    closing: bool,
+    /// Events are forwarded only to authenticated sessions
+    events_task: Option<JoinHandle<()>>,
+    /// Running while session is attached to character
+    replication_task: Option<JoinHandle<()>>,
+    heartbeat_task: Option<JoinHandle<()>>,
+    view: Arc<Mutex<SessionView>>,
+    clock: HeartbeatClock,
+    rtt: Arc<Mutex<RttEstimator>>,
+}
+
+impl SessionState {
+    fn new(outgoing_tx: mpsc::Sender<GameServerMessage>, rtt: Arc<Mutex<RttEstimator>>) -> Self {
+        Self {
+            outgoing_tx,
+            authentication: None,
+            closing: false,
+            events_task: None,
+            replication_task: None,
+            heartbeat_task: None,
+            view: Arc::new(Mutex::new(SessionView::default())),
+            clock: HeartbeatClock::new(),
+            rtt,
+        }
+    }
+
+    fn start_heartbeat(&mut self, interval: Duration) {
+        self.heartbeat_task = Some(tokio::spawn(
+            ConnectionSession::heartbeat_task(self.outgoing_tx.clone(), self.clock, interval)
+        ));
+    }
+
+    fn on_pong(&self, connection_id: ConnectionSessionId, timestamp: Timestamp) {
+        match self.clock.elapsed_since(timestamp) {
+            Some(rtt) => self.rtt.lock().unwrap().update(rtt),
+            None => tracing::warn!("Session {connection_id} answered ping which was never sent"),
+        }
+    }
+
+    fn start_events(&mut self, game: &Arc<Game>) {
+        if self.events_task.is_none() {
+            self.events_task = Some(tokio::spawn(
+                ConnectionSession::events_task(
+                    game.subscribe_events(),
+                    self.outgoing_tx.clone(),
+                    self.view.clone(),
+                    game.world_manager.get_view_range()
+                )
+            ));
+        }
+    }
+
+    fn start_replication(&mut self, game: &Arc<Game>, viewer: EntityId) {
+        if let Some(replication_task) = self.replication_task.take() {
+            replication_task.abort();
+        }
+        self.replication_task = Some(tokio::spawn(
+            ConnectionSession::replication_task(game.clone(), viewer, self.outgoing_tx.clone(), self.view.clone())
+        ));
+    }
+
+    fn stop_tasks(&mut self) {
+        for task in [self.events_task.take(), self.replication_task.take(), self.heartbeat_task.take()].into_iter().flatten() {
+            task.abort();
+        }
+    }
+}
+
 impl ConnectionSession {
+    const OUTGOING_QUEUE_SIZE: usize = 64;
+
+    /// Transport is already split and past its own handshake, if it has any
     pub async fn new(
         connection_id: ConnectionSessionId,
-        mut stream: TcpStream,
+        (mut reader, mut writer): (BoxedFrameReader, BoxedFrameWriter),
         address: SocketAddr,
         disconnect_tx: mpsc::Sender<ConnectionSessionId>,
-        game: Arc<Game>
+        game: Arc<Game>,
+        token_verifier: Arc<AccessTokenVerifier>,
+        limits: SessionLimits
     ) -> Self {
         tracing::info!("Creating connection session for {:?}", address);
+        let rtt = Arc::new(Mutex::new(RttEstimator::default()));
+        let session_rtt = rtt.clone();
+
         let session_task = tokio::spawn(async move {
             tracing::info!("Entered connection session task");
-            loop {
-                // tokio::time::sleep(Duration::from_millis(100)).await;
-                match stream.read_u32_le().await {
-                    Ok(request_length) => {
-                        let mut request_buffer = vec![0u8; request_length as usize];
-                        stream.read_exact(&mut request_buffer).await.unwrap();
-
-                        match Self::process_request_into_response(
-                            connection_id,
-                            request_buffer,
-                            game.clone()
-                        ).await {
-                            Ok(response_bytes) => {
-                                let response_bytes_count = response_bytes.len() as u32;
-                                stream.write_u32_le(response_bytes_count).await.unwrap();
-                                stream.write_all(response_bytes.as_slice()).await.unwrap();
-                            },
-                            Err(e) => {
-                                tracing::error!("Could not response, reason: '{e}'");
-                            }
-                        }
+            let codec = match Self::handshake(&mut reader, &mut writer, limits).await {
+                Ok(codec) => codec,
+                Err(e) => {
+                    tracing::warn!("Handshake with {address} failed, reason: '{e}'");
+                    if disconnect_tx.send(connection_id).await.is_err() {
+                        tracing::warn!("Could not inform about session end. Noone cares :(");
+                    }
+                    return;
+                }
+            };
 
+            // Responses and events share single writer, so frames never interleave
+            let (outgoing_tx, outgoing_rx) = mpsc::channel::<GameServerMessage>(Self::OUTGOING_QUEUE_SIZE);
+            let writer_task = tokio::spawn(Self::writer_task(connection_id, writer, outgoing_rx, codec, limits.max_frame_size));
+            let mut session_state = SessionState::new(outgoing_tx, session_rtt);
+            session_state.start_heartbeat(limits.heartbeat_interval);
+            let mut protocol_violations = 0;
+            loop {
+                // Frame cut by timeout is lost, but session gets closed then anyway
+                let request_buffer = match tokio::time::timeout(limits.idle_timeout, reader.read_frame(limits.max_frame_size)).await {
+                    Ok(Ok(request_buffer)) => request_buffer,
+                    Ok(Err(e @ FrameError::FrameTooLarge { .. })) => {
+                        // Payload left unread, so stream can't be followed anymore
+                        tracing::warn!("Closing session {connection_id}, reason: '{e}'");
+                        break;
+                    },
+                    Ok(Err(e)) => {
+                        tracing::info!("Session {connection_id} closed, reason: '{e}'");
+                        break;
                     },
                     Err(_) => {
-                        if disconnect_tx.send(connection_id).await.is_err() {
-                            tracing::warn!("Could not inform about session end. Noone cares :(");
-                        }
+                        tracing::warn!("Closing session {connection_id}, nothing received for {:?}", limits.idle_timeout);
                         break;
+                    },
+                };
+
+                let message = match Self::process_request_into_response(
+                    connection_id,
+                    &mut session_state,
+                    &request_buffer,
+                    codec,
+                    game.clone(),
+                    &token_verifier
+                ).await {
+                    Ok(Some(message)) => message,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        protocol_violations += 1;
+                        tracing::warn!("Session {connection_id} sent malformed request ({protocol_violations}/{}), reason: '{e}'",
+                            limits.max_protocol_violations);
+                        Self::malformed_request_message(codec, &request_buffer, &e)
                     }
+                };
+
+                if session_state.outgoing_tx.send(message).await.is_err() {
+                    tracing::warn!("Writer of session {connection_id} closed, dropping response");
+                }
+                if protocol_violations >= limits.max_protocol_violations {
+                    tracing::warn!("Closing session {connection_id}, too many protocol violations");
+                    break;
+                }
+                if session_state.closing {
+                    break;
                 }
             }
+
+            session_state.stop_tasks();
+            game.on_session_ended(connection_id).await;
+            if disconnect_tx.send(connection_id).await.is_err() {
+                tracing::warn!("Could not inform about session end. Noone cares :(");
+            }
+            // Dropping last sender lets writer flush queued responses and finish
+            drop(session_state);
+            let _ = writer_task.await;
         });
 
-        Self { connection_id, address, session_task }
+        Self { connection_id, address, session_task, rtt }
     }
 
-    async fn process_request_into_response(
+    /// First request has to be `Hello`, it decides codec of the session.
+    /// Client speaking other protocol version gets error response before session closes.
+    async fn handshake(reader: &mut BoxedFrameReader, writer: &mut BoxedFrameWriter, limits: SessionLimits) -> GameServerResult<Codec> {
+        let request_buffer = tokio::time::timeout(limits.idle_timeout, reader.read_frame(limits.max_frame_size))
+            .await
+            .map_err(|_| HandshakeError::HelloTimeout)??;
+        let GameServerRequestMessage { request_id, request } = match HANDSHAKE_CODEC.decode(&request_buffer) {
+            Ok(request_message) => request_message,
+            Err(e) => {
+                let message = Self::malformed_request_message(HANDSHAKE_CODEC, &request_buffer, &e);
+                // Session gets closed anyway, reply is best effort
+                let message_bytes = HANDSHAKE_CODEC.encode(&message)?;
+                let _ = writer.write_frame(&message_bytes, limits.max_frame_size).await;
+                return Err(e.into());
+            }
+        };
+
+        let codec = match request {
+            GameServerRequest::Hello { protocol_version, codec } if protocol_version == PROTOCOL_VERSION => Ok(codec),
+            GameServerRequest::Hello { protocol_version, .. } => Err(HandshakeError::UnsupportedProtocolVersion {
+                client_version: protocol_version,
+                server_version: PROTOCOL_VERSION,
+            }),
+            _ => Err(HandshakeError::HelloExpected),
+        };
+
+        let result = match &codec {
+            Ok(_) => ResponseResult::Success,
+            Err(e) => ResponseResult::Error { message: e.to_string() },
+        };
+        let response = GameServerResponse::Hello { result, protocol_version: PROTOCOL_VERSION };
+        let message_bytes = HANDSHAKE_CODEC.encode(&GameServerMessage::Response { request_id, response })?;
+        writer.write_frame(&message_bytes, limits.max_frame_size).await?;
+
+        Ok(codec?)
+    }
+
+    /// Response to request which could not be decoded. Without even request id recognized
+    /// client can't match it with any request, so it gets protocol violation instead.
+    fn malformed_request_message(codec: Codec, request_buffer: &[u8], error: &impl std::fmt::Display) -> GameServerMessage {
+        let message = format!("Malformed request: '{error}'");
+        match codec.decode::<GameServerRequestHeader>(request_buffer) {
+            Ok(GameServerRequestHeader { request_id }) => GameServerMessage::Response {
+                request_id,
+                response: GameServerResponse::MalformedRequest { message },
+            },
+            Err(_) => GameServerMessage::ProtocolViolation { message },
+        }
+    }
+
+    async fn writer_task(
         connection_id: ConnectionSessionId,
-        response_buffer: Vec<u8>,
-        game: Arc<Game>
-    ) -> GameServerResult<Vec<u8>> {
-        let request: GameServerRequest = serde_json::from_slice(&response_buffer)
-            .inspect_err(|e| tracing::error!("Error deserializing request: '{e}'"))?;
-
-        let response = match request {
-            GameServerRequest::Status => Self::handle_request_status(),
-            GameServerRequest::EntitiesCount => Self::handle_request_entities_count(game).await,
-            GameServerRequest::AttachToCharacter {character_id} => Self::handle_request_attach_to_character(game, connection_id, character_id).await,
+        mut writer: BoxedFrameWriter,
+        mut outgoing_rx: mpsc::Receiver<GameServerMessage>,
+        codec: Codec,
+        max_frame_size: usize
+    ) {
+        while let Some(message) = outgoing_rx.recv().await {
+            let message_bytes = match codec.encode(&message) {
+                Ok(message_bytes) => message_bytes,
+                Err(e) => {
+                    tracing::error!("Error serializing message {message:?}, reason: '{e}'");
+                    continue;
+                }
+            };
+
+            match writer.write_frame(&message_bytes, max_frame_size).await {
+                Ok(()) => {},
+                Err(e @ FrameError::FrameTooLarge { .. }) => {
+                    // Nothing got written, stream is still fine
+                    tracing::error!("Dropping message for session {connection_id}, reason: '{e}'");
+                },
+                Err(e) => {
+                    tracing::warn!("Could not write to session {connection_id}, reason: '{e}'");
+                    break;
+                }
+            }
+        }
+        // Peer may have closed it already
+        let _ = writer.close().await;
+    }
+
+    /// Pings are sent no matter whether client answers, silence gets detected by the session loop
+    async fn heartbeat_task(outgoing_tx: mpsc::Sender<GameServerMessage>, clock: HeartbeatClock, interval: Duration) {
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        loop {
+            interval.tick().await;
+            if outgoing_tx.send(GameServerMessage::Ping { timestamp: clock.now() }).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    async fn replication_task(
+        game: Arc<Game>,
+        viewer: EntityId,
+        outgoing_tx: mpsc::Sender<GameServerMessage>,
+        view: Arc<Mutex<SessionView>>
+    ) {
+        // Subscribed before taking snapshot, so no delta after it gets missed
+        let mut deltas_rx = game.world_manager.subscribe_deltas();
+        let snapshot = match game.world_manager.get_snapshot(viewer).await {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                tracing::error!("Could not get world snapshot, reason: '{e}'");
+                return;
+            }
         };
 
-        let result_bytes = serde_json::to_vec(&response).inspect_err(|err| {
-            tracing::error!("Error serializing request {request:?}. {:?}", err)
-        }).inspect_err(|e| tracing::error!("Error serializing response: '{e}'"))?;
+        {
+            let mut view = view.lock().unwrap();
+            view.visible = snapshot.entities.iter().map(|entity| entity.entity_id).collect();
+            view.position = snapshot.entities.iter()
+                .find(|entity| entity.entity_id == viewer)
+                .and_then(|entity| entity.position);
+        }
 
-        Ok(result_bytes)
+        let snapshot_tick = snapshot.tick;
+        if outgoing_tx.send(GameServerMessage::Event { event: GameServerEvent::WorldSnapshot { snapshot } }).await.is_err() {
+            return;
+        }
+
+        loop {
+            let delta = match deltas_rx.recv().await {
+                Ok(deltas) => match deltas.get(&viewer) {
+                    Some(delta) if delta.tick > snapshot_tick => delta.clone(),
+                    _ => continue,
+                },
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    // Client notices gap by base tick and requests resync
+                    tracing::warn!("Session lagging, skipped {skipped} deltas");
+                    continue;
+                },
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+
+            {
+                let mut view = view.lock().unwrap();
+                view.visible.extend(delta.entered.iter().copied());
+                for entity_id in &delta.left {
+                    view.visible.remove(entity_id);
+                }
+                if let Some(position) = delta.changed.iter()
+                    .find(|entity| entity.entity_id == viewer)
+                    .and_then(|entity| entity.position) {
+                    view.position = Some(position);
+                }
+            }
+
+            let view_events: Vec<GameServerEvent> = delta.entered.iter()
+                .map(|entity_id| GameServerEvent::EntityEnteredView { entity_id: *entity_id })
+                .chain(delta.left.iter().map(|entity_id| GameServerEvent::EntityLeftView { entity_id: *entity_id }))
+                .collect();
+
+            let events = std::iter::once(GameServerEvent::WorldDelta { delta }).chain(view_events);
+            for event in events {
+                if outgoing_tx.send(GameServerMessage::Event { event }).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn events_task(
+        mut events_rx: broadcast::Receiver<GameServerEvent>,
+        outgoing_tx: mpsc::Sender<GameServerMessage>,
+        view: Arc<Mutex<SessionView>>,
+        view_range: f32
+    ) {
+        loop {
+            match events_rx.recv().await {
+                Ok(event) => {
+                    if !view.lock().unwrap().is_event_visible(&event, view_range) {
+                        continue;
+                    }
+                    if outgoing_tx.send(GameServerMessage::Event { event }).await.is_err() {
+                        break;
+                    }
+                },
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Session lagging, skipped {skipped} events");
+                },
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }
+
+    async fn process_request_into_response(
+        connection_id: ConnectionSessionId,
+        session_state: &mut SessionState,
+        request_buffer: &[u8],
+        codec: Codec,
+        game: Arc<Game>,
+        token_verifier: &AccessTokenVerifier
+    ) -> GameServerResult<Option<GameServerMessage>> {
+        let GameServerRequestMessage { request_id, request } = codec.decode(request_buffer)?;
+
+        let requires_authentication = !matches!(request,
+            GameServerRequest::Hello { .. }
+            | GameServerRequest::Status
+            | GameServerRequest::Ping { .. }
+            | GameServerRequest::Pong { .. }
+            | GameServerRequest::Authenticate { .. }
+        );
+        if let Some(authentication) = session_state.authentication.as_ref().filter(|_| requires_authentication) {
+            if let Err(e) = token_verifier.check_still_valid(authentication.expires_at, &authentication.token_id).await {
+                tracing::warn!("Closing session {connection_id}, reason: '{e}'");
+                session_state.closing = true;
+                return Ok(Some(GameServerMessage::Response { request_id, response: GameServerResponse::NotAuthenticated }));
+            }
+        }
+
+        let username = session_state.authentication.as_ref().map(|authentication| authentication.username.clone());
+        let response = match (request, username.as_deref()) {
+            (GameServerRequest::Hello { .. }, _) => GameServerResponse::Hello {
+                result: ResponseResult::Error { message: HandshakeError::AlreadyHelloed.to_string() },
+                protocol_version: PROTOCOL_VERSION,
+            },
+            (GameServerRequest::Status, _) => Self::handle_request_status(),
+            (GameServerRequest::Ping { timestamp }, _) => GameServerResponse::Pong { timestamp },
+            (GameServerRequest::Pong { timestamp }, _) => {
+                session_state.on_pong(connection_id, timestamp);
+                return Ok(None);
+            },
+            (GameServerRequest::Authenticate { token }, None) => {
+                let (response, authentication) = Self::handle_request_authenticate(token_verifier, connection_id, token).await;
+                if let Some(authentication) = authentication {
+                    session_state.authentication = Some(authentication);
+                    session_state.start_events(&game);
+                }
+                response
+            },
+            (GameServerRequest::Authenticate { .. }, Some(_)) => GameServerResponse::Authenticate {
+                result: ResponseResult::Error { message: AuthError::AlreadyAuthenticated.to_string() },
+            },
+            (_, None) => GameServerResponse::NotAuthenticated,
+            (GameServerRequest::EntitiesCount, Some(_)) => Self::handle_request_entities_count(game).await,
+            (GameServerRequest::AttachToCharacter {character_id}, Some(username)) => {
+                let (response, entity_id) = Self::handle_request_attach_to_character(game.clone(), connection_id, username, character_id).await;
+                if let Some(entity_id) = entity_id {
+                    session_state.start_replication(&game, entity_id);
+                }
+                response
+            },
+            (GameServerRequest::MoveTo { x, y }, Some(_)) => Self::handle_request_move_to(game, connection_id, x, y).await,
+            (GameServerRequest::Step { dir }, Some(_)) => Self::handle_request_step(game, connection_id, dir).await,
+            (GameServerRequest::GetPosition, Some(_)) => Self::handle_request_get_position(game, connection_id).await,
+            (GameServerRequest::Chat { message }, Some(username)) => Self::handle_request_chat(game, username, message),
+            (GameServerRequest::ResyncWorld, Some(_)) => {
+                let result = match game.get_entity_id_of_session(connection_id).await {
+                    Some(entity_id) => {
+                        // Restarted replication begins with fresh snapshot
+                        session_state.start_replication(&game, entity_id);
+                        ResponseResult::Success
+                    },
+                    None => ResponseResult::Error { message: GameError::SessionNotAttachedToEntity.to_string() },
+                };
+                GameServerResponse::ResyncWorld { result }
+            },
+        };
+
+        Ok(Some(GameServerMessage::Response { request_id, response }))
     }
 
     fn handle_request_status() -> GameServerResponse {
@@ -94,6 +530,24 @@ impl ConnectionSession {
         }
     }
 
+    async fn handle_request_authenticate(
+        token_verifier: &AccessTokenVerifier,
+        connection_id: ConnectionSessionId,
+        token: JwtToken
+    ) -> (GameServerResponse, Option<SessionAuthentication>) {
+        match token_verifier.verify(&token).await {
+            Ok(claims) => {
+                tracing::info!("Session {connection_id} authenticated as '{}'", claims.iss);
+                let authentication = SessionAuthentication { username: claims.iss, expires_at: claims.exp, token_id: claims.jti };
+                (GameServerResponse::Authenticate { result: ResponseResult::Success }, Some(authentication))
+            },
+            Err(e) => {
+                tracing::warn!("Session {connection_id} failed to authenticate, reason: '{e}'");
+                (GameServerResponse::Authenticate { result: ResponseResult::Error { message: e.to_string() } }, None)
+            },
+        }
+    }
+
     async fn handle_request_entities_count(game: Arc<Game>) ->  GameServerResponse {
         GameServerResponse::EntitiesCount {
             count: game.world_manager.get_entities_count().await
@@ -103,15 +557,69 @@ impl ConnectionSession {
     async fn handle_request_attach_to_character(
         game: Arc<Game>,
         connection_id: ConnectionSessionId,
+        username: &str,
         character_id: CharacterId
+    ) -> (GameServerResponse, Option<EntityId>) {
+        match game.spawn_character_entity(connection_id, username, character_id).await {
+            Ok(entity_id) => (GameServerResponse::AttachToCharacter { result: ResponseResult::Success }, Some(entity_id)),
+            Err(e) => (GameServerResponse::AttachToCharacter { result: ResponseResult::Error { message: e.to_string() } }, None),
+        }
+    }
+
+    async fn handle_request_move_to(
+        game: Arc<Game>,
+        connection_id: ConnectionSessionId,
+        x: f32,
+        y: f32
     ) -> GameServerResponse {
-        let result = match game.spawn_character_entity(connection_id, character_id).await {
-            Ok(_) => ResponseResult::Success,
+        let result = match game.move_session_entity_to(connection_id, Vec2F::new(x, y)).await {
+            Ok(()) => ResponseResult::Success,
+            Err(e) => ResponseResult::Error { message: e.to_string() },
+        };
+
+        GameServerResponse::MoveTo { result }
+    }
+
+    async fn handle_request_step(
+        game: Arc<Game>,
+        connection_id: ConnectionSessionId,
+        direction: Direction
+    ) -> GameServerResponse {
+        let result = match game.step_session_entity(connection_id, direction).await {
+            Ok(()) => ResponseResult::Success,
+            Err(e) => ResponseResult::Error { message: e.to_string() },
+        };
+
+        GameServerResponse::Step { result }
+    }
+
+    async fn handle_request_get_position(
+        game: Arc<Game>,
+        connection_id: ConnectionSessionId
+    ) -> GameServerResponse {
+        match game.get_session_entity_position(connection_id).await {
+            Ok(position) => GameServerResponse::GetPosition { result: ResponseResult::Success, x: position.x, y: position.y },
+            Err(e) => GameServerResponse::GetPosition { result: ResponseResult::Error { message: e.to_string() }, x: 0.0, y: 0.0 },
+        }
+    }
+
+    fn handle_request_chat(
+        game: Arc<Game>,
+        username: &str,
+        message: String
+    ) -> GameServerResponse {
+        let result = match game.send_chat_message(username, message) {
+            Ok(()) => ResponseResult::Success,
             Err(e) => ResponseResult::Error { message: e.to_string() },
         };
 
-        GameServerResponse::AttachToCharacter { result }
+        GameServerResponse::Chat { result }
     }
 
     pub fn get_id(&self) -> ConnectionSessionId { self.connection_id }
+
+    /// Measured with server pings, `None` until client answers the first one
+    pub fn get_rtt(&self) -> Option<Duration> {
+        self.rtt.lock().unwrap().get_rtt()
+    }
 }
\ No newline at end of file
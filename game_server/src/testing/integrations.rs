@@ -1,16 +1,47 @@
 #[cfg(test)]
 mod tests {
-    use crate::requests::GameServerRequest;
-    use crate::responses::GameServerResponse;
+    use tokio::sync::broadcast;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tokio_tungstenite::tungstenite::Message;
+    use futures_util::{SinkExt, StreamExt};
+    use crate::codec::{Codec, HANDSHAKE_CODEC, PROTOCOL_VERSION};
+    use crate::events::GameServerEvent;
+    use crate::framing::{read_frame, write_frame, DEFAULT_MAX_FRAME_SIZE};
+    use crate::game::world::DEFAULT_VIEW_RANGE;
+    use crate::replication::{ReplicatedWorld, ReplicationError};
+    use crate::requests::{Direction, GameServerRequest, GameServerRequestMessage};
+    use crate::responses::{GameServerMessage, GameServerResponse, ResponseResult};
     use crate::testing::tests_trace_setup;
     use std::future::Future;
     use std::sync::Arc;
     use std::time::Duration;
     use database_adapter::test::DatabaseTestAdapter;
-    use crate::client::GameClient;
-    use crate::GameServer;
+    use accounts_manager::{services, AccountsManagerServer, JwtToken};
+    use database_adapter::DatabaseAdapter;
+    use crate::auth::AccessTokenVerifier;
+    use crate::client::{GameClient, GameClientError};
+    use crate::session::SessionLimits;
+    use crate::{GameServer, GameServerOptions};
 
     fn run_single_client_test<F, Fut>(test_fn: F)
+    where
+        F: FnOnce(GameClient) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        run_client_test(None, test_fn);
+    }
+
+    /// Client is authenticated as given test account before `test_fn` starts
+    fn run_authenticated_client_test<F, Fut>(username: &'static str, test_fn: F)
+    where
+        F: FnOnce(GameClient) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        run_client_test(Some(username), test_fn);
+    }
+
+    fn run_client_test<F, Fut>(username: Option<&'static str>, test_fn: F)
     where
         F: FnOnce(GameClient) -> Fut + Send + 'static,
         Fut: Future<Output = ()> + Send + 'static,
@@ -18,12 +49,19 @@ mod tests {
         let rt = tokio::runtime::Runtime::new().unwrap();
         rt.block_on(async move {
             let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
-            let server = GameServer::run(database_adapter).await.unwrap();
+            let token = match username {
+                Some(username) => Some(login(database_adapter.clone(), username).await),
+                None => None,
+            };
+            let (_accounts_server, server) = run_servers(database_adapter, GameServerOptions::default()).await;
             let server_address = *server.get_address();
             assert_eq!(server.get_connections_count().await.unwrap(), 0);
 
             let client_offloaded_task = tokio::task::spawn(async move {
                 let client = GameClient::connect(server_address).await.unwrap();
+                if let Some(token) = token {
+                    client.authenticate(token).await.unwrap();
+                }
 
                 tracing::info!("Starting client-server test space");
                 test_fn(client).await;
@@ -39,6 +77,22 @@ mod tests {
         });
     }
 
+    /// Game server verifies tokens with keys published by accounts server sharing the database
+    async fn run_servers(database_adapter: Arc<DatabaseTestAdapter>, options: GameServerOptions) -> (AccountsManagerServer, GameServer) {
+        let accounts_server = AccountsManagerServer::run(database_adapter.clone()).await.unwrap();
+        let options = GameServerOptions {
+            accounts_manager_address: accounts_server.get_address().to_string(),
+            ..options
+        };
+        (accounts_server, GameServer::run_with_options(database_adapter, options).await.unwrap())
+    }
+
+    async fn login(database_adapter: Arc<dyn DatabaseAdapter>, username: &str) -> JwtToken {
+        services::login_to_account(username.to_string(), "1234".to_string(), database_adapter)
+            .await.unwrap()
+            .access_token
+    }
+
     #[test]
     fn test_client_server_connection() {
         tests_trace_setup();
@@ -71,7 +125,7 @@ mod tests {
     fn test_client_getting_entities_count() {
         tests_trace_setup();
 
-        run_single_client_test(|client| async move {
+        run_authenticated_client_test("Account1", |client| async move {
             let span = tracing::debug_span!("test_client_getting_entities_count");
             let _guard = span.enter();
 
@@ -87,7 +141,7 @@ mod tests {
     fn test_client_attaching_to_character() {
         tests_trace_setup();
 
-        run_single_client_test(|client| async move {
+        run_authenticated_client_test("Account1", |client| async move {
             let span = tracing::debug_span!("test_client_getting_entities_count");
             let _guard = span.enter();
 
@@ -100,4 +154,864 @@ mod tests {
             assert_eq!(entities_count, 1);
         });
     }
+
+    #[test]
+    fn test_unauthenticated_client_gets_only_status() {
+        tests_trace_setup();
+
+        run_single_client_test(|client| async move {
+            client.get_status().await.unwrap();
+            assert!(matches!(client.get_entities_count().await, Err(GameClientError::NotAuthenticated)));
+            assert!(matches!(client.attach_to_character(1).await, Err(GameClientError::NotAuthenticated)));
+        });
+    }
+
+    #[test]
+    fn test_authenticating_with_bad_token_should_fail() {
+        tests_trace_setup();
+
+        run_single_client_test(|client| async move {
+            assert!(client.authenticate("bad token".to_string()).await.is_err());
+            assert!(matches!(client.attach_to_character(1).await, Err(GameClientError::NotAuthenticated)));
+        });
+    }
+
+    #[test]
+    fn test_authenticating_twice_should_fail() {
+        tests_trace_setup();
+
+        run_authenticated_client_test("Account1", |client| async move {
+            assert!(client.authenticate("bad token".to_string()).await.is_err());
+
+            // Session is still bound to the first account
+            client.attach_to_character(1).await.unwrap();
+        });
+    }
+
+    #[test]
+    fn test_client_attaching_to_character_of_other_account_should_fail() {
+        tests_trace_setup();
+
+        run_authenticated_client_test("Account1", |client| async move {
+            assert!(client.attach_to_character(2).await.is_err());
+            assert_eq!(client.get_entities_count().await.unwrap(), 0);
+        });
+    }
+
+    async fn await_position(client: &GameClient, expected: (f32, f32)) {
+        let result = tokio::time::timeout(Duration::from_secs(3), async {
+            while client.get_position().await.unwrap() != expected {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        }).await;
+        assert!(result.is_ok(), "Position {expected:?} not reached, got {:?}", client.get_position().await);
+    }
+
+    #[test]
+    fn test_client_moving_character() {
+        tests_trace_setup();
+
+        run_authenticated_client_test("Account1", |client| async move {
+            // Tuna at (0, 1)
+            client.attach_to_character(1).await.unwrap();
+            assert_eq!(client.get_position().await.unwrap(), (0.0, 1.0));
+
+            client.move_to(2.0, 1.0).await.unwrap();
+            await_position(&client, (2.0, 1.0)).await;
+
+            client.step(Direction::Up).await.unwrap();
+            await_position(&client, (2.0, 0.0)).await;
+        });
+    }
+
+    #[test]
+    fn test_client_changing_direction_while_moving() {
+        tests_trace_setup();
+
+        run_authenticated_client_test("Account1", |client| async move {
+            // Tuna at (0, 1), first step of the path gets finished
+            client.attach_to_character(1).await.unwrap();
+
+            client.move_to(5.0, 1.0).await.unwrap();
+            client.step(Direction::Down).await.unwrap();
+            await_position(&client, (1.0, 2.0)).await;
+        });
+    }
+
+    #[test]
+    fn test_client_moving_character_too_far_should_fail() {
+        tests_trace_setup();
+
+        run_authenticated_client_test("Account1", |client| async move {
+            client.attach_to_character(1).await.unwrap();
+            assert!(matches!(client.move_to(3e9, -3e9).await, Err(GameClientError::Other(_))));
+            assert!(matches!(client.move_to(f32::MAX, 1.0).await, Err(GameClientError::Other(_))));
+
+            // World keeps running
+            client.move_to(1.0, 1.0).await.unwrap();
+            await_position(&client, (1.0, 1.0)).await;
+        });
+    }
+
+    #[test]
+    fn test_client_moving_without_character_should_fail() {
+        tests_trace_setup();
+
+        run_authenticated_client_test("Account1", |client| async move {
+            assert!(matches!(client.move_to(1.0, 1.0).await, Err(GameClientError::Other(_))));
+            assert!(matches!(client.step(Direction::Left).await, Err(GameClientError::Other(_))));
+            assert!(matches!(client.get_position().await, Err(GameClientError::Other(_))));
+        });
+    }
+
+    #[test]
+    fn test_client_requests_in_flight_at_once() {
+        tests_trace_setup();
+
+        run_authenticated_client_test("Account1", |client| async move {
+            client.attach_to_character(1).await.unwrap();
+
+            let (status, entities_count, position) = tokio::join!(
+                client.get_status(),
+                client.get_entities_count(),
+                client.get_position(),
+            );
+            assert!(!status.unwrap().is_empty());
+            assert_eq!(entities_count.unwrap(), 1);
+            assert_eq!(position.unwrap(), (0.0, 1.0));
+        });
+    }
+
+    async fn connect_authenticated(server: &GameServer, database_adapter: Arc<dyn DatabaseAdapter>, username: &str) -> GameClient {
+        let client = GameClient::connect(*server.get_address()).await.unwrap();
+        client.authenticate(login(database_adapter, username).await).await.unwrap();
+        client
+    }
+
+    async fn recv_event(events_rx: &mut broadcast::Receiver<GameServerEvent>) -> GameServerEvent {
+        tokio::time::timeout(Duration::from_secs(1), events_rx.recv()).await
+            .expect("Event not received")
+            .unwrap()
+    }
+
+    /// Skips replication events
+    async fn recv_game_event(events_rx: &mut broadcast::Receiver<GameServerEvent>) -> GameServerEvent {
+        loop {
+            match recv_event(events_rx).await {
+                GameServerEvent::WorldSnapshot { .. }
+                | GameServerEvent::WorldDelta { .. }
+                | GameServerEvent::EntityEnteredView { .. }
+                | GameServerEvent::EntityLeftView { .. } => continue,
+                event => return event,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clients_receiving_events() {
+        tests_trace_setup();
+
+        let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let (_accounts_server, server) = run_servers(database_adapter.clone(), GameServerOptions::default()).await;
+
+        let client = connect_authenticated(&server, database_adapter.clone(), "Account1").await;
+        let other_client = connect_authenticated(&server, database_adapter.clone(), "Account2").await;
+        let unauthenticated_client = GameClient::connect(*server.get_address()).await.unwrap();
+        let mut events_rx = other_client.subscribe_events();
+        let mut unauthenticated_events_rx = unauthenticated_client.subscribe_events();
+
+        // Entity events reach only sessions with attached character, Raspberry at (-2, 0)
+        other_client.attach_to_character(2).await.unwrap();
+        let mut world = ReplicatedWorld::new();
+        replicate_until(&other_client, &mut events_rx, &mut world, |world| world.get_entity(&0).is_some()).await;
+
+        let entity_id = 1;
+        client.attach_to_character(1).await.unwrap();
+        assert_eq!(
+            recv_game_event(&mut events_rx).await,
+            GameServerEvent::EntitySpawned { entity_id, name: "Tuna".to_string(), x: 0.0, y: 1.0 }
+        );
+        replicate_until(&other_client, &mut events_rx, &mut world, |world| world.get_entity(&entity_id).is_some()).await;
+
+        client.step(Direction::Right).await.unwrap();
+        assert_eq!(
+            recv_game_event(&mut events_rx).await,
+            GameServerEvent::EntityMoving { entity_id, target_x: 1.0, target_y: 1.0 }
+        );
+
+        client.chat("Hello".to_string()).await.unwrap();
+        assert_eq!(
+            recv_game_event(&mut events_rx).await,
+            GameServerEvent::Chat { from: "Account1".to_string(), message: "Hello".to_string() }
+        );
+
+        server.send_notice("Restart soon".to_string()).await.unwrap();
+        assert_eq!(
+            recv_game_event(&mut events_rx).await,
+            GameServerEvent::ServerNotice { message: "Restart soon".to_string() }
+        );
+
+        // Requests still get responses between events
+        assert_eq!(other_client.get_entities_count().await.unwrap(), 2);
+        assert!(unauthenticated_events_rx.try_recv().is_err());
+
+        client.disconnect_await_finished().await;
+        other_client.disconnect_await_finished().await;
+        unauthenticated_client.disconnect_await_finished().await;
+        server.await_all_disconnect().await.unwrap();
+        server.shutdown_gracefully().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_clients_seeing_only_entities_in_view_range() {
+        tests_trace_setup();
+
+        let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        // Raspberry just out of view range from Tuna at (0, 1)
+        let mut raspberry_data = database_adapter.get_character_by_id(2).await.unwrap();
+        raspberry_data.position_x = DEFAULT_VIEW_RANGE + 1.0;
+        raspberry_data.position_y = 1.0;
+        database_adapter.update_character(raspberry_data).await.unwrap();
+        let (_accounts_server, server) = run_servers(database_adapter.clone(), GameServerOptions::default()).await;
+
+        let client = connect_authenticated(&server, database_adapter.clone(), "Account1").await;
+        let other_client = connect_authenticated(&server, database_adapter.clone(), "Account2").await;
+        let mut events_rx = client.subscribe_events();
+        let mut world = ReplicatedWorld::new();
+
+        let entity_id = 0;
+        client.attach_to_character(1).await.unwrap();
+        replicate_until(&client, &mut events_rx, &mut world, |world| world.get_entity(&entity_id).is_some()).await;
+
+        let other_entity_id = 1;
+        other_client.attach_to_character(2).await.unwrap();
+        other_client.step(Direction::Left).await.unwrap();
+        replicate_until(&client, &mut events_rx, &mut world, |world| world.get_entity(&other_entity_id).is_some()).await;
+        assert_eq!(world.get_entity(&other_entity_id).unwrap().position, Some((DEFAULT_VIEW_RANGE, 1.0)));
+        assert_eq!(recv_event(&mut events_rx).await, GameServerEvent::EntityEnteredView { entity_id: other_entity_id });
+
+        other_client.step(Direction::Right).await.unwrap();
+        assert_eq!(
+            recv_game_event(&mut events_rx).await,
+            GameServerEvent::EntityMoving { entity_id: other_entity_id, target_x: DEFAULT_VIEW_RANGE + 1.0, target_y: 1.0 }
+        );
+        replicate_until(&client, &mut events_rx, &mut world, |world| world.get_entity(&other_entity_id).is_none()).await;
+        assert_eq!(recv_event(&mut events_rx).await, GameServerEvent::EntityLeftView { entity_id: other_entity_id });
+        assert_eq!(world.get_entities_count(), 1);
+
+        client.disconnect_await_finished().await;
+        other_client.disconnect_await_finished().await;
+        server.await_all_disconnect().await.unwrap();
+        server.shutdown_gracefully().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_disconnecting_client_despawns_character() {
+        tests_trace_setup();
+
+        let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let (_accounts_server, server) = run_servers(database_adapter.clone(), GameServerOptions::default()).await;
+
+        let client = connect_authenticated(&server, database_adapter.clone(), "Account1").await;
+        let other_client = connect_authenticated(&server, database_adapter.clone(), "Account2").await;
+        let mut events_rx = other_client.subscribe_events();
+        let mut world = ReplicatedWorld::new();
+
+        other_client.attach_to_character(2).await.unwrap();
+        client.attach_to_character(1).await.unwrap();
+        let entity_id = 1;
+        replicate_until(&other_client, &mut events_rx, &mut world, |world| world.get_entity(&entity_id).is_some()).await;
+        client.step(Direction::Right).await.unwrap();
+        replicate_until(&other_client, &mut events_rx, &mut world, |world| {
+            world.get_entity(&entity_id).unwrap().position != Some((0.0, 1.0))
+        }).await;
+
+        client.disconnect_await_finished().await;
+        assert_eq!(recv_game_event(&mut events_rx).await, GameServerEvent::EntityDespawned { entity_id });
+        replicate_until(&other_client, &mut events_rx, &mut world, |world| world.get_entity(&entity_id).is_none()).await;
+        assert_eq!(other_client.get_entities_count().await.unwrap(), 1);
+
+        // Character was saved when despawned, quick reconnect spawns it again
+        let client = connect_authenticated(&server, database_adapter.clone(), "Account1").await;
+        client.attach_to_character(1).await.unwrap();
+        assert_ne!(client.get_position().await.unwrap(), (0.0, 1.0));
+
+        client.disconnect_await_finished().await;
+        other_client.disconnect_await_finished().await;
+        server.await_all_disconnect().await.unwrap();
+        server.shutdown_gracefully().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_clients_attaching_to_the_same_character() {
+        tests_trace_setup();
+
+        let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let (_accounts_server, server) = run_servers(database_adapter.clone(), GameServerOptions::default()).await;
+
+        let client = connect_authenticated(&server, database_adapter.clone(), "Account1").await;
+        let other_client = connect_authenticated(&server, database_adapter.clone(), "Account1").await;
+
+        let (result, other_result) = tokio::join!(client.attach_to_character(1), other_client.attach_to_character(1));
+        let (attached_client, rejected_client, error) = match (result, other_result) {
+            (Ok(()), Err(e)) => (client, other_client, e),
+            (Err(e), Ok(())) => (other_client, client, e),
+            results => panic!("Expected exactly one client attached, got {results:?}"),
+        };
+        assert!(matches!(&error, GameClientError::Other(message) if message.contains("already in world")), "{error:?}");
+        assert_eq!(attached_client.get_entities_count().await.unwrap(), 1);
+        assert!(rejected_client.get_position().await.is_err());
+
+        // Released when its session ends, progress saved by it is what the other one gets
+        attached_client.step(Direction::Right).await.unwrap();
+        await_position(&attached_client, (1.0, 1.0)).await;
+        attached_client.disconnect_await_finished().await;
+        let result = tokio::time::timeout(Duration::from_secs(3), async {
+            while rejected_client.attach_to_character(1).await.is_err() {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        }).await;
+        assert!(result.is_ok(), "Character not released");
+        assert_eq!(rejected_client.get_position().await.unwrap(), (1.0, 1.0));
+
+        rejected_client.disconnect_await_finished().await;
+        server.await_all_disconnect().await.unwrap();
+        server.shutdown_gracefully().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_saving_attached_characters() {
+        tests_trace_setup();
+
+        let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let (_accounts_server, server) = run_servers(database_adapter.clone(), GameServerOptions::default()).await;
+
+        let client = connect_authenticated(&server, database_adapter.clone(), "Account1").await;
+        client.attach_to_character(1).await.unwrap();
+        client.step(Direction::Right).await.unwrap();
+        await_position(&client, (1.0, 1.0)).await;
+
+        // Client is still connected, so only shutdown can save the step
+        server.shutdown_gracefully().await.unwrap();
+        let character_data = database_adapter.get_character_by_id(1).await.unwrap();
+        assert_eq!((character_data.position_x, character_data.position_y), (1.0, 1.0));
+
+        client.disconnect_await_finished().await;
+    }
+
+    #[tokio::test]
+    async fn test_clients_stepping_into_same_tile() {
+        tests_trace_setup();
+
+        let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let (_accounts_server, server) = run_servers(database_adapter.clone(), GameServerOptions::default()).await;
+
+        let client = connect_authenticated(&server, database_adapter.clone(), "Account1").await;
+        let other_client = connect_authenticated(&server, database_adapter.clone(), "Account2").await;
+
+        // Janusz at (0, 0) and Raspberry at (-2, 0) step into (-1, 0) at once, only one gets there
+        client.attach_to_character(0).await.unwrap();
+        other_client.attach_to_character(2).await.unwrap();
+        let (result, other_result) = tokio::join!(client.step(Direction::Left), other_client.step(Direction::Right));
+        let error = match (result, other_result) {
+            (Ok(()), Err(GameClientError::Other(message))) | (Err(GameClientError::Other(message)), Ok(())) => message,
+            results => panic!("Expected exactly one step to fail: {results:?}"),
+        };
+        assert!(error.contains("occupied"), "Unexpected error: {error}");
+
+        client.disconnect_await_finished().await;
+        other_client.disconnect_await_finished().await;
+        server.await_all_disconnect().await.unwrap();
+        server.shutdown_gracefully().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_client_using_json_codec() {
+        tests_trace_setup();
+
+        let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let (_accounts_server, server) = run_servers(database_adapter.clone(), GameServerOptions::default()).await;
+
+        let client = GameClient::connect_with_codec(*server.get_address(), Codec::Json).await.unwrap();
+        client.authenticate(login(database_adapter.clone(), "Account1").await).await.unwrap();
+        client.attach_to_character(1).await.unwrap();
+        assert_eq!(client.get_position().await.unwrap(), (0.0, 1.0));
+
+        let response = client.make_request(GameServerRequest::Hello { protocol_version: PROTOCOL_VERSION, codec: Codec::Json }).await.unwrap();
+        assert!(matches!(response, GameServerResponse::Hello { result: ResponseResult::Error { .. }, .. }));
+
+        client.disconnect_await_finished().await;
+        server.await_all_disconnect().await.unwrap();
+        server.shutdown_gracefully().await.unwrap();
+    }
+
+    /// Sends raw request encoded with handshake codec, returns response if any
+    async fn exchange_raw_hello(stream: &mut TcpStream, request: GameServerRequest) -> Option<GameServerResponse> {
+        let message_bytes = HANDSHAKE_CODEC.encode(&GameServerRequestMessage { request_id: 0, request }).unwrap();
+        write_frame(stream, &message_bytes, DEFAULT_MAX_FRAME_SIZE).await.unwrap();
+        let message_bytes = read_frame(stream, DEFAULT_MAX_FRAME_SIZE).await.ok()?;
+        match HANDSHAKE_CODEC.decode(&message_bytes).unwrap() {
+            GameServerMessage::Response { request_id: 0, response } => Some(response),
+            message => panic!("Got unexpected message: {message:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_client_with_other_protocol_version_should_be_rejected() {
+        tests_trace_setup();
+
+        let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let (_accounts_server, server) = run_servers(database_adapter, GameServerOptions::default()).await;
+
+        let mut stream = TcpStream::connect(*server.get_address()).await.unwrap();
+        let hello = GameServerRequest::Hello { protocol_version: PROTOCOL_VERSION + 1, codec: Codec::Bincode };
+        match exchange_raw_hello(&mut stream, hello).await.unwrap() {
+            GameServerResponse::Hello { result: ResponseResult::Error { message }, protocol_version } => {
+                assert_eq!(protocol_version, PROTOCOL_VERSION);
+                assert!(message.contains("Unsupported protocol version"), "Unexpected error: {message}");
+            },
+            response => panic!("Got unexpected response: {response:?}"),
+        }
+        // Server closes connection right after
+        assert!(read_frame(&mut stream, DEFAULT_MAX_FRAME_SIZE).await.is_err());
+
+        // Client skipping hello is rejected as well
+        let mut stream = TcpStream::connect(*server.get_address()).await.unwrap();
+        let response = exchange_raw_hello(&mut stream, GameServerRequest::Status).await.unwrap();
+        assert!(matches!(response, GameServerResponse::Hello { result: ResponseResult::Error { .. }, .. }));
+        assert!(read_frame(&mut stream, DEFAULT_MAX_FRAME_SIZE).await.is_err());
+
+        server.await_all_disconnect().await.unwrap();
+        server.shutdown_gracefully().await.unwrap();
+    }
+
+    /// Connects with raw stream speaking bincode after hello
+    async fn connect_raw(server: &GameServer) -> TcpStream {
+        let mut stream = TcpStream::connect(*server.get_address()).await.unwrap();
+        let hello = GameServerRequest::Hello { protocol_version: PROTOCOL_VERSION, codec: Codec::Bincode };
+        let response = exchange_raw_hello(&mut stream, hello).await.unwrap();
+        assert!(matches!(response, GameServerResponse::Hello { result: ResponseResult::Success, .. }));
+        stream
+    }
+
+    async fn exchange_raw_frame(stream: &mut TcpStream, payload: &[u8]) -> Option<GameServerMessage> {
+        write_frame(stream, payload, DEFAULT_MAX_FRAME_SIZE).await.unwrap();
+        let message_bytes = tokio::time::timeout(Duration::from_secs(5), read_frame(stream, DEFAULT_MAX_FRAME_SIZE))
+            .await
+            .expect("Server did not respond")
+            .ok()?;
+        Some(Codec::Bincode.decode(&message_bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_malformed_requests_should_get_error_response() {
+        tests_trace_setup();
+
+        let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let options = GameServerOptions {
+            session_limits: SessionLimits { max_protocol_violations: 3, ..Default::default() },
+            ..Default::default()
+        };
+        let (_accounts_server, server) = run_servers(database_adapter, options).await;
+        let mut stream = connect_raw(&server).await;
+
+        // Request id followed by unknown request variant
+        let mut payload = 7u64.to_le_bytes().to_vec();
+        payload.extend(u32::MAX.to_le_bytes());
+        match exchange_raw_frame(&mut stream, &payload).await.unwrap() {
+            GameServerMessage::Response { request_id: 7, response: GameServerResponse::MalformedRequest { .. } } => {},
+            message => panic!("Got unexpected message: {message:?}"),
+        }
+
+        // Too short to hold even request id
+        let message = exchange_raw_frame(&mut stream, &[1, 2]).await.unwrap();
+        assert!(matches!(message, GameServerMessage::ProtocolViolation { .. }));
+
+        // Session is still usable
+        let request = Codec::Bincode.encode(&GameServerRequestMessage { request_id: 8, request: GameServerRequest::Status }).unwrap();
+        let message = exchange_raw_frame(&mut stream, &request).await.unwrap();
+        assert!(matches!(message, GameServerMessage::Response { request_id: 8, response: GameServerResponse::Status { .. } }));
+
+        // Last tolerated violation is still answered, then session gets closed
+        let message = exchange_raw_frame(&mut stream, &[]).await.unwrap();
+        assert!(matches!(message, GameServerMessage::ProtocolViolation { .. }));
+        assert!(read_frame(&mut stream, DEFAULT_MAX_FRAME_SIZE).await.is_err());
+
+        server.await_all_disconnect().await.unwrap();
+        server.shutdown_gracefully().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_too_large_frame_should_close_session() {
+        tests_trace_setup();
+
+        let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let options = GameServerOptions {
+            session_limits: SessionLimits { max_frame_size: 128, ..Default::default() },
+            ..Default::default()
+        };
+        let (_accounts_server, server) = run_servers(database_adapter, options).await;
+
+        // Claims 4 GiB, server must not wait for it nor allocate it
+        let mut stream = connect_raw(&server).await;
+        stream.write_all(&u32::MAX.to_le_bytes()).await.unwrap();
+        assert!(read_frame(&mut stream, DEFAULT_MAX_FRAME_SIZE).await.is_err());
+
+        // Limit applies to hello as well
+        let mut stream = TcpStream::connect(*server.get_address()).await.unwrap();
+        write_frame(&mut stream, &[b' '; 129], DEFAULT_MAX_FRAME_SIZE).await.unwrap();
+        assert!(read_frame(&mut stream, DEFAULT_MAX_FRAME_SIZE).await.is_err());
+
+        server.await_all_disconnect().await.unwrap();
+        server.shutdown_gracefully().await.unwrap();
+    }
+
+    /// Xorshift, deterministic so failing input can be reproduced
+    struct TestRng(u64);
+
+    impl TestRng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, bound: usize) -> usize {
+            (self.next() % bound as u64) as usize
+        }
+
+        fn bytes(&mut self, max_length: usize) -> Vec<u8> {
+            let length = self.below(max_length + 1);
+            (0..length).map(|_| self.next() as u8).collect()
+        }
+    }
+
+    /// Throws random input at the server, closes write half and drains whatever comes back
+    async fn send_random_input(server_address: std::net::SocketAddr, seed: u64) {
+        let mut rng = TestRng(seed);
+        let mut stream = TcpStream::connect(server_address).await.unwrap();
+
+        let mut input = Vec::new();
+        if rng.below(2) == 0 {
+            let codec = if rng.below(2) == 0 { Codec::Json } else { Codec::Bincode };
+            let hello = GameServerRequest::Hello { protocol_version: PROTOCOL_VERSION, codec };
+            let message_bytes = HANDSHAKE_CODEC.encode(&GameServerRequestMessage { request_id: 0, request: hello }).unwrap();
+            write_frame(&mut input, &message_bytes, DEFAULT_MAX_FRAME_SIZE).await.unwrap();
+        }
+        for _ in 0..rng.below(16) {
+            match rng.below(4) {
+                // Well framed garbage
+                0 | 1 => write_frame(&mut input, &rng.bytes(64), DEFAULT_MAX_FRAME_SIZE).await.unwrap(),
+                // Bare garbage, random length prefix included
+                2 => input.extend(rng.bytes(64)),
+                // Frame cut short
+                _ => {
+                    input.extend(64u32.to_le_bytes());
+                    input.extend(rng.bytes(32));
+                },
+            }
+        }
+
+        // Server may close connection while it is still written to
+        let _ = stream.write_all(&input).await;
+        let _ = stream.shutdown().await;
+        let mut output = Vec::new();
+        let drained = tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut output)).await;
+        assert!(drained.is_ok(), "Session stuck on input with seed {seed}");
+    }
+
+    #[tokio::test]
+    async fn test_server_surviving_random_input() {
+        tests_trace_setup();
+
+        let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let (_accounts_server, server) = run_servers(database_adapter, GameServerOptions::default()).await;
+        let server_address = *server.get_address();
+
+        let tasks: Vec<_> = (1..=64u64)
+            .map(|seed| tokio::spawn(send_random_input(server_address, seed.wrapping_mul(0x9e37_79b9_7f4a_7c15))))
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        // Well behaved client is served as usual
+        let client = GameClient::connect(server_address).await.unwrap();
+        assert!(matches!(client.make_request(GameServerRequest::Status).await.unwrap(), GameServerResponse::Status { .. }));
+        client.disconnect_await_finished().await;
+
+        server.await_all_disconnect().await.unwrap();
+        server.shutdown_gracefully().await.unwrap();
+    }
+
+    #[test]
+    fn test_client_measuring_rtt() {
+        tests_trace_setup();
+
+        run_single_client_test(|client| async move {
+            let rtt = client.ping().await.unwrap();
+            assert!(rtt < Duration::from_secs(1), "Unexpected RTT on localhost: {rtt:?}");
+            assert!(client.get_rtt().is_some());
+        });
+    }
+
+    /// Session limits letting idle session get closed quickly
+    fn short_heartbeat_options() -> GameServerOptions {
+        GameServerOptions {
+            session_limits: SessionLimits {
+                heartbeat_interval: Duration::from_millis(50),
+                idle_timeout: Duration::from_millis(300),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    /// Sends raw request encoded with bincode, skipping pings till its response comes
+    async fn raw_request(stream: &mut TcpStream, request_id: u64, request: GameServerRequest) -> GameServerResponse {
+        let message_bytes = Codec::Bincode.encode(&GameServerRequestMessage { request_id, request }).unwrap();
+        write_frame(stream, &message_bytes, DEFAULT_MAX_FRAME_SIZE).await.unwrap();
+        loop {
+            let message_bytes = read_frame(stream, DEFAULT_MAX_FRAME_SIZE).await.unwrap();
+            match Codec::Bincode.decode(&message_bytes).unwrap() {
+                GameServerMessage::Response { request_id: response_id, response } if response_id == request_id => return response,
+                GameServerMessage::Ping { .. } => {},
+                message => panic!("Got unexpected message: {message:?}"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_server_measuring_rtt_of_sessions() {
+        tests_trace_setup();
+
+        let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let (_accounts_server, server) = run_servers(database_adapter, short_heartbeat_options()).await;
+
+        let client = GameClient::connect(*server.get_address()).await.unwrap();
+        let rtt = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(rtt) = server.get_sessions_rtt().await.unwrap().values().next().copied().flatten() {
+                    return rtt;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        }).await.expect("Client did not answer any ping");
+        assert!(rtt < Duration::from_secs(1), "Unexpected RTT on localhost: {rtt:?}");
+
+        // Answering pings keeps client connected while idle
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        client.get_status().await.unwrap();
+
+        client.disconnect_await_finished().await;
+        server.await_all_disconnect().await.unwrap();
+        server.shutdown_gracefully().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_silent_sessions_should_be_closed() {
+        tests_trace_setup();
+
+        let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        // Logging in blocks test runtime for longer than idle timeout
+        let other_token = login(database_adapter.clone(), "Account2").await;
+        let token = login(database_adapter.clone(), "Account1").await;
+        let (_accounts_server, server) = run_servers(database_adapter.clone(), short_heartbeat_options()).await;
+        let other_client = GameClient::connect(*server.get_address()).await.unwrap();
+        other_client.authenticate(other_token).await.unwrap();
+        other_client.attach_to_character(2).await.unwrap();
+
+        // Client never answering pings, with character spawned
+        let mut stream = connect_raw(&server).await;
+        let response = raw_request(&mut stream, 1, GameServerRequest::Authenticate { token }).await;
+        assert!(matches!(response, GameServerResponse::Authenticate { result: ResponseResult::Success }));
+        let response = raw_request(&mut stream, 2, GameServerRequest::AttachToCharacter { character_id: 1 }).await;
+        assert!(matches!(response, GameServerResponse::AttachToCharacter { result: ResponseResult::Success }));
+        assert_eq!(other_client.get_entities_count().await.unwrap(), 2);
+
+        // Pings keep coming till the session gets closed
+        let closed = tokio::time::timeout(Duration::from_secs(5), async {
+            while read_frame(&mut stream, DEFAULT_MAX_FRAME_SIZE).await.is_ok() {}
+        }).await;
+        assert!(closed.is_ok(), "Silent session not closed");
+        assert_eq!(other_client.get_entities_count().await.unwrap(), 1);
+
+        // Client not even saying hello
+        let mut stream = TcpStream::connect(*server.get_address()).await.unwrap();
+        let closed = tokio::time::timeout(Duration::from_secs(5), read_frame(&mut stream, DEFAULT_MAX_FRAME_SIZE)).await;
+        assert!(matches!(closed, Ok(Err(_))), "Session without hello not closed");
+
+        other_client.disconnect_await_finished().await;
+        server.await_all_disconnect().await.unwrap();
+        server.shutdown_gracefully().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_websocket_and_tcp_clients_playing_together() {
+        tests_trace_setup();
+
+        let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let token = login(database_adapter.clone(), "Account1").await;
+        let websocket_token = login(database_adapter.clone(), "Account2").await;
+        let (_accounts_server, server) = run_servers(database_adapter, GameServerOptions::default()).await;
+
+        let client = GameClient::connect(*server.get_address()).await.unwrap();
+        client.authenticate(token).await.unwrap();
+        let websocket_client = GameClient::connect_websocket(*server.get_websocket_address()).await.unwrap();
+        websocket_client.authenticate(websocket_token).await.unwrap();
+        assert_eq!(server.get_connections_count().await.unwrap(), 2);
+
+        let mut events_rx = client.subscribe_events();
+        let mut websocket_events_rx = websocket_client.subscribe_events();
+        let mut websocket_world = ReplicatedWorld::new();
+        websocket_client.attach_to_character(2).await.unwrap();
+        client.attach_to_character(1).await.unwrap();
+        assert_eq!(websocket_client.get_entities_count().await.unwrap(), 2);
+
+        // Moves of TCP client get replicated to WebSocket one
+        let entity_id = 1;
+        client.step(Direction::Right).await.unwrap();
+        replicate_until(&websocket_client, &mut websocket_events_rx, &mut websocket_world, |world| {
+            world.get_entity(&entity_id).is_some_and(|entity| entity.position == Some((1.0, 1.0)))
+        }).await;
+
+        websocket_client.chat("Hello".to_string()).await.unwrap();
+        loop {
+            match recv_game_event(&mut events_rx).await {
+                GameServerEvent::Chat { from, message } => {
+                    assert_eq!((from.as_str(), message.as_str()), ("Account2", "Hello"));
+                    break;
+                },
+                // Own moves
+                _ => continue,
+            }
+        }
+        assert!(websocket_client.ping().await.is_ok());
+
+        websocket_client.disconnect_await_finished().await;
+        assert_eq!(client.get_entities_count().await.unwrap(), 1);
+        client.disconnect_await_finished().await;
+        server.await_all_disconnect().await.unwrap();
+        server.shutdown_gracefully().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_websocket_raw_messages() {
+        tests_trace_setup();
+
+        let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let options = GameServerOptions {
+            session_limits: SessionLimits { max_frame_size: 128, ..Default::default() },
+            ..Default::default()
+        };
+        let (_accounts_server, server) = run_servers(database_adapter, options).await;
+        let url = format!("ws://{}/", server.get_websocket_address());
+        let (mut websocket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+        // Hello typed as text, the way it can be done from browser console
+        let hello = GameServerRequest::Hello { protocol_version: PROTOCOL_VERSION, codec: Codec::Json };
+        let hello = String::from_utf8(HANDSHAKE_CODEC.encode(&GameServerRequestMessage { request_id: 0, request: hello }).unwrap()).unwrap();
+        websocket.send(Message::text(hello)).await.unwrap();
+        match websocket.next().await.unwrap().unwrap() {
+            Message::Binary(message_bytes) => {
+                let message: GameServerMessage = HANDSHAKE_CODEC.decode(&message_bytes).unwrap();
+                assert!(matches!(message, GameServerMessage::Response {
+                    response: GameServerResponse::Hello { result: ResponseResult::Success, .. },
+                    ..
+                }));
+            },
+            message => panic!("Got unexpected message: {message:?}"),
+        }
+
+        // Too large message closes the session
+        websocket.send(Message::binary(vec![b' '; 129])).await.unwrap();
+        let closed = tokio::time::timeout(Duration::from_secs(5), async {
+            while let Some(Ok(message)) = websocket.next().await {
+                if message.is_close() {
+                    break;
+                }
+            }
+        }).await;
+        assert!(closed.is_ok(), "Session not closed");
+
+        server.await_all_disconnect().await.unwrap();
+        server.shutdown_gracefully().await.unwrap();
+    }
+
+    /// Applies replication events until `condition` holds for replicated world
+    async fn replicate_until(
+        client: &GameClient,
+        events_rx: &mut broadcast::Receiver<GameServerEvent>,
+        world: &mut ReplicatedWorld,
+        condition: impl Fn(&ReplicatedWorld) -> bool,
+    ) {
+        while !condition(world) {
+            match recv_event(events_rx).await {
+                GameServerEvent::WorldSnapshot { snapshot } => world.apply_snapshot(snapshot),
+                GameServerEvent::WorldDelta { delta } => {
+                    if let Err(ReplicationError::Gap { .. }) = world.apply_delta(&delta) {
+                        client.request_resync_world().await.unwrap();
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+
+    #[test]
+    fn test_client_replicating_world() {
+        tests_trace_setup();
+
+        run_authenticated_client_test("Account1", |client| async move {
+            let mut events_rx = client.subscribe_events();
+            let mut world = ReplicatedWorld::new();
+            assert!(client.request_resync_world().await.is_err());
+
+            client.attach_to_character(1).await.unwrap();
+            replicate_until(&client, &mut events_rx, &mut world, |world| world.get_entity(&0).is_some()).await;
+            let entity = world.get_entity(&0).unwrap();
+            assert_eq!(entity.name.as_deref(), Some("Tuna"));
+            assert_eq!(entity.position, Some((0.0, 1.0)));
+
+            client.move_to(1.0, 1.0).await.unwrap();
+            replicate_until(&client, &mut events_rx, &mut world, |world| {
+                world.get_entity(&0).unwrap().position == Some((1.0, 1.0))
+            }).await;
+            let tick = world.get_tick().unwrap();
+
+            // Resync starts from snapshot matching already replicated state
+            client.request_resync_world().await.unwrap();
+            let mut resynced_world = ReplicatedWorld::new();
+            replicate_until(&client, &mut events_rx, &mut resynced_world, |world| world.get_tick().is_some()).await;
+            assert!(resynced_world.get_tick().unwrap() >= tick);
+            assert_eq!(resynced_world.get_entity(&0), world.get_entity(&0));
+        });
+    }
+
+    #[tokio::test]
+    async fn test_session_closed_after_logout() {
+        tests_trace_setup();
+
+        let database_adapter = Arc::new(DatabaseTestAdapter::with_test_data().await);
+        let (accounts_server, server) = run_servers(database_adapter.clone(), GameServerOptions::default()).await;
+        let token_verifier = AccessTokenVerifier::new(&accounts_server.get_address().to_string(), database_adapter.clone()).await.unwrap();
+
+        let token = login(database_adapter.clone(), "Account1").await;
+        let claims = token_verifier.verify(&token).await.unwrap();
+        let client = GameClient::connect(*server.get_address()).await.unwrap();
+        client.authenticate(token).await.unwrap();
+        client.attach_to_character(1).await.unwrap();
+        assert_eq!(client.get_position().await.unwrap(), (0.0, 1.0));
+
+        services::logout_account(claims, database_adapter.clone()).await.unwrap();
+        assert!(matches!(client.get_position().await, Err(GameClientError::NotAuthenticated)));
+
+        // Session gets closed by server, character gets despawned with it
+        server.await_all_disconnect().await.unwrap();
+        assert_eq!(server.get_connections_count().await.unwrap(), 0);
+
+        client.disconnect_await_finished().await;
+        server.shutdown_gracefully().await.unwrap();
+    }
 }
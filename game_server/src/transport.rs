@@ -0,0 +1,117 @@
+use async_trait::async_trait;
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::error::CapacityError;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::tungstenite::Message;
+use crate::framing::{self, FrameError, FrameResult};
+
+/// Receiving half of connection, yields whole frames whatever carries them
+#[async_trait]
+pub trait FrameReader: Send {
+    async fn read_frame(&mut self, max_frame_size: usize) -> FrameResult<Vec<u8>>;
+}
+
+/// Sending half of connection
+#[async_trait]
+pub trait FrameWriter: Send {
+    async fn write_frame(&mut self, payload: &[u8], max_frame_size: usize) -> FrameResult<()>;
+
+    /// Tells peer nothing more comes. WebSocket stays open without it, as long as its reader lives.
+    async fn close(&mut self) -> FrameResult<()>;
+}
+
+pub type BoxedFrameReader = Box<dyn FrameReader>;
+pub type BoxedFrameWriter = Box<dyn FrameWriter>;
+
+/// Length prefixed frames, see `framing`
+pub fn split_tcp(stream: TcpStream) -> (BoxedFrameReader, BoxedFrameWriter) {
+    let (reader, writer) = stream.into_split();
+    (Box::new(reader), Box::new(writer))
+}
+
+/// Frame per binary message, text messages are taken as frames too, so JSON codec can be typed by hand
+pub fn split_websocket<S>(stream: WebSocketStream<S>) -> (BoxedFrameReader, BoxedFrameWriter)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static
+{
+    let (writer, reader) = stream.split();
+    (Box::new(reader), Box::new(writer))
+}
+
+/// Oversized message is rejected by the protocol itself, before it gets buffered
+pub fn websocket_config(max_frame_size: usize) -> WebSocketConfig {
+    WebSocketConfig::default()
+        .max_message_size(Some(max_frame_size))
+        .max_frame_size(Some(max_frame_size))
+}
+
+#[async_trait]
+impl FrameReader for OwnedReadHalf {
+    async fn read_frame(&mut self, max_frame_size: usize) -> FrameResult<Vec<u8>> {
+        framing::read_frame(self, max_frame_size).await
+    }
+}
+
+#[async_trait]
+impl FrameWriter for OwnedWriteHalf {
+    async fn write_frame(&mut self, payload: &[u8], max_frame_size: usize) -> FrameResult<()> {
+        framing::write_frame(self, payload, max_frame_size).await
+    }
+
+    async fn close(&mut self) -> FrameResult<()> {
+        Ok(self.shutdown().await?)
+    }
+}
+
+#[async_trait]
+impl<S> FrameReader for SplitStream<WebSocketStream<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send
+{
+    async fn read_frame(&mut self, max_frame_size: usize) -> FrameResult<Vec<u8>> {
+        let payload = loop {
+            match self.next().await {
+                Some(Ok(Message::Binary(payload))) => break payload.to_vec(),
+                Some(Ok(Message::Text(text))) => break text.as_bytes().to_vec(),
+                // Pings get answered by the protocol itself
+                Some(Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_))) => continue,
+                Some(Ok(Message::Close(_))) | None => {
+                    return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+                },
+                Some(Err(tokio_tungstenite::tungstenite::Error::Capacity(CapacityError::MessageTooLong { size, max_size }))) => {
+                    return Err(FrameError::FrameTooLarge { length: size, max_length: max_size });
+                },
+                Some(Err(e)) => return Err(e.into()),
+            }
+        };
+
+        // Peer's config may differ from the limit
+        if payload.len() > max_frame_size {
+            return Err(FrameError::FrameTooLarge { length: payload.len(), max_length: max_frame_size });
+        }
+        Ok(payload)
+    }
+}
+
+#[async_trait]
+impl<S> FrameWriter for SplitSink<WebSocketStream<S>, Message>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send
+{
+    async fn write_frame(&mut self, payload: &[u8], max_frame_size: usize) -> FrameResult<()> {
+        if payload.len() > max_frame_size {
+            return Err(FrameError::FrameTooLarge { length: payload.len(), max_length: max_frame_size });
+        }
+        self.send(Message::binary(payload.to_vec())).await?;
+        Ok(())
+    }
+
+    async fn close(&mut self) -> FrameResult<()> {
+        Ok(SinkExt::close(self).await?)
+    }
+}